@@ -43,3 +43,40 @@ pub fn defid_as_local(did: DefId) -> Option<LocalDefId> {
     #[cfg(feature = "rustc")]
     return did.as_local();
 }
+
+/// The number of the crate `did` belongs to. Crate numbers are only meaningful
+/// within one compiler session.
+pub fn crate_num_of(did: DefId) -> u32 {
+    #[cfg(not(feature = "rustc"))]
+    return did.krate.private;
+    #[cfg(feature = "rustc")]
+    return did.krate.as_u32();
+}
+
+/// `did` with its crate number replaced by `krate`.
+pub fn with_crate_num(did: DefId, krate: u32) -> DefId {
+    #[cfg(not(feature = "rustc"))]
+    return DefId {
+        krate: crate::rustc_proxies::CrateNum { private: krate },
+        ..did
+    };
+    #[cfg(feature = "rustc")]
+    return DefId {
+        krate: def_id::CrateNum::from_u32(krate),
+        ..did
+    };
+}
+
+/// The [`DefId`] of the item with this index in crate `krate`.
+pub fn def_id_from_parts(krate: u32, index: u32) -> DefId {
+    #[cfg(not(feature = "rustc"))]
+    return DefId {
+        krate: crate::rustc_proxies::CrateNum { private: krate },
+        index: DefIndex { private: index },
+    };
+    #[cfg(feature = "rustc")]
+    return DefId {
+        krate: def_id::CrateNum::from_u32(krate),
+        index: DefIndex::from_u32(index),
+    };
+}
@@ -3,13 +3,13 @@ use std::rc::Rc;
 use either::Either;
 use itertools::Itertools;
 use rustc_abi::{FieldIdx, VariantIdx};
-use rustc_hir::def_id::DefId;
+use rustc_hir::{def::DefKind, def_id::DefId, AsyncGeneratorKind, GeneratorKind};
 use rustc_middle::{
     mir::{
-        AggregateKind, BasicBlock, Body, Location, Operand, Place, Rvalue, Statement,
-        StatementKind, Terminator, TerminatorKind,
+        AggregateKind, BasicBlock, Body, Location, Operand, Place, PlaceElem, Rvalue, Statement,
+        StatementKind, Terminator, TerminatorKind, START_BLOCK,
     },
-    ty::{GenericArgsRef, Instance, TyCtxt},
+    ty::{self, EarlyBinder, GenericArgsRef, Instance, InstanceDef, ParamEnv, TyCtxt},
 };
 
 use crate::utils::is_async;
@@ -21,12 +21,18 @@ use super::{
 
 /// Describe in which way a function is `async`.
 ///
-/// Critically distinguishes between a normal `async fn` and an
-/// `#[async_trait]`.
-#[derive(Debug, Clone, Copy)]
+/// Critically distinguishes between a normal `async fn` and functions that
+/// create the future of an async block and return it, such as the ones
+/// generated by `#[async_trait]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AsyncType {
+    /// An `async fn`, including an `async fn` in a trait.
     Fn,
+    /// Returns an async block as `Pin<Box<dyn Future>>`, the shape
+    /// `#[async_trait]` produces.
     Trait,
+    /// Returns an async block as `impl Future`.
+    Wrapper,
 }
 
 /// Context for a call to [`Future::poll`](std::future::Future::poll), when
@@ -40,6 +46,10 @@ pub struct AsyncFnPollEnv<'tcx> {
     /// A place which carries the runtime value representing the generator in
     /// the caller.
     pub generator_data: Place<'tcx>,
+    /// If the polled future is a `Pin<Box<dyn Future>>`, `generator_data` is
+    /// the boxed trait object and this is the type of the generator behind
+    /// it.
+    pub boxed_generator: Option<ty::Ty<'tcx>>,
 }
 
 /// Stores ids that are needed to construct projections around async functions.
@@ -61,14 +71,26 @@ impl AsyncInfo {
     }
 }
 
-pub fn try_as_async_trait_function<'tcx>(
+/// Try to interpret this function as a wrapper around an async block, e.g.
+/// the output of `#[async_trait]` or a function returning `impl Future`.
+///
+/// Returns the generator of the async block that is returned and where it is
+/// created. Functions that create more than one candidate async block are not
+/// considered wrappers.
+pub fn try_as_async_wrapper<'tcx>(
     tcx: TyCtxt,
     def_id: DefId,
     body: &Body<'tcx>,
-) -> Option<(DefId, GenericArgsRef<'tcx>, Location)> {
-    if !has_async_trait_signature(tcx, def_id) {
-        return None;
-    }
+) -> Option<(DefId, GenericArgsRef<'tcx>, Location, AsyncType)> {
+    let output = async_wrapper_output(tcx, def_id)?;
+    let (asyncness, returned) = if match_pin_box_dyn_ty(tcx.lang_items(), output) {
+        (AsyncType::Trait, None)
+    } else {
+        (
+            AsyncType::Wrapper,
+            Some(opaque_future_generator(tcx, output)?),
+        )
+    };
     let mut matching_statements =
         body.basic_blocks
             .iter_enumerated()
@@ -87,9 +109,35 @@ pub fn try_as_async_trait_function<'tcx>(
                     },
                 )
             })
-            .collect::<Vec<_>>();
-    assert_eq!(matching_statements.len(), 1);
-    matching_statements.pop()
+            .filter(|(generator, _, _)| {
+                tcx.generator_is_async(*generator) && returned.map_or(true, |r| r == *generator)
+            });
+    let (generator, args, location) = matching_statements.next()?;
+    matching_statements
+        .next()
+        .is_none()
+        .then_some((generator, args, location, asyncness))
+}
+
+/// Does the wrapper with body `body` do anything before it creates its async
+/// block at `creation`, other than moving or borrowing its arguments.
+pub(crate) fn has_async_prelude(body: &Body<'_>, creation: Location) -> bool {
+    creation.block != START_BLOCK
+        || body.basic_blocks[creation.block].statements[..creation.statement_index]
+            .iter()
+            .any(|statement| {
+                !matches!(
+                    &statement.kind,
+                    StatementKind::StorageLive(_)
+                        | StatementKind::StorageDead(_)
+                        | StatementKind::Nop
+                        | StatementKind::FakeRead(_)
+                        | StatementKind::Retag(..)
+                        | StatementKind::PlaceMention(_)
+                        | StatementKind::AscribeUserType(..)
+                        | StatementKind::Assign(box (_, Rvalue::Use(_) | Rvalue::Ref(..)))
+                )
+            })
 }
 
 pub fn match_async_trait_assign<'tcx>(
@@ -104,23 +152,64 @@ pub fn match_async_trait_assign<'tcx>(
     }
 }
 
-/// Does this function have a structure as created by the `#[async_trait]` macro
-pub fn is_async_trait_fn(tcx: TyCtxt, def_id: DefId, body: &Body<'_>) -> bool {
-    try_as_async_trait_function(tcx, def_id, body).is_some()
+/// Does this function return the future of an async block it creates, see
+/// [`try_as_async_wrapper`].
+pub fn is_async_wrapper_fn(tcx: TyCtxt, def_id: DefId, body: &Body<'_>) -> bool {
+    try_as_async_wrapper(tcx, def_id, body).is_some()
 }
 
-fn has_async_trait_signature(tcx: TyCtxt, def_id: DefId) -> bool {
-    if let Some(assoc_item) = tcx.opt_associated_item(def_id) {
-        let sig = tcx.fn_sig(def_id).skip_binder();
-        assoc_item.container == ty::AssocItemContainer::ImplContainer
-            && assoc_item.trait_item_def_id.is_some()
-            && match_pin_box_dyn_ty(tcx.lang_items(), sig.output().skip_binder())
-    } else {
-        false
+/// The return type of `def_id` if it could be the future of an async block.
+fn async_wrapper_output(tcx: TyCtxt, def_id: DefId) -> Option<ty::Ty> {
+    if !matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn) {
+        return None;
+    }
+    let output = tcx.fn_sig(def_id).skip_binder().output().skip_binder();
+    (match_pin_box_dyn_ty(tcx.lang_items(), output)
+        || opaque_future_generator(tcx, output).is_some())
+    .then_some(output)
+}
+
+/// If `t` is an `impl Future` whose hidden type is a generator, return the
+/// generator.
+fn opaque_future_generator(tcx: TyCtxt, t: ty::Ty) -> Option<DefId> {
+    let ty::TyKind::Alias(ty::AliasKind::Opaque, alias) = t.kind() else {
+        return None;
+    };
+    let future_trait = tcx.lang_items().future_trait()?;
+    let is_future = tcx
+        .explicit_item_bounds(alias.def_id)
+        .skip_binder()
+        .iter()
+        .any(|(clause, _)| {
+            clause
+                .as_trait_clause()
+                .map_or(false, |t| t.def_id() == future_trait)
+        });
+    if !is_future {
+        return None;
+    }
+    let ty::TyKind::Generator(generator, _, _) = tcx.type_of(alias.def_id).skip_binder().kind()
+    else {
+        return None;
+    };
+    Some(*generator)
+}
+
+/// The generator that is created by a call to `wrapper`, a function
+/// recognized by [`try_as_async_wrapper`] with body `body`.
+pub(crate) fn wrapped_generator<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    wrapper: Instance<'tcx>,
+    body: &Body<'tcx>,
+) -> Option<Instance<'tcx>> {
+    if !matches!(wrapper.def, InstanceDef::Item(_)) {
+        return None;
     }
+    let (generator, args, _, _) = try_as_async_wrapper(tcx, wrapper.def_id(), body)?;
+    let args = EarlyBinder::bind(args).instantiate(tcx, wrapper.args);
+    utils::try_resolve_function(tcx, generator, ParamEnv::reveal_all(), args)
 }
 
-use rustc_middle::ty;
 fn match_pin_box_dyn_ty(lang_items: &rustc_hir::LanguageItems, t: ty::Ty) -> bool {
     let ty::TyKind::Adt(pin_ty, args) = t.kind() else {
         return false;
@@ -138,9 +227,6 @@ fn match_pin_box_dyn_ty(lang_items: &rustc_hir::LanguageItems, t: ty::Ty) -> boo
     let ty::TyKind::Dynamic(pred, _, ty::DynKind::Dyn) = t_a.boxed_ty().kind() else {
         return false;
     };
-    if pred.len() != 2 {
-        return false;
-    }
     pred.iter().any(|p| {
         let ty::ExistentialPredicate::Trait(t) = p.skip_binder() else {
             return false;
@@ -168,9 +254,10 @@ fn get_async_generator<'tcx>(body: &Body<'tcx>) -> (DefId, GenericArgsRef<'tcx>,
     (*def_id, generic_args, location)
 }
 
-/// Try to interpret this function as an async function.
+/// Try to interpret this function as an async function or a wrapper around
+/// an async block (see [`try_as_async_wrapper`]).
 ///
-/// If this is an async function it returns the [`Instance`] of the generator,
+/// If this is the case it returns the [`Instance`] of the generator,
 /// the location where the generator is bound and the type of [`Asyncness`]
 /// which in this case is guaranteed to satisfy [`Asyncness::is_async`].
 pub fn determine_async<'tcx>(
@@ -178,19 +265,35 @@ pub fn determine_async<'tcx>(
     def_id: DefId,
     body: &Body<'tcx>,
 ) -> Option<(Instance<'tcx>, Location, AsyncType)> {
-    let ((generator_def_id, args, loc), asyncness) = if is_async(tcx, def_id) {
-        (get_async_generator(body), AsyncType::Fn)
+    let (generator_def_id, args, loc, asyncness) = if is_async(tcx, def_id) {
+        let (generator, args, loc) = get_async_generator(body);
+        (generator, args, loc, AsyncType::Fn)
     } else {
-        (
-            try_as_async_trait_function(tcx, def_id, body)?,
-            AsyncType::Trait,
-        )
+        try_as_async_wrapper(tcx, def_id, body)?
     };
     let param_env = tcx.param_env_reveal_all_normalized(def_id);
     let generator_fn = utils::try_resolve_function(tcx, generator_def_id, param_env, args)?;
     Some((generator_fn, loc, asyncness))
 }
 
+/// The reverse of [`determine_async`] for an `async fn`: if `generator` is the
+/// generator of an `async fn`, return the instance of that function.
+///
+/// Polling the future returned by an `async fn` calls its generator, this
+/// recovers which function was awaited.
+pub fn async_fn_of_generator<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    generator: Instance<'tcx>,
+) -> Option<Instance<'tcx>> {
+    let def_id = generator.def_id();
+    if tcx.generator_kind(def_id) != Some(GeneratorKind::Async(AsyncGeneratorKind::Fn)) {
+        return None;
+    }
+    let parent = tcx.opt_parent(def_id)?;
+    let args = tcx.mk_args(generator.args.as_generator().parent_args());
+    Some(Instance::new(parent, args))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AsyncDeterminationResult<T> {
     Resolved(T),
@@ -213,25 +316,81 @@ impl<'tcx, 'mir> LocalAnalysis<'tcx, 'mir> {
     /// Future>::poll`](std::future::Future::poll) where `T` is the type of an
     /// `async fn` or `async {}` created generator.
     ///
-    /// Resolves the original arguments that constituted the generator.
+    /// Resolves the original arguments that constituted the generator and the
+    /// generator that is actually polled. The latter differs from
+    /// `resolved_fn` if the future is a `Pin<Box<dyn Future>>` returned by an
+    /// async wrapper (see [`try_as_async_wrapper`]), such as an
+    /// `#[async_trait]` method.
     pub(crate) fn try_poll_call_kind<'a>(
         &'a self,
         def_id: DefId,
         resolved_fn: Instance<'tcx>,
         original_args: &'a [Operand<'tcx>],
-    ) -> AsyncDeterminationResult<CallKind<'tcx>> {
+    ) -> AsyncDeterminationResult<(Instance<'tcx>, CallKind<'tcx>)> {
         let lang_items = self.tcx().lang_items();
-        if lang_items.future_poll_fn() == Some(def_id)
-            && is_async_fn_or_block(self.tcx(), resolved_fn)
-        {
+        if lang_items.future_poll_fn() != Some(def_id) {
+            return AsyncDeterminationResult::NotAsync;
+        }
+        if is_async_fn_or_block(self.tcx(), resolved_fn) {
             match self.find_async_args(original_args) {
-                Ok(poll) => AsyncDeterminationResult::Resolved(CallKind::AsyncPoll(poll)),
+                Ok(poll) => {
+                    AsyncDeterminationResult::Resolved((resolved_fn, CallKind::AsyncPoll(poll)))
+                }
                 Err(str) => AsyncDeterminationResult::Unresolvable(str),
             }
+        } else if self.polls_boxed_future(original_args) {
+            // If we cannot find where the future was created this is just a
+            // poll of some trait object.
+            let Ok(mut poll) = self.find_async_args(original_args) else {
+                return AsyncDeterminationResult::NotAsync;
+            };
+            let Some(generator) = poll.async_fn_parent.and_then(|wrapper| {
+                let body = self.memo.body_cache.try_get(wrapper.def_id())?;
+                wrapped_generator(self.tcx(), wrapper, body.body())
+            }) else {
+                return AsyncDeterminationResult::NotAsync;
+            };
+            // The generator's fields live behind the box, places of the
+            // generator are translated onto `*pinned.pointer` with the
+            // generator's concrete type.
+            let tcx = self.tcx();
+            let pointer = FieldIdx::from_u32(0);
+            let boxed = poll
+                .generator_data
+                .ty(&self.mono_body, tcx)
+                .field_ty(tcx, pointer);
+            poll.generator_data = tcx.mk_place_deref(
+                poll.generator_data
+                    .project_deeper(&[PlaceElem::Field(pointer, boxed)], tcx),
+            );
+            poll.boxed_generator = Some(
+                tcx.type_of(generator.def_id())
+                    .instantiate(tcx, generator.args),
+            );
+            AsyncDeterminationResult::Resolved((generator, CallKind::AsyncPoll(poll)))
         } else {
             AsyncDeterminationResult::NotAsync
         }
     }
+
+    /// Is the receiver of this [`Future::poll`](std::future::Future::poll)
+    /// call a `Pin<&mut Pin<Box<dyn Future>>>`.
+    fn polls_boxed_future(&self, args: &[Operand<'tcx>]) -> bool {
+        let Some(receiver) = args.first() else {
+            return false;
+        };
+        let ty::TyKind::Adt(_, pin_args) = receiver.ty(&self.mono_body, self.tcx()).kind() else {
+            return false;
+        };
+        pin_args
+            .first()
+            .and_then(|arg| arg.as_type())
+            .and_then(|reference| reference.builtin_deref(true))
+            .map_or(false, |future| {
+                match_pin_box_dyn_ty(self.tcx().lang_items(), future.ty)
+            })
+    }
+
     /// Given the arguments to a `Future::poll` call, walk back through the
     /// body to find the original future being polled, and get the arguments to the future.
     fn find_async_args<'a>(
@@ -262,8 +421,8 @@ impl<'tcx, 'mir> LocalAnalysis<'tcx, 'mir> {
                 Some(locs) = &self.body_assignments.get(&local),
                 "Local has no assignments"
             );
-            assert!(locs.len() == 1);
-            Ok(locs[0])
+            let_assert!([loc] = locs.as_slice(), "Local is assigned more than once");
+            Ok(*loc)
         };
 
         let_assert!(
@@ -314,7 +473,9 @@ impl<'tcx, 'mir> LocalAnalysis<'tcx, 'mir> {
                     },
                 ..
             }) => {
-                let (op, generics) = self.operand_to_def_id(func).unwrap();
+                let (op, generics) = self
+                    .operand_to_def_id(func)
+                    .ok_or_else(|| format!("Future is not created by a function item: {func:?}"))?;
                 (Some(op), generics, *destination)
             }
             Either::Left(Statement { kind, .. }) => match kind {
@@ -333,11 +494,15 @@ impl<'tcx, 'mir> LocalAnalysis<'tcx, 'mir> {
                     (None, generics, target.place().unwrap())
                 }
                 _ => {
-                    panic!("Assignment to into_future input is not a call: {stmt:?}");
+                    return Err(format!(
+                        "Assignment to into_future input is not a call: {stmt:?}"
+                    ));
                 }
             },
             _ => {
-                panic!("Assignment to into_future input is not a call: {stmt:?}");
+                return Err(format!(
+                    "Assignment to into_future input is not a call: {stmt:?}"
+                ));
             }
         };
 
@@ -357,6 +522,7 @@ impl<'tcx, 'mir> LocalAnalysis<'tcx, 'mir> {
             async_fn_parent,
             creation_loc,
             generator_data,
+            boxed_generator: None,
         })
     }
 }
@@ -1,11 +1,13 @@
-use std::path::PathBuf;
+use std::{fs::File, ops::Range, path::PathBuf};
 
 use flowistry::mir::FlowistryInput;
 
 use polonius_engine::FactTypes;
 use rustc_borrowck::consumers::{ConsumerOptions, RustcFacts};
+use rustc_data_structures::memmap::Mmap;
+use rustc_hash::FxHashMap;
 use rustc_hir::{
-    def_id::{CrateNum, DefId, LocalDefId, LOCAL_CRATE},
+    def_id::{CrateNum, DefId, DefIndex, LocalDefId, LOCAL_CRATE},
     intravisit::{self},
 };
 use rustc_macros::{Decodable, Encodable, TyDecodable, TyEncodable};
@@ -15,9 +17,11 @@ use rustc_middle::{
     ty::TyCtxt,
 };
 
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+use rustc_type_ir::TyEncoder;
 use rustc_utils::cache::Cache;
 
-use crate::encoder::{decode_from_file, encode_to_file};
+use crate::encoder::{ParalegalDecoder, ParalegalEncoder};
 
 /// A mir [`Body`] and all the additional borrow checking facts that our
 /// points-to analysis needs.
@@ -85,7 +89,9 @@ pub type LocationIndex = <RustcFacts as FactTypes>::Point;
 /// bodies it returns or risk UB.
 pub struct BodyCache<'tcx> {
     tcx: TyCtxt<'tcx>,
-    cache: Cache<DefId, CachedBody<'tcx>>,
+    cache: Cache<DefId, Option<CachedBody<'tcx>>>,
+    /// The opened body archive of each crate we loaded bodies from
+    archives: Cache<CrateNum, Option<BodyArchive>>,
 }
 
 impl<'tcx> BodyCache<'tcx> {
@@ -93,28 +99,158 @@ impl<'tcx> BodyCache<'tcx> {
         Self {
             tcx,
             cache: Default::default(),
+            archives: Default::default(),
         }
     }
 
     /// Serve the body from the cache or read it from the disk.
     ///
-    /// Returns `None` if the policy forbids loading from this crate.
+    /// Panics if no body was dumped for this item, use [`Self::try_get`] if
+    /// that may be the case.
     pub fn get(&self, key: DefId) -> &'tcx CachedBody<'tcx> {
-        let cbody = self.cache.get(key, |_| load_body_and_facts(self.tcx, key));
+        self.try_get(key).unwrap_or_else(|| {
+            panic!(
+                "No facts for {key:?} found at any path tried: {:?}",
+                local_or_remote_paths(key.krate, self.tcx, INTERMEDIATE_ARTIFACT_EXT)
+            )
+        })
+    }
+
+    /// Serve the body from the cache or read it from the disk.
+    ///
+    /// Returns `None` if no body was dumped for this item, for instance
+    /// because its crate was not compiled with
+    /// [`dump_mir_and_borrowck_facts`] or the build is stale.
+    pub fn try_get(&self, key: DefId) -> Option<&'tcx CachedBody<'tcx>> {
+        let cbody = self
+            .cache
+            .get(key, |_| {
+                let archive = self
+                    .archives
+                    .get(key.krate, |krate| BodyArchive::open(self.tcx, krate))
+                    .as_ref()?;
+                archive.load(self.tcx, key.index)
+            })
+            .as_ref()?;
         // SAFETY: Theoretically this struct may not outlive the body, but
         // to simplify lifetimes flowistry uses 'tcx anywhere. But if we
         // actually try to provide that we're risking race conditions
         // (because it needs global variables like MIR_BODIES).
         //
         // So until we fix flowistry's lifetimes this is good enough.
-        unsafe { std::mem::transmute(cbody) }
+        Some(unsafe { std::mem::transmute(cbody) })
+    }
+}
+
+/// The bodies of one crate, stored in a single file.
+///
+/// The file starts with [`ARCHIVE_MAGIC`], followed by the encoded
+/// [`CachedBody`]s and an index that maps the [`DefIndex`] of each body to its
+/// position and length in the file. The index is the number of entries
+/// followed by the entries, each a `u32` [`DefIndex`] and `u64` position and
+/// length. The file ends with the position of the index. All numbers outside
+/// of the bodies are little endian. Bodies are only decoded when they are
+/// requested.
+struct BodyArchive {
+    data: Mmap,
+    index: FxHashMap<DefIndex, Range<usize>>,
+}
+
+/// Identifies a body archive, includes a version of the format.
+const ARCHIVE_MAGIC: &[u8; 8] = b"PLGBODY2";
+
+/// The size of an entry in the index of a [`BodyArchive`].
+const INDEX_ENTRY_SIZE: usize = 4 + 8 + 8;
+
+impl BodyArchive {
+    /// Map the archive for `krate` into memory and read its index.
+    fn open(tcx: TyCtxt, krate: CrateNum) -> Option<Self> {
+        let paths = local_or_remote_paths(krate, tcx, INTERMEDIATE_ARTIFACT_EXT);
+        let Some(file) = paths.iter().find_map(|path| File::open(path).ok()) else {
+            log::debug!("No body archive found for crate {krate:?} at any path tried: {paths:?}");
+            return None;
+        };
+        // SAFETY: The archive is written once when its crate is compiled and
+        // not modified while this compiler session runs.
+        let data = unsafe { Mmap::map(file) }.ok()?;
+        match read_archive_index(&data) {
+            Ok(index) => Some(Self { data, index }),
+            Err(e) => {
+                log::warn!("The body archive for crate {krate:?} is corrupted: {e}");
+                None
+            }
+        }
+    }
+
+    /// Decode the body of the item with this index, if it is in the archive.
+    fn load<'tcx>(&self, tcx: TyCtxt<'tcx>, index: DefIndex) -> Option<CachedBody<'tcx>> {
+        let range = self.index.get(&index)?;
+        // Shorthands in the body may refer to earlier positions in the file,
+        // so the decoder sees everything up to the end of the body.
+        let mut decoder = ParalegalDecoder::new_at(tcx, &self.data[..range.end], range.start);
+        let body = CachedBody::decode(&mut decoder);
+        if decoder.position() != range.end {
+            log::warn!("The body for {index:?} does not match its length in the archive");
+            return None;
+        }
+        Some(body)
     }
 }
 
-/// A visitor to collect all bodies in the crate and write them to disk.
+/// Read the index of a [`BodyArchive`] and check that the bodies it points to
+/// lie between the magic number and the index.
+fn read_archive_index(data: &[u8]) -> Result<FxHashMap<DefIndex, Range<usize>>, String> {
+    let read_u64 = |at: usize| -> Result<usize, String> {
+        let bytes = data
+            .get(at..at + 8)
+            .ok_or_else(|| format!("position {at} is out of bounds"))?;
+        usize::try_from(u64::from_le_bytes(bytes.try_into().unwrap()))
+            .map_err(|_| format!("the number at {at} is too large"))
+    };
+    if data.get(..ARCHIVE_MAGIC.len()) != Some(&ARCHIVE_MAGIC[..]) {
+        return Err("unknown format".to_string());
+    }
+    let footer_start = data
+        .len()
+        .checked_sub(8)
+        .filter(|start| *start >= ARCHIVE_MAGIC.len())
+        .ok_or("the file is truncated")?;
+    let index_start = read_u64(footer_start)?;
+    if !(ARCHIVE_MAGIC.len()..footer_start).contains(&index_start) {
+        return Err(format!("index position {index_start} is out of bounds"));
+    }
+    let entries_start = index_start + 8;
+    let count = read_u64(index_start)?;
+    if count
+        .checked_mul(INDEX_ENTRY_SIZE)
+        .and_then(|size| size.checked_add(entries_start))
+        != Some(footer_start)
+    {
+        return Err(format!(
+            "{count} index entries do not fit between the index and the footer"
+        ));
+    }
+    (0..count)
+        .map(|i| {
+            let entry = entries_start + i * INDEX_ENTRY_SIZE;
+            let def_index = u32::from_le_bytes(data[entry..entry + 4].try_into().unwrap());
+            let start = read_u64(entry + 4)?;
+            let end = start
+                .checked_add(read_u64(entry + 12)?)
+                .filter(|end| start >= ARCHIVE_MAGIC.len() && *end <= index_start)
+                .ok_or_else(|| format!("the body of {def_index} is out of bounds"))?;
+            Ok((DefIndex::from_u32(def_index), start..end))
+        })
+        .collect()
+}
+
+/// A visitor to collect all bodies in the crate and write them to a
+/// [`BodyArchive`].
 struct DumpingVisitor<'tcx> {
     tcx: TyCtxt<'tcx>,
-    target_dir: PathBuf,
+    encoder: ParalegalEncoder<'tcx>,
+    /// The position and length of each body written so far
+    index: Vec<(u32, usize, usize)>,
 }
 
 /// Some data in a [Body] is not cross-crate compatible. Usually because it
@@ -151,19 +287,13 @@ impl<'tcx> intravisit::Visitor<'tcx> for DumpingVisitor<'tcx> {
         local_def_id: rustc_hir::def_id::LocalDefId,
     ) {
         let to_write = CachedBody::retrieve(self.tcx, local_def_id);
-
-        let dir = &self.target_dir;
-        let path = dir.join(
-            self.tcx
-                .def_path(local_def_id.to_def_id())
-                .to_filename_friendly_no_crate(),
-        );
-
-        if !dir.exists() {
-            std::fs::create_dir(dir).unwrap();
-        }
-
-        encode_to_file(self.tcx, path, &to_write);
+        let start = self.encoder.position();
+        to_write.encode(&mut self.encoder);
+        self.index.push((
+            local_def_id.local_def_index.as_u32(),
+            start,
+            self.encoder.position() - start,
+        ));
 
         intravisit::walk_fn(
             self,
@@ -181,12 +311,46 @@ impl<'tcx> intravisit::Visitor<'tcx> for DumpingVisitor<'tcx> {
 ///
 /// Ensure this gets called early in the compiler before the unoptimmized mir
 /// bodies are stolen.
-pub fn dump_mir_and_borrowck_facts(tcx: TyCtxt) {
+///
+/// If the [body store](body_store_dir) already contains the bodies of this
+/// crate nothing is written. Otherwise the bodies of dependencies (crates that
+/// cargo does not consider a primary package) are also added to the store.
+pub fn dump_mir_and_borrowck_facts(tcx: TyCtxt) -> std::io::Result<()> {
+    if let Some(stored) = stored_artifact(tcx, LOCAL_CRATE, INTERMEDIATE_ARTIFACT_EXT) {
+        log::debug!("Reusing bodies from {}", stored.display());
+        return Ok(());
+    }
+    let path = intermediate_out_dir(tcx, INTERMEDIATE_ARTIFACT_EXT);
+    // Earlier versions stored one file per body in a directory of this name
+    if path.is_dir() {
+        match std::fs::remove_dir_all(&path) {
+            // Another build of the crate may have removed it first
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+    }
+    let mut encoder = ParalegalEncoder::new(path, tcx);
+    encoder.emit_raw_bytes(ARCHIVE_MAGIC);
     let mut vis = DumpingVisitor {
         tcx,
-        target_dir: intermediate_out_dir(tcx, INTERMEDIATE_ARTIFACT_EXT),
+        encoder,
+        index: vec![],
     };
     tcx.hir().visit_all_item_likes_in_crate(&mut vis);
+    let DumpingVisitor {
+        mut encoder, index, ..
+    } = vis;
+    let index_start = encoder.position() as u64;
+    encoder.emit_raw_bytes(&(index.len() as u64).to_le_bytes());
+    for (def_index, start, len) in index {
+        encoder.emit_raw_bytes(&def_index.to_le_bytes());
+        encoder.emit_raw_bytes(&(start as u64).to_le_bytes());
+        encoder.emit_raw_bytes(&(len as u64).to_le_bytes());
+    }
+    encoder.emit_raw_bytes(&index_start.to_le_bytes());
+    encoder.finish();
+    add_to_body_store(tcx, &path, INTERMEDIATE_ARTIFACT_EXT);
+    Ok(())
 }
 
 const INTERMEDIATE_ARTIFACT_EXT: &str = "bwbf";
@@ -194,28 +358,109 @@ const INTERMEDIATE_ARTIFACT_EXT: &str = "bwbf";
 /// Get the path where artifacts from this crate would be stored. Unlike
 /// [`TyCtxt::crate_extern_paths`] this function does not crash when supplied
 /// with [`LOCAL_CRATE`].
+///
+/// The artifact from the [body store](body_store_dir) comes first, if there
+/// is one. It matches the crate hash of `krate`, whereas an artifact next to
+/// the crate may be left over from an earlier build.
 pub fn local_or_remote_paths(krate: CrateNum, tcx: TyCtxt, ext: &str) -> Vec<PathBuf> {
+    let mut paths = stored_artifact(tcx, krate, ext)
+        .into_iter()
+        .collect::<Vec<_>>();
     if krate == LOCAL_CRATE {
-        vec![intermediate_out_dir(tcx, ext)]
+        paths.push(intermediate_out_dir(tcx, ext));
     } else {
-        tcx.crate_extern_paths(krate)
-            .iter()
-            .map(|p| p.with_extension(ext))
-            .collect()
+        paths.extend(
+            tcx.crate_extern_paths(krate)
+                .iter()
+                .map(|p| p.with_extension(ext)),
+        );
     }
+    paths
 }
 
-/// Try to load a [`CachedBody`] for this id.
-fn load_body_and_facts(tcx: TyCtxt<'_>, def_id: DefId) -> CachedBody<'_> {
-    let paths = local_or_remote_paths(def_id.krate, tcx, INTERMEDIATE_ARTIFACT_EXT);
-    for path in &paths {
-        let path = path.join(tcx.def_path(def_id).to_filename_friendly_no_crate());
-        if let Ok(data) = decode_from_file(tcx, path) {
-            return data;
-        };
+/// Environment variable that points to the body store.
+pub const BODY_STORE_VAR: &str = "PARALEGAL_BODY_STORE";
+
+/// The directory of the shared body store, if it is enabled with
+/// [`BODY_STORE_VAR`].
+///
+/// The store keeps the artifacts (such as the dumped bodies) of crates across
+/// builds and projects so that dependencies only need to be dumped once. They
+/// are stored at `<store>/<rustc version>/<crate name>/<crate version>-<crate
+/// hash>.<ext>`. The crate hash identifies the exact build of the crate
+/// because the [`DefIndex`]es in the artifacts are only valid for that build.
+pub fn body_store_dir() -> Option<PathBuf> {
+    std::env::var_os(BODY_STORE_VAR)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The directory in the body store for `krate`.
+fn store_crate_dir(tcx: TyCtxt, krate: CrateNum) -> Option<PathBuf> {
+    let rustc_version = tcx
+        .sess
+        .cfg_version
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    Some(
+        body_store_dir()?
+            .join(rustc_version)
+            .join(tcx.crate_name(krate).as_str()),
+    )
+}
+
+/// The artifact with this extension for `krate` in the body store, if there is
+/// one.
+fn stored_artifact(tcx: TyCtxt, krate: CrateNum, ext: &str) -> Option<PathBuf> {
+    let dir = store_crate_dir(tcx, krate)?;
+    let suffix = format!("-{}.{ext}", tcx.crate_hash(krate).to_hex());
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.ends_with(&suffix))
+        })
+}
+
+/// Whether the body store contains the dumped bodies of `krate`.
+pub fn has_stored_bodies(tcx: TyCtxt, krate: CrateNum) -> bool {
+    stored_artifact(tcx, krate, INTERMEDIATE_ARTIFACT_EXT).is_some()
+}
+
+/// Add the artifact at `path` for the local crate to the body store if the
+/// crate is a dependency (one that cargo does not consider a primary package).
+///
+/// Failures are only logged, the artifact remains usable locally.
+pub fn add_to_body_store(tcx: TyCtxt, path: &std::path::Path, ext: &str) {
+    if std::env::var_os("CARGO_PRIMARY_PACKAGE").is_some() {
+        return;
     }
+    if let Err(e) = store_artifact(tcx, path, ext) {
+        log::warn!("Could not add {} to the body store: {e}", path.display());
+    }
+}
 
-    panic!("No facts for {def_id:?} found at any path tried: {paths:?}");
+/// Copy the artifact at `path` for the local crate into the body store.
+fn store_artifact(tcx: TyCtxt, path: &std::path::Path, ext: &str) -> std::io::Result<()> {
+    let Some(dir) = store_crate_dir(tcx, LOCAL_CRATE) else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(&dir)?;
+    let version = std::env::var("CARGO_PKG_VERSION").unwrap_or_else(|_| "unknown".to_string());
+    let hash = tcx.crate_hash(LOCAL_CRATE).to_hex();
+    let tmp = dir.join(format!("{version}-{hash}.{ext}.{}", std::process::id()));
+    std::fs::copy(path, &tmp)?;
+    // Renaming is atomic, concurrent readers never see a partial artifact
+    std::fs::rename(tmp, dir.join(format!("{version}-{hash}.{ext}")))
 }
 
 /// Create the name of the file in which to store intermediate artifacts.
@@ -2,13 +2,14 @@
 
 use flowistry_pdg::{rustc_portable::Location, CallString};
 
+use rustc_hir::def_id::DefId;
 use rustc_middle::{
     mir::{self, Operand},
-    ty::{Instance, ParamEnv},
+    ty::{GenericArgsRef, Instance, ParamEnv, PolyFnSig, Ty, TyCtxt},
 };
 use rustc_span::Span;
 
-use crate::calling_convention::CallingConvention;
+use crate::{calling_convention::CallingConvention, fn_pointers::FnPointerTargets};
 
 pub trait CallChangeCallback<'tcx> {
     fn on_inline(&self, info: CallInfo<'tcx, '_>) -> CallChanges<'tcx>;
@@ -23,6 +24,50 @@ pub trait CallChangeCallback<'tcx> {
         _call_span: Span,
     ) {
     }
+
+    /// Called for each call through a function pointer in the body of
+    /// `_under_analysis` whose possible targets are unknown, e.g. because the
+    /// pointer is an argument of the body. The call is approximated like any
+    /// call that is not inlined.
+    fn on_unknown_fn_pointer_call(
+        &self,
+        _under_analysis: Instance<'tcx>,
+        _loc: Location,
+        _call_span: Span,
+    ) {
+    }
+
+    /// Called for each operation in the body of `_under_analysis` whose
+    /// effects are approximated because the analysis cannot see through it,
+    /// see [`UnsafeOperation`].
+    fn on_unsafe_operation(
+        &self,
+        _under_analysis: Instance<'tcx>,
+        _loc: Location,
+        _operation: UnsafeOperation,
+        _span: Span,
+    ) {
+    }
+
+    /// Called when an entrypoint is a wrapper around an async block, such as
+    /// an `#[async_trait]` method, that does more than move its arguments
+    /// into the block before creating it at `_loc`. Only the async block is
+    /// analyzed, the flows of whatever `_wrapper` does before are missing
+    /// from the graph.
+    fn on_async_wrapper_prelude(&self, _wrapper: Instance<'tcx>, _loc: Location, _span: Span) {}
+
+    /// The interior mutability model of `_callee`, if any. Guards are located
+    /// with this before the calls of a body are handled, so a callback that
+    /// returns [`SkipCall::InteriorMutability`] with
+    /// [`InteriorMutability::Guard`] from [`Self::on_inline`] must return it
+    /// here too.
+    fn interior_mutability(
+        &self,
+        _callee: Instance<'tcx>,
+        _span: Span,
+    ) -> Option<InteriorMutability> {
+        None
+    }
 }
 
 pub struct CallChangeCallbackFn<'tcx> {
@@ -45,6 +90,21 @@ impl<'tcx> CallChangeCallback<'tcx> for CallChangeCallbackFn<'tcx> {
 pub enum InlineMissReason {
     Async(String),
     TraitMethod,
+    /// Inlining the call would exceed the limit set with
+    /// [`MemoPdgConstructor::with_call_string_limit`](crate::MemoPdgConstructor::with_call_string_limit).
+    CallStringLimit,
+}
+
+/// An operation that the alias analysis cannot see through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsafeOperation {
+    /// Dereferencing a raw pointer or a pointer created by a transmute or a
+    /// foreign function.
+    RawPointerDeref,
+    /// A call to `transmute` or a transmuting cast.
+    Transmute,
+    /// A call to a function in an `extern` block.
+    ForeignCall,
 }
 
 impl<'tcx> Default for CallChanges<'tcx> {
@@ -67,6 +127,9 @@ pub struct CallInfo<'tcx, 'mir> {
     /// The call-stack up to the current call site.
     pub call_string: CallString,
 
+    /// The function whose body contains the call.
+    pub caller: Instance<'tcx>,
+
     /// Would the PDG for this function be served from the cache.
     pub is_cached: bool,
 
@@ -76,18 +139,50 @@ pub struct CallInfo<'tcx, 'mir> {
 
     pub caller_body: &'mir mir::Body<'tcx>,
     pub param_env: ParamEnv<'tcx>,
+
+    /// Function pointers in [`Self::caller_body`], computed once per body.
+    pub(crate) fn_pointer_targets: &'mir FnPointerTargets<'tcx>,
+}
+
+impl<'tcx> CallInfo<'tcx, '_> {
+    /// The functions that the arguments of type `ty`, a function pointer with
+    /// signature `sig`, may point to, as determined for calls through
+    /// function pointers in the caller. `None` if no argument has this type
+    /// or the targets of one of them are unknown.
+    pub fn fn_pointer_candidates(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        ty: Ty<'tcx>,
+        sig: PolyFnSig<'tcx>,
+    ) -> Option<Vec<(DefId, GenericArgsRef<'tcx>)>> {
+        let body = self.caller_body;
+        let mut candidates = vec![];
+        let mut found = false;
+        for place in self.arguments.iter().filter_map(Operand::place) {
+            if tcx.erase_regions(place.ty(body, tcx).ty) != tcx.erase_regions(ty) {
+                continue;
+            }
+            found = true;
+            for candidate in self.fn_pointer_targets.resolve(tcx, place, sig)? {
+                if !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        found.then_some(candidates)
+    }
 }
 
 /// User-provided changes to the default PDG construction behavior for function calls.
 ///
 /// Construct [`CallChanges`] via [`CallChanges::default`].
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CallChanges<'tcx> {
     pub(crate) skip: SkipCall<'tcx>,
 }
 
 /// Whether or not to skip recursing into a function call during PDG construction.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SkipCall<'tcx> {
     /// Skip the function, and perform a modular approxmation of its effects.
     Skip,
@@ -100,6 +195,75 @@ pub enum SkipCall<'tcx> {
         instance: Instance<'tcx>,
         calling_convention: CallingConvention<'tcx>,
     },
+
+    /// Skip the function and use the provided description of its effects
+    /// instead of the modular approximation.
+    Summary(CallSummary),
+
+    /// Skip the function and treat it as an access to an interior-mutable
+    /// value behind a shared reference.
+    InteriorMutability(InteriorMutability),
+}
+
+/// How a function accesses an interior-mutable value (e.g. a `RefCell`) that
+/// it receives by shared reference. The modular approximation assumes that
+/// nothing behind a shared reference is mutated, these models describe the
+/// mutations instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InteriorMutability {
+    /// The value behind argument `target` is overwritten with the other
+    /// arguments, e.g. `Cell::set` or `AtomicUsize::store`. The return value
+    /// depends on all arguments and the old value.
+    Set { target: u8 },
+    /// Like [`Self::Set`] but the new value also depends on the old one, e.g.
+    /// `AtomicUsize::fetch_add`.
+    Update { target: u8 },
+    /// Returns a guard through which the value behind the first argument can
+    /// be mutated, e.g. `RefCell::borrow_mut` or `Mutex::lock`. Writes
+    /// through the guard (or values it is moved into) mutate that value.
+    Guard,
+}
+
+/// User provided description of which arguments of a call flow where.
+///
+/// Argument indices are 0-based and refer to the arguments as they appear at
+/// the call site.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallSummary {
+    /// Arguments whose values flow into the return value.
+    pub return_from: Vec<u8>,
+    /// Arguments that are written through (e.g. `&mut` arguments), together
+    /// with the arguments whose values flow into them.
+    pub writes: Vec<(u8, Vec<u8>)>,
+}
+
+impl CallSummary {
+    /// Add the flows of `other` to this summary.
+    pub(crate) fn join(&mut self, other: &CallSummary) {
+        self.return_from.extend(&other.return_from);
+        self.writes.extend(other.writes.iter().cloned());
+        self.normalize();
+    }
+
+    /// Sort and deduplicate the flows, merging writes to the same argument,
+    /// so that equal summaries compare equal.
+    pub(crate) fn normalize(&mut self) {
+        self.return_from.sort_unstable();
+        self.return_from.dedup();
+        self.writes.sort_by_key(|(target, _)| *target);
+        let mut merged: Vec<(u8, Vec<u8>)> = Vec::with_capacity(self.writes.len());
+        for (target, mut sources) in self.writes.drain(..) {
+            match merged.last_mut() {
+                Some((last, known)) if *last == target => known.append(&mut sources),
+                _ => merged.push((target, sources)),
+            }
+        }
+        for (_, sources) in &mut merged {
+            sources.sort_unstable();
+            sources.dedup();
+        }
+        self.writes = merged;
+    }
 }
 
 impl<'tcx> CallChanges<'tcx> {
@@ -6,27 +6,55 @@ use rustc_abi::FieldIdx;
 
 use rustc_middle::{
     mir::{Body, HasLocalDecls, Operand, Place, PlaceElem, RETURN_PLACE},
-    ty::TyCtxt,
+    ty::{Ty, TyCtxt},
 };
 
 use crate::{async_support::AsyncInfo, local_analysis::CallKind, utils};
 
 /// Describes how the formal parameters of a given function call relate to the
 /// actual parameters.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum CallingConvention<'tcx> {
     /// 1 to 1 mapping
     Direct(Box<[Operand<'tcx>]>),
-    /// First argument is the closed-over environment, second argument is a
-    /// tuple that contains the actual argument to the call of the closure
-    /// function.
+    /// First argument is the closed-over environment, the remaining formal
+    /// parameters are described by `arguments`.
     Indirect {
         once_shim: bool,
         closure_arg: Operand<'tcx>,
-        tupled_arguments: Operand<'tcx>,
+        arguments: ClosureArguments<'tcx>,
     },
     /// An async generator, only has one argument which is the generator state.
-    Async(Place<'tcx>),
+    Async {
+        generator: Place<'tcx>,
+        /// The concrete type of the generator if `generator` is a boxed trait
+        /// object, see
+        /// [`AsyncFnPollEnv::boxed_generator`](crate::async_support::AsyncFnPollEnv::boxed_generator).
+        boxed_generator: Option<Ty<'tcx>>,
+    },
+}
+
+/// How the formal parameters of a closure (all but the environment) relate to
+/// the actual parameters in a [`CallingConvention::Indirect`] call.
+#[derive(Debug)]
+pub enum ClosureArguments<'tcx> {
+    /// A tuple that contains the actual arguments, as in
+    /// `Fn::call(closure, (args..))`.
+    Tupled(Operand<'tcx>),
+    /// One entry per formal parameter. Used when a call to a higher-order
+    /// function is replaced by a call to its closure, parameters without an
+    /// entry have no counterpart in the caller.
+    Spread(Box<[ClosureArgument<'tcx>]>),
+}
+
+/// An actual parameter in [`ClosureArguments::Spread`].
+#[derive(Debug)]
+pub enum ClosureArgument<'tcx> {
+    /// The formal parameter is this operand.
+    Exact(Operand<'tcx>),
+    /// The formal parameter is an element of this collection. Elements have
+    /// no place in the caller, so the whole collection stands in for them.
+    ElementOf(Operand<'tcx>),
 }
 
 /// The result of calculating a translation from a child place (in a called
@@ -55,6 +83,12 @@ impl<'a, 'tcx> PlaceTranslation<'a, 'tcx> {
             self.scope.tcx,
             self.scope.parent_body,
             self.scope.parent_body_def_id,
+            match self.scope.calling_convention {
+                CallingConvention::Async {
+                    boxed_generator, ..
+                } => *boxed_generator,
+                _ => None,
+            },
         )
     }
 
@@ -70,12 +104,15 @@ impl<'tcx> CallingConvention<'tcx> {
         args: Cow<'_, [Operand<'tcx>]>,
     ) -> CallingConvention<'tcx> {
         match kind {
-            CallKind::AsyncPoll(poll) => CallingConvention::Async(poll.generator_data),
+            CallKind::AsyncPoll(poll) => CallingConvention::Async {
+                generator: poll.generator_data,
+                boxed_generator: poll.boxed_generator,
+            },
             CallKind::Direct => CallingConvention::Direct(args.into()),
             CallKind::Indirect { once_shim } => CallingConvention::Indirect {
                 once_shim: *once_shim,
                 closure_arg: args[0].clone(),
-                tupled_arguments: args[1].clone(),
+                arguments: ClosureArguments::Tupled(args[1].clone()),
             },
         }
     }
@@ -174,19 +211,19 @@ impl<'a, 'tcx> PlaceTranslator<'a, 'tcx> {
                 &child.projection[..],
             ),
             // Map arguments to projections of the future, the poll's first argument
-            CallingConvention::Async(ctx) => {
+            CallingConvention::Async { generator, .. } => {
                 if child.local.as_usize() == 1 {
-                    (*ctx, &child.projection[..])
+                    (*generator, &child.projection[..])
                 } else {
                     return None;
                 }
             }
             // Map closure captures to the first argument.
-            // Map formal parameters to the second argument.
+            // Map formal parameters to the remaining arguments.
             CallingConvention::Indirect {
                 once_shim,
                 closure_arg,
-                tupled_arguments,
+                arguments,
             } => {
                 if child.local.as_usize() == 1 {
                     // Accounting for shims
@@ -212,16 +249,29 @@ impl<'a, 'tcx> PlaceTranslator<'a, 'tcx> {
                     };
                     (closure_arg.place()?, &child.projection[next_idx..])
                 } else {
-                    let tuple_arg = tupled_arguments.place()?;
-                    let _projection = child.projection.to_vec();
-                    let field = FieldIdx::from_usize(child.local.as_usize() - 2);
-                    let field_ty = tuple_arg
-                        .ty(self.parent_body, self.tcx)
-                        .field_ty(self.tcx, field);
-                    (
-                        tuple_arg.project_deeper(&[PlaceElem::Field(field, field_ty)], self.tcx),
-                        &child.projection[..],
-                    )
+                    let param_idx = child.local.as_usize() - 2;
+                    match arguments {
+                        ClosureArguments::Tupled(tupled) => {
+                            let tuple_arg = tupled.place()?;
+                            let field = FieldIdx::from_usize(param_idx);
+                            let field_ty = tuple_arg
+                                .ty(self.parent_body, self.tcx)
+                                .field_ty(self.tcx, field);
+                            (
+                                tuple_arg
+                                    .project_deeper(&[PlaceElem::Field(field, field_ty)], self.tcx),
+                                &child.projection[..],
+                            )
+                        }
+                        ClosureArguments::Spread(args) => match args.get(param_idx)? {
+                            ClosureArgument::Exact(arg) => (arg.place()?, &child.projection[..]),
+                            // Projections of the element cannot be applied
+                            // to the collection.
+                            ClosureArgument::ElementOf(collection) => {
+                                (collection.place()?, &[][..])
+                            }
+                        },
+                    }
                 }
             }
         };
@@ -0,0 +1,157 @@
+//! Conservative treatment of operations the alias analysis cannot see through.
+//!
+//! Flowistry computes aliases from the facts of the borrow checker. These say
+//! nothing about raw pointers, values produced by `transmute` or pointers that
+//! are handed to foreign functions. When enabled with
+//! [`MemoPdgConstructor::with_conservative_unsafe`] a dereference of such a
+//! pointer is assumed to access every place in the body that has the type of
+//! the pointee. [`UnsafeOperations`] finds these operations in a body so that
+//! they can be reported to
+//! [`CallChangeCallback::on_unsafe_operation`](crate::CallChangeCallback::on_unsafe_operation).
+//!
+//! [`MemoPdgConstructor::with_conservative_unsafe`]: crate::MemoPdgConstructor::with_conservative_unsafe
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use rustc_hir::def_id::DefId;
+use rustc_middle::{
+    mir::{
+        visit::{PlaceContext, Visitor},
+        Body, CastKind, Local, Location, Place, ProjectionElem, Rvalue, StatementKind,
+        TerminatorKind,
+    },
+    ty::{Ty, TyCtxt},
+};
+use rustc_span::sym;
+
+use crate::callback::UnsafeOperation;
+
+/// The operations in a body that are approximated conservatively.
+pub(crate) struct UnsafeOperations {
+    /// Every such operation with its location, in the order of the body.
+    pub(crate) locations: Vec<(Location, UnsafeOperation)>,
+    /// Locals that are assigned the result of a transmute or a foreign call.
+    /// If these are pointers the borrow checker knows nothing about their
+    /// pointees.
+    opaque: FxHashSet<Local>,
+    /// Calls whose effects are approximated conservatively.
+    opaque_calls: FxHashMap<Location, UnsafeOperation>,
+}
+
+impl UnsafeOperations {
+    pub(crate) fn collect<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>) -> Self {
+        let mut this = UnsafeOperations {
+            locations: vec![],
+            opaque: Default::default(),
+            opaque_calls: Default::default(),
+        };
+        for (block, data) in body.basic_blocks.iter_enumerated() {
+            for (statement_index, statement) in data.statements.iter().enumerate() {
+                let StatementKind::Assign(box (destination, Rvalue::Cast(CastKind::Transmute, ..))) =
+                    &statement.kind
+                else {
+                    continue;
+                };
+                let location = Location {
+                    block,
+                    statement_index,
+                };
+                this.locations.push((location, UnsafeOperation::Transmute));
+                this.opaque.insert(destination.local);
+            }
+            let TerminatorKind::Call {
+                func, destination, ..
+            } = &data.terminator().kind
+            else {
+                continue;
+            };
+            let Some((def_id, _)) = func.const_fn_def() else {
+                continue;
+            };
+            let Some(operation) = opaque_call(tcx, def_id) else {
+                continue;
+            };
+            let location = body.terminator_loc(block);
+            this.locations.push((location, operation));
+            this.opaque.insert(destination.local);
+            this.opaque_calls.insert(location, operation);
+        }
+
+        let mut derefs = RawDerefs {
+            operations: &this,
+            tcx,
+            body,
+            found: vec![],
+        };
+        derefs.visit_body(body);
+        let found = derefs.found;
+        this.locations.extend(
+            found
+                .into_iter()
+                .map(|location| (location, UnsafeOperation::RawPointerDeref)),
+        );
+        this.locations.sort_by_key(|(location, _)| *location);
+        this.locations.dedup();
+        this
+    }
+
+    /// Whether the call at `location` is a transmute or a foreign call.
+    pub(crate) fn opaque_call(&self, location: Location) -> Option<UnsafeOperation> {
+        self.opaque_calls.get(&location).copied()
+    }
+
+    /// If `place` dereferences a pointer whose pointees the borrow checker does
+    /// not know, returns the length of the projection up to and including
+    /// the first such dereference and the type of the pointee.
+    pub(crate) fn opaque_deref<'tcx>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        body: &Body<'tcx>,
+        place: Place<'tcx>,
+    ) -> Option<(usize, Ty<'tcx>)> {
+        place.iter_projections().find_map(|(base, elem)| {
+            if elem != ProjectionElem::Deref {
+                return None;
+            }
+            let base_ty = base.ty(body, tcx).ty;
+            let is_opaque = base_ty.is_unsafe_ptr()
+                || (base.projection.is_empty() && self.opaque.contains(&base.local));
+            if !is_opaque {
+                return None;
+            }
+            let pointee = base_ty.builtin_deref(true)?.ty;
+            Some((base.projection.len() + 1, pointee))
+        })
+    }
+}
+
+/// Whether a call to `def_id` is treated conservatively and why.
+fn opaque_call(tcx: TyCtxt<'_>, def_id: DefId) -> Option<UnsafeOperation> {
+    if tcx.is_foreign_item(def_id) {
+        Some(UnsafeOperation::ForeignCall)
+    } else if tcx.is_intrinsic(def_id) && tcx.item_name(def_id) == sym::transmute {
+        Some(UnsafeOperation::Transmute)
+    } else {
+        None
+    }
+}
+
+/// Finds the locations that dereference an opaque pointer.
+struct RawDerefs<'a, 'tcx> {
+    operations: &'a UnsafeOperations,
+    tcx: TyCtxt<'tcx>,
+    body: &'a Body<'tcx>,
+    found: Vec<Location>,
+}
+
+impl<'tcx> Visitor<'tcx> for RawDerefs<'_, 'tcx> {
+    fn visit_place(&mut self, place: &Place<'tcx>, _context: PlaceContext, location: Location) {
+        if self.found.last() != Some(&location)
+            && self
+                .operations
+                .opaque_deref(self.tcx, self.body, *place)
+                .is_some()
+        {
+            self.found.push(location);
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use std::{borrow::Cow, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::{OnceCell, RefCell},
+    rc::Rc,
+};
 
 use either::Either;
 use flowistry::mir::FlowistryInput;
@@ -13,7 +17,7 @@ use rustc_hir::def_id::{DefId, LocalDefId};
 use rustc_index::IndexVec;
 use rustc_middle::{
     mir::{visit::Visitor, AggregateKind, Location, Place, Rvalue, Terminator, TerminatorKind},
-    ty::{Instance, TyCtxt},
+    ty::{Instance, ParamEnv, TyCtxt},
 };
 use rustc_mir_dataflow::{self as df};
 
@@ -23,12 +27,14 @@ use crate::{
     async_support::*,
     body_cache::{self, BodyCache, CachedBody},
     calling_convention::PlaceTranslator,
+    dyn_dispatch::DynImplementors,
     graph::{
         push_call_string_root, DepEdge, DepGraph, DepNode, PartialGraph, SourceUse, TargetUse,
     },
     local_analysis::{CallHandling, InstructionState, LocalAnalysis},
     mutation::{ModularMutationVisitor, Mutation, Time},
-    utils::{manufacture_substs_for, try_resolve_function},
+    recursion::SummaryCache,
+    utils::{instantiate_substs_for, try_resolve_function, Instantiation},
     CallChangeCallback,
 };
 
@@ -43,6 +49,13 @@ pub struct MemoPdgConstructor<'tcx> {
     pub(crate) async_info: Rc<AsyncInfo>,
     pub(crate) pdg_cache: PdgCache<'tcx>,
     pub(crate) body_cache: Rc<body_cache::BodyCache<'tcx>>,
+    closed_world_dyn_dispatch: bool,
+    dyn_implementors: OnceCell<DynImplementors<'tcx>>,
+    call_candidates: RefCell<FxHashMap<GlobalLocation, Vec<DefId>>>,
+    polled_generators: RefCell<FxHashMap<GlobalLocation, Vec<Instance<'tcx>>>>,
+    call_string_limit: Option<usize>,
+    pub(crate) conservative_unsafe: bool,
+    pub(crate) summaries: SummaryCache<'tcx>,
 }
 
 impl<'tcx> MemoPdgConstructor<'tcx> {
@@ -55,6 +68,13 @@ impl<'tcx> MemoPdgConstructor<'tcx> {
             async_info: AsyncInfo::make(tcx).expect("Async functions are not defined"),
             pdg_cache: Default::default(),
             body_cache: Rc::new(BodyCache::new(tcx)),
+            closed_world_dyn_dispatch: false,
+            dyn_implementors: Default::default(),
+            call_candidates: Default::default(),
+            polled_generators: Default::default(),
+            call_string_limit: None,
+            conservative_unsafe: false,
+            summaries: Default::default(),
         }
     }
 
@@ -67,6 +87,13 @@ impl<'tcx> MemoPdgConstructor<'tcx> {
             async_info: AsyncInfo::make(tcx).expect("Async functions are not defined"),
             pdg_cache: Default::default(),
             body_cache,
+            closed_world_dyn_dispatch: false,
+            dyn_implementors: Default::default(),
+            call_candidates: Default::default(),
+            polled_generators: Default::default(),
+            call_string_limit: None,
+            conservative_unsafe: false,
+            summaries: Default::default(),
         }
     }
 
@@ -76,6 +103,41 @@ impl<'tcx> MemoPdgConstructor<'tcx> {
         self
     }
 
+    /// Resolve calls through `dyn Trait` objects under a closed-world
+    /// assumption: the only implementations of the trait are those that are
+    /// coerced to a trait object in the local crate. Each such implementation
+    /// is inlined and their graphs are joined at the call site.
+    ///
+    /// Without this option (the default) these calls are approximated.
+    pub fn with_closed_world_dyn_dispatch(&mut self, closed_world: bool) -> &mut Self {
+        self.closed_world_dyn_dispatch = closed_world;
+        self
+    }
+
+    /// Inline at most `limit` levels of calls below the root function, calls
+    /// beyond that are approximated and reported to
+    /// [`CallChangeCallback::on_inline_miss`] with
+    /// [`InlineMissReason::CallStringLimit`]. Call strings in the resulting
+    /// graph have at most `limit + 1` locations.
+    ///
+    /// `None` (the default) inlines without limit.
+    pub fn with_call_string_limit(&mut self, limit: Option<usize>) -> &mut Self {
+        self.call_string_limit = limit;
+        self
+    }
+
+    /// Treat raw pointers, transmutes and foreign calls conservatively: a
+    /// dereference of a pointer the borrow checker knows nothing about may
+    /// access any place in the body with the type of the pointee. Each such
+    /// operation is reported to [`CallChangeCallback::on_unsafe_operation`].
+    ///
+    /// Without this option (the default) these operations only use the
+    /// aliases computed by Flowistry, which may miss flows.
+    pub fn with_conservative_unsafe(&mut self, conservative: bool) -> &mut Self {
+        self.conservative_unsafe = conservative;
+        self
+    }
+
     /// Register a callback to determine how to deal with function calls seen.
     /// Overwrites any previously registered callback with no warning.
     pub fn with_call_change_callback(
@@ -89,34 +151,124 @@ impl<'tcx> MemoPdgConstructor<'tcx> {
     /// Construct the intermediate PDG for this function. Instantiates any
     /// generic arguments as `dyn <constraints>`.
     pub fn construct_root<'a>(&'a self, function: LocalDefId) -> &'a PartialGraph<'tcx> {
-        let generics = manufacture_substs_for(self.tcx, function.to_def_id())
+        self.construct_instantiated_root(function, &Instantiation::default())
+    }
+
+    fn construct_instantiated_root<'a>(
+        &'a self,
+        function: LocalDefId,
+        instantiation: &Instantiation<'tcx>,
+    ) -> &'a PartialGraph<'tcx> {
+        self.construct_for(
+            self.root_instance(function, instantiation),
+            self.call_string_limit,
+        )
+        .expect("Invariant broken, entrypoint cannot have been recursive.")
+    }
+
+    /// The instance of `function` that is analyzed when it is an entrypoint.
+    fn root_instance(
+        &self,
+        function: LocalDefId,
+        instantiation: &Instantiation<'tcx>,
+    ) -> Instance<'tcx> {
+        let generics = instantiate_substs_for(self.tcx, function.to_def_id(), instantiation)
             .map_err(|i| vec![i])
             .unwrap();
-        let resolution = try_resolve_function(
+        try_resolve_function(
             self.tcx,
             function.to_def_id(),
             self.tcx.param_env_reveal_all_normalized(function),
             generics,
         )
-        .unwrap();
-
-        self.construct_for(resolution)
-            .expect("Invariant broken, entrypoint cannot have been recursive.")
+        .unwrap()
     }
 
     /// Construct a  graph for this instance of return it from the cache.
+    /// `inline_budget` is the number of levels of calls that may still be
+    /// inlined below this function.
     ///
-    /// Returns `None` if this is a recursive call trying to construct the graph again.
+    /// Returns `None` if this is a recursive call trying to construct the
+    /// graph again. Use [`Self::recursive_summary`] for such calls.
     pub(crate) fn construct_for<'a>(
         &'a self,
         resolution: Instance<'tcx>,
+        inline_budget: Option<usize>,
     ) -> Option<&'a PartialGraph<'tcx>> {
-        self.pdg_cache.get_maybe_recursive(resolution, |_| {
-            let g = LocalAnalysis::new(self, resolution).construct_partial();
-            trace!("Computed new for {resolution:?}");
-            g.check_invariants();
-            g
-        })
+        self.pdg_cache
+            .get_maybe_recursive((resolution, inline_budget), |_| {
+                let g =
+                    LocalAnalysis::new(self, resolution, inline_budget, false).construct_partial();
+                trace!("Computed new for {resolution:?}");
+                g.check_invariants();
+                g
+            })
+    }
+
+    /// Resolve a call to a trait object method to all local candidate
+    /// implementations. Only succeeds in closed-world mode, see
+    /// [`Self::with_closed_world_dyn_dispatch`].
+    pub(crate) fn resolve_dyn_call(
+        &self,
+        virtual_fn: Instance<'tcx>,
+        param_env: ParamEnv<'tcx>,
+    ) -> Option<Vec<Instance<'tcx>>> {
+        if !self.closed_world_dyn_dispatch {
+            return None;
+        }
+        self.dyn_implementors
+            .get_or_init(|| DynImplementors::collect(self.tcx, &self.body_cache))
+            .candidates(self.tcx, param_env, virtual_fn)
+    }
+
+    pub(crate) fn record_call_candidates(
+        &self,
+        at: GlobalLocation,
+        candidates: impl IntoIterator<Item = DefId>,
+    ) {
+        let mut recorded = self.call_candidates.borrow_mut();
+        let known = recorded.entry(at).or_default();
+        for candidate in candidates {
+            if !known.contains(&candidate) {
+                known.push(candidate);
+            }
+        }
+    }
+
+    /// The functions that a call through a trait object or a function pointer
+    /// at this location was resolved to. Empty if the call was not resolved.
+    ///
+    /// Note that a location may be visited in several instantiations of its
+    /// function, this returns the candidates of all of them.
+    pub fn call_candidates(&self, at: GlobalLocation) -> Vec<DefId> {
+        self.call_candidates
+            .borrow()
+            .get(&at)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn record_polled_generator(&self, at: GlobalLocation, generator: Instance<'tcx>) {
+        let mut recorded = self.polled_generators.borrow_mut();
+        let known = recorded.entry(at).or_default();
+        if !known.contains(&generator) {
+            known.push(generator);
+        }
+    }
+
+    /// The generators that were inlined for a poll of a boxed future at this
+    /// location, instead of the `poll` implementation of the box. This happens
+    /// when the future was created by a wrapper around an async block, such
+    /// as an `#[async_trait]` method.
+    ///
+    /// Like [`Self::call_candidates`] this contains the generators of all
+    /// instantiations of the function of the location.
+    pub fn polled_generators(&self, at: GlobalLocation) -> Vec<Instance<'tcx>> {
+        self.polled_generators
+            .borrow()
+            .get(&at)
+            .cloned()
+            .unwrap_or_default()
     }
 
     /// Has a PDG been constructed for this instance before?
@@ -127,21 +279,44 @@ impl<'tcx> MemoPdgConstructor<'tcx> {
     /// Construct a final PDG for this function. Same as
     /// [`Self::construct_root`] this instantiates all generics as `dyn`.
     ///
-    /// Additionally if this is an `async fn` or `#[async_trait]` it will inline
+    /// Additionally if this is an `async fn`, an `#[async_trait]` or a function
+    /// returning an async block as `impl Future` it will inline
     /// the closure as though the function were called with `poll`.
+    /// Anything a wrapper does before it creates its async block is not part
+    /// of the graph, such wrappers are reported to
+    /// [`CallChangeCallback::on_async_wrapper_prelude`].
     pub fn construct_graph(&self, function: LocalDefId) -> DepGraph<'tcx> {
-        if let Some((generator, loc, _ty)) = determine_async(
-            self.tcx,
-            function.to_def_id(),
-            self.body_cache.get(function.to_def_id()).body(),
-        ) {
+        self.construct_instantiated_graph(function, &Instantiation::default())
+    }
+
+    /// Like [`Self::construct_graph`] but the type parameters in
+    /// `instantiation` are instantiated with the given types instead of `dyn`.
+    pub fn construct_instantiated_graph(
+        &self,
+        function: LocalDefId,
+        instantiation: &Instantiation<'tcx>,
+    ) -> DepGraph<'tcx> {
+        let body = self.body_cache.get(function.to_def_id()).body();
+        if let Some((generator, loc, asyncness)) =
+            determine_async(self.tcx, function.to_def_id(), body)
+        {
             // TODO remap arguments
+            let generator = generator.def_id().expect_local();
+            if asyncness != AsyncType::Fn && has_async_prelude(body, loc) {
+                if let Some(callback) = &self.call_change_callback {
+                    callback.on_async_wrapper_prelude(
+                        self.root_instance(function, instantiation),
+                        loc,
+                        self.tcx.def_span(function),
+                    );
+                }
+            }
 
             // Note that this deliberately register this result in a separate
             // cache. This is because when this async fn is called somewhere we
             // don't want to use this "fake inlined" version.
             return push_call_string_root(
-                self.construct_root(generator.def_id().expect_local()),
+                self.construct_instantiated_root(generator, instantiation),
                 GlobalLocation {
                     function: function.to_def_id(),
                     location: flowistry_pdg::RichLocation::Location(loc),
@@ -149,7 +324,8 @@ impl<'tcx> MemoPdgConstructor<'tcx> {
             )
             .to_petgraph();
         }
-        self.construct_root(function).to_petgraph()
+        self.construct_instantiated_root(function, instantiation)
+            .to_petgraph()
     }
 
     /// Try to retrieve or load a body for this id.
@@ -256,19 +432,24 @@ impl<'mir, 'tcx> ResultsVisitor<'mir, 'tcx, LocalAnalysisResults<'tcx, 'mir>>
         terminator: &'mir rustc_middle::mir::Terminator<'tcx>,
         location: Location,
     ) {
+        let mut handling = None;
         if let TerminatorKind::Call { func, args, .. } = &terminator.kind {
             let constructor = results.analysis;
 
-            if matches!(
-                constructor.determine_call_handling(
-                    location,
-                    Cow::Borrowed(func),
-                    Cow::Borrowed(args),
-                    terminator.source_info.span
-                ),
-                Some(CallHandling::Ready { .. })
+            match constructor.determine_call_handling(
+                location,
+                Cow::Borrowed(func),
+                Cow::Borrowed(args),
+                terminator.source_info.span,
             ) {
-                return;
+                Some(
+                    CallHandling::Ready { .. }
+                    | CallHandling::Dispatched {
+                        approximated: false,
+                        ..
+                    },
+                ) => return,
+                other => handling = other,
             }
         }
 
@@ -287,7 +468,24 @@ impl<'mir, 'tcx> ResultsVisitor<'mir, 'tcx, LocalAnalysisResults<'tcx, 'mir>>
                 )
             });
         arg_vis.set_time(Time::After);
-        arg_vis.visit_terminator(terminator, location);
+        let TerminatorKind::Call {
+            args, destination, ..
+        } = &terminator.kind
+        else {
+            return arg_vis.visit_terminator(terminator, location);
+        };
+        match handling {
+            Some(CallHandling::Summarized(summary)) => {
+                arg_vis.visit_summarized_call(args, *destination, location, &summary)
+            }
+            Some(CallHandling::InteriorMutability(model)) => {
+                arg_vis.visit_interior_mutability_call(args, *destination, location, model)
+            }
+            Some(CallHandling::Conservative) => {
+                arg_vis.visit_conservative_call(args, *destination, location)
+            }
+            _ => arg_vis.visit_terminator(terminator, location),
+        }
     }
 }
 
@@ -343,12 +541,20 @@ impl<'tcx> PartialGraph<'tcx> {
             return false;
         };
 
-        let (child_descriptor, calling_convention, precise) = match handling {
+        let (child_descriptors, calling_convention, precise, approximated) = match handling {
             CallHandling::Ready {
                 calling_convention,
                 descriptor,
                 precise,
-            } => (descriptor, calling_convention, precise),
+            } => (vec![descriptor], calling_convention, precise, false),
+            // Each inlined candidate's graph joins at the call site, the
+            // others are approximated like a call that is not inlined.
+            CallHandling::Dispatched {
+                calling_convention,
+                descriptors,
+                precise,
+                approximated,
+            } => (descriptors, calling_convention, precise, approximated),
             CallHandling::ApproxAsyncFn => {
                 // Register a synthetic assignment of `future = (arg0, arg1, ...)`.
                 let rvalue = Rvalue::Aggregate(
@@ -372,12 +578,14 @@ impl<'tcx> PartialGraph<'tcx> {
                 );
                 return false;
             }
+            // Handled like a non-inlined call, but with the effects from the
+            // summary or model, see
+            // `visit_terminator_after_primary_effect`.
+            CallHandling::Summarized(_)
+            | CallHandling::InteriorMutability(_)
+            | CallHandling::Conservative => return false,
         };
 
-        let child_graph = push_call_string_root(child_descriptor, gloc);
-
-        trace!("Child graph has generics {:?}", child_descriptor.generics);
-
         let is_root = |n: CallString| n.len() == 2;
 
         let translator = PlaceTranslator::new(
@@ -390,48 +598,54 @@ impl<'tcx> PartialGraph<'tcx> {
             precise,
         );
 
-        // For each source node CHILD that is parentable to PLACE,
-        // add an edge from PLACE -> CHILD.
-        trace!("PARENT -> CHILD EDGES:");
-        for (child_src, _kind) in child_graph.parentable_srcs(is_root) {
-            if let Some(translation) = translator.translate_to_parent(child_src.place) {
-                self.register_mutation(
-                    results,
-                    state,
-                    Inputs::Unresolved {
-                        places: vec![(translation, None)],
-                    },
-                    Either::Right(child_src),
-                    location,
-                    TargetUse::Assign,
-                );
+        for child_descriptor in child_descriptors {
+            let child_graph = push_call_string_root(child_descriptor, gloc);
+
+            trace!("Child graph has generics {:?}", child_descriptor.generics);
+
+            // For each source node CHILD that is parentable to PLACE,
+            // add an edge from PLACE -> CHILD.
+            trace!("PARENT -> CHILD EDGES:");
+            for (child_src, _kind) in child_graph.parentable_srcs(is_root) {
+                if let Some(translation) = translator.translate_to_parent(child_src.place) {
+                    self.register_mutation(
+                        results,
+                        state,
+                        Inputs::Unresolved {
+                            places: vec![(translation, None)],
+                        },
+                        Either::Right(child_src),
+                        location,
+                        TargetUse::Assign,
+                    );
+                }
             }
-        }
 
-        // For each destination node CHILD that is parentable to PLACE,
-        // add an edge from CHILD -> PLACE.
-        //
-        // PRECISION TODO: for a given child place, we only want to connect
-        // the *last* nodes in the child function to the parent, not *all* of them.
-        trace!("CHILD -> PARENT EDGES:");
-        for (child_dst, kind) in child_graph.parentable_dsts(is_root) {
-            if let Some(parent_place) = translator.translate_to_parent(child_dst.place) {
-                self.register_mutation(
-                    results,
-                    state,
-                    Inputs::Resolved {
-                        node: child_dst,
-                        node_use: SourceUse::Operand,
-                    },
-                    Either::Left(parent_place),
-                    location,
-                    kind.map_or(TargetUse::Return, TargetUse::MutArg),
-                );
+            // For each destination node CHILD that is parentable to PLACE,
+            // add an edge from CHILD -> PLACE.
+            //
+            // PRECISION TODO: for a given child place, we only want to connect
+            // the *last* nodes in the child function to the parent, not *all* of them.
+            trace!("CHILD -> PARENT EDGES:");
+            for (child_dst, kind) in child_graph.parentable_dsts(is_root) {
+                if let Some(parent_place) = translator.translate_to_parent(child_dst.place) {
+                    self.register_mutation(
+                        results,
+                        state,
+                        Inputs::Resolved {
+                            node: child_dst,
+                            node_use: SourceUse::Operand,
+                        },
+                        Either::Left(parent_place),
+                        location,
+                        kind.map_or(TargetUse::Return, TargetUse::MutArg),
+                    );
+                }
             }
+            self.nodes.extend(child_graph.nodes);
+            self.edges.extend(child_graph.edges);
         }
-        self.nodes.extend(child_graph.nodes);
-        self.edges.extend(child_graph.edges);
-        true
+        !approximated
     }
 }
 
@@ -508,8 +722,10 @@ impl<'tcx> PartialGraph<'tcx> {
     }
 }
 
-/// How we are indexing into [`PdgCache`]
-pub type PdgCacheKey<'tcx> = Instance<'tcx>;
+/// How we are indexing into [`PdgCache`]: the instance and how many levels of
+/// calls may be inlined below it (`None` if unlimited, see
+/// [`MemoPdgConstructor::with_call_string_limit`]).
+pub type PdgCacheKey<'tcx> = (Instance<'tcx>, Option<usize>);
 /// Stores PDG's we have already computed and which we know we can use again
 /// given a certain key.
 pub type PdgCache<'tcx> = Rc<Cache<PdgCacheKey<'tcx>, PartialGraph<'tcx>>>;
@@ -0,0 +1,171 @@
+//! Closed-world resolution of calls through trait objects.
+//!
+//! A call to a method of `dyn Trait` cannot be resolved statically. If we
+//! assume however that the local crate is the entire program, then the only
+//! types that can hide behind such an object are those that are coerced to
+//! `dyn Trait` somewhere in the crate. [`DynImplementors`] collects these
+//! coercions so that a virtual call can be resolved to each candidate
+//! implementation.
+
+use flowistry::mir::FlowistryInput;
+use rustc_hash::{FxHashMap, FxHashSet};
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_middle::{
+    mir::{CastKind, Rvalue, StatementKind},
+    ty::{
+        adjustment::{Adjust, PointerCoercion},
+        GenericArg, Instance, List, ParamEnv, PolyExistentialPredicate, Ty, TyCtxt, TyKind,
+        TypeAndMut, TypeVisitableExt,
+    },
+};
+
+use crate::{body_cache::BodyCache, utils};
+
+/// Concrete types that are coerced to a trait object in the local crate,
+/// indexed by the principal trait of that object.
+pub(crate) struct DynImplementors<'tcx> {
+    by_trait: FxHashMap<DefId, Vec<Ty<'tcx>>>,
+}
+
+impl<'tcx> DynImplementors<'tcx> {
+    /// Scan the bodies of all local functions for unsizing coercions to a
+    /// trait object.
+    ///
+    /// Only bodies for which type checking recorded an unsizing coercion are
+    /// loaded. Functions without a cached body are skipped.
+    pub(crate) fn collect(tcx: TyCtxt<'tcx>, body_cache: &BodyCache<'tcx>) -> Self {
+        let mut by_trait: FxHashMap<DefId, Vec<Ty<'tcx>>> = FxHashMap::default();
+        for owner in tcx.hir().body_owners() {
+            if !tcx.def_kind(owner).is_fn_like() || !has_unsizing_coercion(tcx, owner) {
+                continue;
+            }
+            let Some(body) = body_cache.try_get(owner.to_def_id()) else {
+                continue;
+            };
+            let body = body.body();
+            for stmt in body.basic_blocks.iter().flat_map(|bb| bb.statements.iter()) {
+                let StatementKind::Assign(box (
+                    _,
+                    Rvalue::Cast(CastKind::PointerCoercion(PointerCoercion::Unsize), op, target),
+                )) = &stmt.kind
+                else {
+                    continue;
+                };
+                let Some((concrete, predicates)) = unsized_pair(op.ty(body, tcx), *target) else {
+                    continue;
+                };
+                // Generic sources cannot be resolved to an implementation
+                // without knowing the instantiation of the surrounding
+                // function, object-to-object casts add no new candidates.
+                if concrete.has_param() || concrete.is_trait() {
+                    continue;
+                }
+                let Some(principal) = predicates.principal_def_id() else {
+                    continue;
+                };
+                let concrete = tcx.erase_regions(concrete);
+                let known = by_trait.entry(principal).or_default();
+                if !known.contains(&concrete) {
+                    known.push(concrete);
+                }
+            }
+        }
+        Self { by_trait }
+    }
+
+    /// Resolve the trait object method `virtual_fn` for each concrete type
+    /// that was coerced to an object of that trait (or one of its subtraits).
+    ///
+    /// Types that do not implement the trait with the generic arguments of
+    /// this call are skipped. Returns `None` if no candidate is left.
+    pub(crate) fn candidates(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        param_env: ParamEnv<'tcx>,
+        virtual_fn: Instance<'tcx>,
+    ) -> Option<Vec<Instance<'tcx>>> {
+        let method = virtual_fn.def_id();
+        let trait_id = tcx.trait_of_item(method)?;
+        let (_self_ty, trait_args) = virtual_fn.args.split_first()?;
+        let mut seen = FxHashSet::default();
+        let candidates = self
+            .by_trait
+            .iter()
+            .filter(|(principal, _)| is_subtrait_of(tcx, **principal, trait_id))
+            .flat_map(|(_, types)| types.iter().copied())
+            .filter(|ty| seen.insert(*ty))
+            .filter_map(|concrete| {
+                let args = tcx.mk_args_from_iter(
+                    std::iter::once(GenericArg::from(concrete)).chain(trait_args.iter().copied()),
+                );
+                utils::try_resolve_function(tcx, method, param_env, args)
+            })
+            .filter(|instance| !utils::is_virtual(tcx, instance.def_id()))
+            .collect::<Vec<_>>();
+        (!candidates.is_empty()).then_some(candidates)
+    }
+}
+
+/// Whether type checking inserted an unsizing coercion anywhere in the body
+/// of `owner`. Closures share the results of their enclosing function, so
+/// this may also be true because of a coercion outside the closure.
+fn has_unsizing_coercion(tcx: TyCtxt<'_>, owner: LocalDefId) -> bool {
+    tcx.typeck(owner)
+        .adjustments()
+        .items()
+        .any(|(_, adjustments)| {
+            adjustments.iter().any(|adjustment| {
+                matches!(adjustment.kind, Adjust::Pointer(PointerCoercion::Unsize))
+            })
+        })
+}
+
+/// Find the concrete type and the trait object it is coerced to in an
+/// unsizing cast from `source` to `target`, looking through references, raw
+/// pointers and smart pointers such as `Box`.
+fn unsized_pair<'tcx>(
+    source: Ty<'tcx>,
+    target: Ty<'tcx>,
+) -> Option<(Ty<'tcx>, &'tcx List<PolyExistentialPredicate<'tcx>>)> {
+    match (source.kind(), target.kind()) {
+        (_, TyKind::Dynamic(predicates, ..)) => Some((source, predicates)),
+        (TyKind::Ref(_, source, _), TyKind::Ref(_, target, _))
+        | (TyKind::Ref(_, source, _), TyKind::RawPtr(TypeAndMut { ty: target, .. }))
+        | (
+            TyKind::RawPtr(TypeAndMut { ty: source, .. }),
+            TyKind::RawPtr(TypeAndMut { ty: target, .. }),
+        ) => unsized_pair(*source, *target),
+        (TyKind::Adt(source_def, source_args), TyKind::Adt(target_def, target_args))
+            if source_def == target_def =>
+        {
+            let (source, target) = source_args
+                .types()
+                .zip(target_args.types())
+                .find(|(source, target)| source != target)?;
+            unsized_pair(source, target)
+        }
+        _ => None,
+    }
+}
+
+/// Whether `sub` is `sup` or (transitively) has `sup` as a supertrait.
+fn is_subtrait_of(tcx: TyCtxt<'_>, sub: DefId, sup: DefId) -> bool {
+    let mut seen = FxHashSet::default();
+    let mut queue = vec![sub];
+    while let Some(current) = queue.pop() {
+        if current == sup {
+            return true;
+        }
+        if !seen.insert(current) {
+            continue;
+        }
+        queue.extend(
+            tcx.super_predicates_of(current)
+                .predicates
+                .iter()
+                .filter_map(|(clause, _)| clause.as_trait_clause())
+                .map(|trait_clause| trait_clause.def_id()),
+        );
+    }
+    false
+}
@@ -20,8 +20,6 @@
 //! Note that we encode `AllocId`s simply as themselves. This is possibly
 //! incorrect but we're not really relying on this information at the moment so
 //! we are not investing in it.
-use std::fs::File;
-use std::io::{self, Read};
 use std::path::Path;
 use std::{num::NonZeroU64, path::PathBuf};
 
@@ -68,17 +66,6 @@ impl<'tcx> ParalegalEncoder<'tcx> {
     }
 }
 
-/// Convenience function that encodes some value to a file.
-pub fn encode_to_file<'tcx, V: Encodable<ParalegalEncoder<'tcx>>>(
-    tcx: TyCtxt<'tcx>,
-    path: impl AsRef<Path>,
-    v: &V,
-) {
-    let mut encoder = ParalegalEncoder::new(path, tcx);
-    v.encode(&mut encoder);
-    encoder.finish();
-}
-
 /// Whatever can't survive the crossing we need to live without.
 const CLEAR_CROSS_CRATE: bool = true;
 
@@ -142,26 +129,20 @@ pub struct ParalegalDecoder<'tcx, 'a> {
 impl<'tcx, 'a> ParalegalDecoder<'tcx, 'a> {
     /// Decode what is in this buffer.
     pub fn new(tcx: TyCtxt<'tcx>, buf: &'a [u8]) -> Self {
+        Self::new_at(tcx, buf, 0)
+    }
+
+    /// Decode what is in this buffer, starting at `position`. Shorthands
+    /// refer to positions in the entire buffer.
+    pub fn new_at(tcx: TyCtxt<'tcx>, buf: &'a [u8], position: usize) -> Self {
         Self {
             tcx,
-            mem_decoder: MemDecoder::new(buf, 0),
+            mem_decoder: MemDecoder::new(buf, position),
             shorthand_map: Default::default(),
         }
     }
 }
 
-/// Convenience function that decodes a value from a file.
-pub fn decode_from_file<'tcx, V: for<'a> Decodable<ParalegalDecoder<'tcx, 'a>>>(
-    tcx: TyCtxt<'tcx>,
-    path: impl AsRef<Path>,
-) -> io::Result<V> {
-    let mut file = File::open(path)?;
-    let mut buf = Vec::new();
-    file.read_to_end(&mut buf)?;
-    let mut decoder = ParalegalDecoder::new(tcx, buf.as_slice());
-    Ok(V::decode(&mut decoder))
-}
-
 impl<'tcx, 'a> TyDecoder for ParalegalDecoder<'tcx, 'a> {
     const CLEAR_CROSS_CRATE: bool = CLEAR_CROSS_CRATE;
 
@@ -0,0 +1,207 @@
+//! Resolution of calls through function pointers.
+//!
+//! This is a flow-insensitive analysis of a single body that tracks which
+//! functions are turned into function pointers and where these pointers may
+//! end up. Locals that are connected through assignments, references,
+//! aggregates or function calls are merged into one class. As a result a
+//! pointer that is stored in a struct or a table and later read back out
+//! resolves to all functions that were stored in it.
+//!
+//! A class is unknown if a function pointer may enter it from outside the
+//! body, e.g. through an argument, a constant or static, or as the return value
+//! of a local function that itself does not receive any function pointers.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use rustc_hir::def_id::DefId;
+use rustc_index::IndexVec;
+use rustc_middle::{
+    mir::{Body, CastKind, Local, Operand, Place, Rvalue, StatementKind, TerminatorKind},
+    ty::{adjustment::PointerCoercion, GenericArgsRef, PolyFnSig, Ty, TyCtxt, TyKind},
+};
+
+/// The functions that may reach each local of a body as function pointers.
+pub(crate) struct FnPointerTargets<'tcx> {
+    /// Union-find forest over the locals of the body.
+    parents: IndexVec<Local, Local>,
+    /// Reified functions, indexed by the representative of their class.
+    targets: FxHashMap<Local, Vec<(DefId, GenericArgsRef<'tcx>)>>,
+    /// Representatives of the classes that may contain pointers of unknown
+    /// origin.
+    unknown: FxHashSet<Local>,
+}
+
+impl<'tcx> FnPointerTargets<'tcx> {
+    pub(crate) fn compute(tcx: TyCtxt<'tcx>, body: &Body<'tcx>) -> Self {
+        let mut this = Self {
+            parents: body.local_decls.indices().collect(),
+            targets: Default::default(),
+            unknown: Default::default(),
+        };
+        let mut sources = vec![];
+        let mut unknown = body.args_iter().collect::<Vec<_>>();
+
+        for block in body.basic_blocks.iter() {
+            for statement in &block.statements {
+                let StatementKind::Assign(box (place, rvalue)) = &statement.kind else {
+                    continue;
+                };
+                match rvalue {
+                    Rvalue::Cast(
+                        CastKind::PointerCoercion(PointerCoercion::ReifyFnPointer),
+                        op,
+                        _,
+                    ) => match op.ty(body, tcx).kind() {
+                        TyKind::FnDef(def_id, args) => {
+                            sources.push((place.local, (*def_id, *args)))
+                        }
+                        _ => unknown.push(place.local),
+                    },
+                    // The closure would have to be called with its environment,
+                    // which we do not model here.
+                    Rvalue::Cast(
+                        CastKind::PointerCoercion(PointerCoercion::ClosureFnPointer(_)),
+                        ..,
+                    ) => unknown.push(place.local),
+                    _ => {
+                        for read in rvalue_reads(rvalue) {
+                            match read {
+                                Operand::Copy(read) | Operand::Move(read) => {
+                                    this.union(place.local, read.local)
+                                }
+                                Operand::Constant(constant) => {
+                                    if contains_fn_ptr(tcx, constant.ty()) {
+                                        unknown.push(place.local)
+                                    }
+                                }
+                            }
+                        }
+                        if let Rvalue::Ref(_, _, read)
+                        | Rvalue::AddressOf(_, read)
+                        | Rvalue::CopyForDeref(read) = rvalue
+                        {
+                            this.union(place.local, read.local)
+                        }
+                    }
+                }
+            }
+            if let TerminatorKind::Call {
+                func,
+                args,
+                destination,
+                ..
+            } = &block.terminator().kind
+            {
+                let mut receives_fn_ptr = false;
+                for arg in args {
+                    receives_fn_ptr |= contains_fn_ptr(tcx, arg.ty(body, tcx));
+                    match arg {
+                        Operand::Copy(arg) | Operand::Move(arg) => {
+                            this.union(destination.local, arg.local)
+                        }
+                        Operand::Constant(constant) => {
+                            if contains_fn_ptr(tcx, constant.ty()) {
+                                unknown.push(destination.local)
+                            }
+                        }
+                    }
+                }
+                // Functions outside this crate cannot name our functions, so
+                // any pointer they return must have been passed to them.
+                let may_create_fn_ptr = func
+                    .const_fn_def()
+                    .map_or(true, |(def_id, _)| def_id.is_local());
+                if may_create_fn_ptr
+                    && !receives_fn_ptr
+                    && contains_fn_ptr(tcx, destination.ty(body, tcx).ty)
+                {
+                    unknown.push(destination.local)
+                }
+            }
+        }
+
+        for (local, target) in sources {
+            let root = this.find(local);
+            let targets = this.targets.entry(root).or_default();
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        for local in unknown {
+            let root = this.find(local);
+            this.unknown.insert(root);
+        }
+        this
+    }
+
+    /// The functions the pointer `place` may point to that have signature
+    /// `sig`. Returns `None` if the set of functions is not known.
+    pub(crate) fn resolve(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        place: Place<'tcx>,
+        sig: PolyFnSig<'tcx>,
+    ) -> Option<Vec<(DefId, GenericArgsRef<'tcx>)>> {
+        let root = self.find(place.local);
+        if self.unknown.contains(&root) {
+            return None;
+        }
+        let targets = self
+            .targets
+            .get(&root)?
+            .iter()
+            .copied()
+            .filter(|(def_id, args)| {
+                same_signature(tcx, tcx.fn_sig(*def_id).instantiate(tcx, args), sig)
+            })
+            .collect::<Vec<_>>();
+        (!targets.is_empty()).then_some(targets)
+    }
+
+    fn find(&self, mut local: Local) -> Local {
+        while self.parents[local] != local {
+            local = self.parents[local];
+        }
+        local
+    }
+
+    fn union(&mut self, a: Local, b: Local) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[b] = a;
+        }
+    }
+}
+
+/// The operands an rvalue reads from in a way that may copy a function
+/// pointer. Scalar operations are skipped as they cannot produce a pointer.
+fn rvalue_reads<'a, 'tcx>(rvalue: &'a Rvalue<'tcx>) -> Vec<&'a Operand<'tcx>> {
+    match rvalue {
+        Rvalue::Use(op) | Rvalue::Repeat(op, _) | Rvalue::Cast(_, op, _) => vec![op],
+        Rvalue::Aggregate(_, ops) => ops.iter().collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether a value of this type may hold a function pointer, looking through
+/// generic arguments and the fields of ADTs.
+fn contains_fn_ptr<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> bool {
+    let mut seen = FxHashSet::default();
+    let mut queue = vec![ty];
+    while let Some(ty) = queue.pop() {
+        for ty in ty.walk().filter_map(|arg| arg.as_type()) {
+            match ty.kind() {
+                TyKind::FnPtr(_) => return true,
+                TyKind::Adt(def, args) if seen.insert(ty) => {
+                    queue.extend(def.all_fields().map(|field| field.ty(tcx, args)))
+                }
+                _ => (),
+            }
+        }
+    }
+    false
+}
+
+fn same_signature<'tcx>(tcx: TyCtxt<'tcx>, a: PolyFnSig<'tcx>, b: PolyFnSig<'tcx>) -> bool {
+    let erase = |sig| tcx.erase_regions(tcx.erase_late_bound_regions(sig));
+    erase(a).inputs_and_output == erase(b).inputs_and_output
+}
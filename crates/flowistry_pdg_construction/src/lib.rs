@@ -19,12 +19,16 @@ extern crate rustc_target;
 extern crate rustc_type_ir;
 
 use self::graph::DepGraph;
-pub use async_support::{determine_async, is_async_trait_fn, match_async_trait_assign};
+pub use async_support::{
+    async_fn_of_generator, determine_async, is_async_wrapper_fn, match_async_trait_assign,
+    AsyncType,
+};
 use rustc_hir::def_id::LocalDefId;
 pub mod callback;
 pub use crate::construct::MemoPdgConstructor;
 pub use callback::{
-    CallChangeCallback, CallChangeCallbackFn, CallChanges, CallInfo, InlineMissReason, SkipCall,
+    CallChangeCallback, CallChangeCallbackFn, CallChanges, CallInfo, CallSummary, InlineMissReason,
+    InteriorMutability, SkipCall, UnsafeOperation,
 };
 use rustc_middle::ty::TyCtxt;
 
@@ -32,11 +36,15 @@ mod approximation;
 mod async_support;
 pub mod body_cache;
 pub mod calling_convention;
+mod conservative;
 mod construct;
+mod dyn_dispatch;
 pub mod encoder;
+mod fn_pointers;
 pub mod graph;
 mod local_analysis;
 mod mutation;
+mod recursion;
 pub mod utils;
 
 /// Computes a global program dependence graph (PDG) starting from the root function specified by `def_id`.
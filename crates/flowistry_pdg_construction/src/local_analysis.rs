@@ -1,4 +1,10 @@
-use std::{borrow::Cow, collections::HashSet, iter, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::{OnceCell, RefCell},
+    collections::HashSet,
+    iter,
+    rc::Rc,
+};
 
 use flowistry::mir::{placeinfo::PlaceInfo, FlowistryInput};
 use flowistry_pdg::{CallString, GlobalLocation, RichLocation};
@@ -11,10 +17,11 @@ use rustc_hir::def_id::DefId;
 use rustc_index::IndexVec;
 use rustc_middle::{
     mir::{
-        visit::Visitor, AggregateKind, BasicBlock, Body, HasLocalDecls, Location, Operand, Place,
-        PlaceElem, Rvalue, Statement, Terminator, TerminatorEdges, TerminatorKind, RETURN_PLACE,
+        visit::Visitor, AggregateKind, BasicBlock, Body, HasLocalDecls, Local, Location,
+        Mutability, Operand, Place, PlaceElem, Rvalue, Statement, StatementKind, Terminator,
+        TerminatorEdges, TerminatorKind, RETURN_PLACE,
     },
-    ty::{GenericArgKind, GenericArgsRef, Instance, InstanceDef, TyCtxt, TyKind},
+    ty::{GenericArgKind, GenericArgsRef, Instance, InstanceDef, ParamEnv, Ty, TyCtxt, TyKind},
 };
 use rustc_mir_dataflow::{self as df, fmt::DebugWithContext, Analysis};
 use rustc_span::Span;
@@ -25,10 +32,13 @@ use crate::{
     async_support::*,
     body_cache::CachedBody,
     calling_convention::*,
+    conservative::UnsafeOperations,
+    fn_pointers::FnPointerTargets,
     graph::{DepEdge, DepNode, PartialGraph, SourceUse, TargetUse},
     mutation::{ModularMutationVisitor, Mutation, Time},
     utils::{self, is_async, is_virtual, try_monomorphize, type_as_fn},
-    CallChangeCallback, CallChanges, CallInfo, InlineMissReason, MemoPdgConstructor, SkipCall,
+    CallChangeCallback, CallChanges, CallInfo, CallSummary, InlineMissReason, InteriorMutability,
+    MemoPdgConstructor, SkipCall,
 };
 
 #[derive(PartialEq, Eq, Default, Clone, Debug)]
@@ -58,6 +68,24 @@ pub(crate) struct LocalAnalysis<'tcx, 'a> {
     control_dependencies: ControlDependencies<BasicBlock>,
     pub(crate) body_assignments: utils::BodyAssignments,
     start_loc: FxHashSet<RichLocation>,
+    fn_pointer_targets: OnceCell<FnPointerTargets<'tcx>>,
+    /// Calls through function pointers with unknown targets that were already
+    /// reported. Call handling is determined more than once per location.
+    unknown_fn_pointer_calls: RefCell<FxHashSet<Location>>,
+    /// How many more levels of calls may be inlined below this body, `None`
+    /// if unlimited.
+    inline_budget: Option<usize>,
+    /// Summarize all calls instead of inlining them. Used to compute the
+    /// summary of this body, see [`MemoPdgConstructor::recursive_summary`].
+    summarizing: bool,
+    /// The operations that are treated conservatively, `None` unless
+    /// [`MemoPdgConstructor::with_conservative_unsafe`] is set.
+    unsafe_operations: Option<UnsafeOperations>,
+    /// The places in this body of each pointee type of an opaque pointer.
+    compatible_places: RefCell<FxHashMap<Ty<'tcx>, Vec<Place<'tcx>>>>,
+    /// Interior mutability guards, see [`Self::find_guards`]. Set when
+    /// construction starts.
+    guards: OnceCell<FxHashMap<Local, Place<'tcx>>>,
 }
 
 impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
@@ -68,6 +96,8 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
     pub(crate) fn new(
         memo: &'a MemoPdgConstructor<'tcx>,
         root: Instance<'tcx>,
+        inline_budget: Option<usize>,
+        summarizing: bool,
     ) -> LocalAnalysis<'tcx, 'a> {
         let tcx = memo.tcx;
         let def_id = root.def_id();
@@ -97,6 +127,9 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         start_loc.insert(RichLocation::Start);
 
         let body_assignments = utils::find_body_assignments(&body);
+        let unsafe_operations = memo
+            .conservative_unsafe
+            .then(|| UnsafeOperations::collect(tcx, &body));
 
         LocalAnalysis {
             memo,
@@ -108,9 +141,21 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
             start_loc,
             def_id,
             body_assignments,
+            fn_pointer_targets: OnceCell::new(),
+            unknown_fn_pointer_calls: Default::default(),
+            inline_budget,
+            summarizing,
+            unsafe_operations,
+            compatible_places: Default::default(),
+            guards: OnceCell::new(),
         }
     }
 
+    /// The inlining budget of the functions called from this body.
+    fn callee_budget(&self) -> Option<usize> {
+        self.inline_budget.map(|budget| budget.saturating_sub(1))
+    }
+
     fn make_dep_node(
         &self,
         place: Place<'tcx>,
@@ -162,10 +207,21 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         self.memo.call_change_callback.as_ref().map(Rc::as_ref)
     }
 
+    /// The callback to report approximations to. While summarizing the body
+    /// is also visited (and reported) by the regular construction.
+    fn approximation_callback(&self) -> Option<&dyn CallChangeCallback<'tcx>> {
+        self.call_change_callback().filter(|_| !self.summarizing)
+    }
+
     pub(crate) fn async_info(&self) -> &AsyncInfo {
         &self.memo.async_info
     }
 
+    fn fn_pointer_targets(&self) -> &FnPointerTargets<'tcx> {
+        self.fn_pointer_targets
+            .get_or_init(|| FnPointerTargets::compute(self.tcx(), &self.mono_body))
+    }
+
     pub(crate) fn make_call_string(&self, location: impl Into<RichLocation>) -> CallString {
         CallString::single(GlobalLocation {
             function: self.def_id,
@@ -174,7 +230,29 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
     }
 
     /// Returns the aliases of `place`. See [`PlaceInfo::aliases`] for details.
+    ///
+    /// If an alias is an interior mutability guard (see [`Self::find_guards`])
+    /// the aliases of the guarded place are included. In conservative mode
+    /// dereferences of opaque pointers alias every place of the pointee type.
     pub(crate) fn aliases(&'a self, place: Place<'tcx>) -> impl Iterator<Item = Place<'tcx>> + 'a {
+        let mut aliases = self.flowistry_aliases(place).collect::<Vec<_>>();
+        if let Some(guards) = self.guards.get() {
+            let guarded = aliases
+                .iter()
+                .filter_map(|alias| guards.get(&alias.local))
+                .copied()
+                .collect::<Vec<_>>();
+            aliases.extend(
+                guarded
+                    .into_iter()
+                    .flat_map(|guarded| self.flowistry_aliases(guarded)),
+            );
+        }
+        aliases.extend(self.conservative_aliases(place));
+        aliases.into_iter()
+    }
+
+    fn flowistry_aliases(&'a self, place: Place<'tcx>) -> impl Iterator<Item = Place<'tcx>> + 'a {
         // MASSIVE HACK ALERT:
         // The issue is that monomorphization erases regions, due to how it's implemented in rustc.
         // However, Flowistry's alias analysis uses regions to figure out aliases.
@@ -187,8 +265,13 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         // Then we reproject the aliases with the remaining projection, to create {_1.0}.
         //
         // This is a massive hack bc it's inefficient and I'm not certain that it's sound.
-        let place_retyped =
-            utils::retype_place(place, self.tcx(), self.body_with_facts.body(), self.def_id);
+        let place_retyped = utils::retype_place(
+            place,
+            self.tcx(),
+            self.body_with_facts.body(),
+            self.def_id,
+            None,
+        );
         self.place_info
             .aliases(place_retyped)
             .iter()
@@ -208,6 +291,136 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
             })
     }
 
+    /// Find the locals that hold guards returned by calls modeled with
+    /// [`InteriorMutability::Guard`], together with the places they give
+    /// access to. Guards are followed through moves and through calls that
+    /// consume them, such as `Result::unwrap`.
+    fn find_guards(&self) -> FxHashMap<Local, Place<'tcx>> {
+        let tcx = self.tcx();
+        let body = &self.mono_body;
+        let mut guards = FxHashMap::default();
+        let Some(callback) = self.call_change_callback() else {
+            return guards;
+        };
+        let param_env = tcx.param_env(self.def_id);
+        for data in body.basic_blocks.iter() {
+            let terminator = data.terminator();
+            let TerminatorKind::Call {
+                func,
+                args,
+                destination,
+                ..
+            } = &terminator.kind
+            else {
+                continue;
+            };
+            let Some(callee) = self.operand_to_def_id(func).and_then(|(def_id, args)| {
+                utils::try_resolve_function(tcx, def_id, param_env, args)
+            }) else {
+                continue;
+            };
+            if let (
+                Some(InteriorMutability::Guard),
+                Some(Operand::Copy(guarded) | Operand::Move(guarded)),
+            ) = (
+                callback.interior_mutability(callee, terminator.source_info.span),
+                args.first(),
+            ) {
+                guards.insert(
+                    destination.local,
+                    guarded.project_deeper(&[PlaceElem::Deref], tcx),
+                );
+            }
+        }
+        if guards.is_empty() {
+            return guards;
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let mut derive = |from: &Place<'tcx>, to: &Place<'tcx>| {
+                if let Some(guarded) = guards.get(&from.local).copied() {
+                    if !guards.contains_key(&to.local) {
+                        guards.insert(to.local, guarded);
+                        changed = true;
+                    }
+                }
+            };
+            for data in body.basic_blocks.iter() {
+                for statement in &data.statements {
+                    if let StatementKind::Assign(box (
+                        to,
+                        Rvalue::Use(Operand::Copy(from) | Operand::Move(from)),
+                    )) = &statement.kind
+                    {
+                        derive(from, to);
+                    }
+                }
+                if let TerminatorKind::Call {
+                    args, destination, ..
+                } = &data.terminator().kind
+                {
+                    for arg in args {
+                        if let Operand::Move(from) = arg {
+                            derive(from, destination);
+                        }
+                    }
+                }
+            }
+        }
+        guards
+    }
+
+    /// If `place` dereferences a pointer whose pointees are unknown, every
+    /// place in this body with the type of the pointee.
+    fn conservative_aliases(&self, place: Place<'tcx>) -> Vec<Place<'tcx>> {
+        let tcx = self.tcx();
+        let Some((prefix, pointee)) = self
+            .unsafe_operations
+            .as_ref()
+            .and_then(|ops| ops.opaque_deref(tcx, &self.mono_body, place))
+        else {
+            return vec![];
+        };
+        let pointee = tcx.erase_regions(pointee);
+        let rest = &place.projection[prefix..];
+        let mut compatible_places = self.compatible_places.borrow_mut();
+        let candidates = compatible_places.entry(pointee).or_insert_with(|| {
+            self.mono_body
+                .local_decls
+                .indices()
+                .flat_map(|local| {
+                    let place = Place::from(local);
+                    iter::once(place)
+                        .chain(
+                            self.place_info
+                                .reachable_values(place, Mutability::Not)
+                                .iter()
+                                .copied(),
+                        )
+                        .flat_map(|place| self.place_info.children(place).iter().copied())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|candidate| {
+                    tcx.erase_regions(candidate.ty(&self.mono_body, tcx).ty) == pointee
+                })
+                .unique()
+                .collect()
+        });
+        candidates
+            .iter()
+            .map(|candidate| candidate.project_deeper(rest, tcx))
+            .collect()
+    }
+
+    /// Whether `place` dereferences a pointer whose pointees are unknown.
+    fn is_opaque_deref(&self, place: Place<'tcx>) -> bool {
+        self.unsafe_operations.as_ref().is_some_and(|ops| {
+            ops.opaque_deref(self.tcx(), &self.mono_body, place)
+                .is_some()
+        })
+    }
+
     pub(crate) fn tcx(&self) -> TyCtxt<'tcx> {
         self.memo.tcx
     }
@@ -324,6 +537,9 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         location: Location,
         mutated: Place<'tcx>,
     ) {
+        // Writes through an opaque pointer may target any of its candidates,
+        // so none of them is definitely overwritten.
+        let weak = self.is_opaque_deref(mutated);
         self.find_outputs(mutated, location)
             .into_iter()
             .for_each(|(dst, _)| {
@@ -331,7 +547,9 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
 
                 // Clear all previous mutations.
                 let dst_mutations = state.last_mutation.entry(dst).or_default();
-                dst_mutations.clear();
+                if !weak {
+                    dst_mutations.clear();
+                }
 
                 // Register that `dst` is mutated at the current location.
                 dst_mutations.insert(RichLocation::Location(location));
@@ -366,6 +584,16 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         );
 
         let Some((called_def_id, generic_args)) = self.operand_to_def_id(&func) else {
+            if func.ty(&self.mono_body, tcx).is_fn_ptr() {
+                let descriptors = self.dispatch_fn_pointer(location, &func, &args, span)?;
+                trace!("  Dispatching to {} candidates", descriptors.len());
+                return Some(CallHandling::Dispatched {
+                    calling_convention: CallingConvention::Direct(args.into()),
+                    descriptors,
+                    precise: true,
+                    approximated: false,
+                });
+            }
             tcx.sess
                 .span_err(span, "Operand is cannot be interpreted as function");
             return None;
@@ -432,7 +660,21 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
             resolved_fn = Instance::expect_resolve(tcx, param_env, func_t, g);
             CallKind::Indirect { once_shim: true }
         } else {
-            self.classify_call_kind(called_def_id, resolved_fn, &args, span)
+            let (polled_fn, call_kind) =
+                self.classify_call_kind(called_def_id, resolved_fn, &args, span);
+            if polled_fn != resolved_fn {
+                // A boxed future is polled, we inline the generator it wraps
+                // instead. Resolving call strings needs to know about this.
+                self.memo.record_polled_generator(
+                    GlobalLocation {
+                        function: self.def_id,
+                        location: RichLocation::Location(location),
+                    },
+                    polled_fn,
+                );
+                resolved_fn = polled_fn;
+            }
+            call_kind
         };
         let resolved_def_id = resolved_fn.def_id();
         if log_enabled!(Level::Trace) && called_def_id != resolved_def_id {
@@ -458,8 +700,25 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
             }
         );
 
+        // Checked before consulting the callback, so that it never sees a call
+        // that is not going to be inlined anyway.
+        if self.inline_budget == Some(0) {
+            trace!("  Bailing because the call string limit is reached");
+            if let Some(callback) = self.approximation_callback() {
+                callback.on_inline_miss(
+                    resolved_fn,
+                    param_env,
+                    location,
+                    self.root,
+                    InlineMissReason::CallStringLimit,
+                    span,
+                );
+            }
+            return None;
+        }
+
         // Recursively generate the PDG for the child function.
-        let cache_key = resolved_fn;
+        let cache_key = (resolved_fn, self.callee_budget());
 
         let is_cached = self.memo.is_in_cache(cache_key);
 
@@ -467,6 +726,7 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
             let info = CallInfo {
                 callee: resolved_fn,
                 call_string: self.make_call_string(location),
+                caller: self.root,
                 is_cached,
                 async_parent: if let CallKind::AsyncPoll(poll) = &call_kind {
                     // Special case for async. We ask for skipping not on the closure, but
@@ -485,10 +745,51 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
                 arguments: &args,
                 caller_body: &self.mono_body,
                 param_env,
+                fn_pointer_targets: self.fn_pointer_targets(),
             };
-            callback.on_inline(info)
+            self.memo
+                .call_changes((self.root, self.inline_budget), location, || {
+                    callback.on_inline(info)
+                })
         });
 
+        let call_changes = match call_changes {
+            Some(CallChanges {
+                skip: SkipCall::Summary(summary),
+            }) => {
+                trace!("  Using call summary provided by user");
+                return Some(CallHandling::Summarized(summary));
+            }
+            Some(CallChanges {
+                skip: SkipCall::InteriorMutability(model),
+            }) => {
+                trace!("  Using interior mutability model {model:?}");
+                return Some(CallHandling::InteriorMutability(model));
+            }
+            other => other,
+        };
+
+        // A stub for a foreign function takes precedence over the
+        // conservative approximation.
+        let replaced = matches!(
+            &call_changes,
+            Some(CallChanges {
+                skip: SkipCall::Replace { .. }
+            })
+        );
+        if let Some(operation) = self
+            .unsafe_operations
+            .as_ref()
+            .filter(|_| !replaced)
+            .and_then(|ops| ops.opaque_call(location))
+        {
+            trace!("  Approximating opaque call conservatively");
+            if let Some(callback) = self.approximation_callback() {
+                callback.on_unsafe_operation(self.root, location, operation, span);
+            }
+            return Some(CallHandling::Conservative);
+        }
+
         // Handle async functions at the time of polling, not when the future is created.
         if is_async(tcx, resolved_def_id) {
             trace!("  Bailing because func is async");
@@ -526,9 +827,32 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
             }
             _ => (CallingConvention::from_call_kind(&call_kind, args), true),
         };
+        // Summaries refer to the arguments of the callee, which are the
+        // arguments at the call site only for direct calls.
+        let summarizable = precise && matches!(calling_convention, CallingConvention::Direct(_));
         if is_virtual(tcx, resolved_def_id) {
+            if self.summarizing {
+                return summarizable
+                    .then(|| self.summarize_dyn_call(resolved_fn, param_env))
+                    .flatten();
+            }
+            if let Some((descriptors, approximated)) = self.dispatch_closed_world(
+                resolved_fn,
+                param_env,
+                location,
+                span,
+                &calling_convention,
+            ) {
+                trace!("  Dispatching to {} candidates", descriptors.len());
+                return Some(CallHandling::Dispatched {
+                    calling_convention,
+                    descriptors,
+                    precise: false,
+                    approximated,
+                });
+            }
             trace!("  bailing because is unresolvable trait method");
-            if let Some(callback) = self.call_change_callback() {
+            if let Some(callback) = self.approximation_callback() {
                 callback.on_inline_miss(
                     resolved_fn,
                     param_env,
@@ -540,9 +864,19 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
             }
             return None;
         }
-        let Some(descriptor) = self.memo.construct_for(resolved_fn) else {
-            trace!("  Bailing because of recursion.");
-            return None;
+        if self.summarizing {
+            return summarizable
+                .then(|| CallHandling::Summarized(self.memo.recursive_summary(cache_key)));
+        }
+        let Some(descriptor) = self.memo.construct_for(resolved_fn, cache_key.1) else {
+            if !summarizable {
+                trace!("  Bailing because of recursion.");
+                return None;
+            }
+            trace!("  Summarizing recursive call");
+            return Some(CallHandling::Summarized(
+                self.memo.recursive_summary(cache_key),
+            ));
         };
 
         Some(CallHandling::Ready {
@@ -552,6 +886,147 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         })
     }
 
+    /// The joined summaries of all candidate implementations of a call
+    /// through a trait object. `None` if the call cannot be dispatched.
+    fn summarize_dyn_call(
+        &self,
+        virtual_fn: Instance<'tcx>,
+        param_env: ParamEnv<'tcx>,
+    ) -> Option<CallHandling<'tcx, 'a>> {
+        let candidates = self.memo.resolve_dyn_call(virtual_fn, param_env)?;
+        let mut summary = CallSummary::default();
+        for candidate in candidates {
+            summary.join(
+                &self
+                    .memo
+                    .recursive_summary((candidate, self.callee_budget())),
+            );
+        }
+        Some(CallHandling::Summarized(summary))
+    }
+
+    /// Resolve a call through a trait object to the graphs of all candidate
+    /// implementations (see [`MemoPdgConstructor::with_closed_world_dyn_dispatch`]).
+    ///
+    /// Also returns whether some candidates are not inlined. Their effects are
+    /// approximated in addition to the inlined graphs. Returns `None` if there
+    /// are no candidates or none of them can be inlined, in which case the
+    /// entire call is approximated.
+    fn dispatch_closed_world<'b>(
+        &'b self,
+        virtual_fn: Instance<'tcx>,
+        param_env: ParamEnv<'tcx>,
+        location: Location,
+        span: Span,
+        calling_convention: &CallingConvention<'tcx>,
+    ) -> Option<(Vec<&'b PartialGraph<'tcx>>, bool)> {
+        // Replaced calls or closure calls are never dispatched.
+        let CallingConvention::Direct(args) = calling_convention else {
+            return None;
+        };
+        let candidates = self.memo.resolve_dyn_call(virtual_fn, param_env)?;
+        let descriptors = candidates
+            .iter()
+            .filter_map(|&candidate| {
+                if let Some(callback) = self.call_change_callback() {
+                    let changes = callback.on_inline(CallInfo {
+                        callee: candidate,
+                        call_string: self.make_call_string(location),
+                        caller: self.root,
+                        is_cached: self.memo.is_in_cache((candidate, self.callee_budget())),
+                        async_parent: None,
+                        span,
+                        arguments: args,
+                        caller_body: &self.mono_body,
+                        param_env,
+                        fn_pointer_targets: self.fn_pointer_targets(),
+                    });
+                    if !matches!(changes.skip, SkipCall::NoSkip) {
+                        trace!("  Callback refused candidate {candidate:?}");
+                        return None;
+                    }
+                }
+                self.memo.construct_for(candidate, self.callee_budget())
+            })
+            .collect::<Vec<_>>();
+        if descriptors.is_empty() {
+            return None;
+        }
+        let approximated = descriptors.len() < candidates.len();
+        self.memo.record_call_candidates(
+            GlobalLocation {
+                function: self.def_id,
+                location: RichLocation::Location(location),
+            },
+            candidates.iter().map(Instance::def_id),
+        );
+        Some((descriptors, approximated))
+    }
+
+    /// Resolve a call through a function pointer to the graphs of all
+    /// functions that may reach the pointer in this body (see
+    /// [`FnPointerTargets`]).
+    ///
+    /// Returns `None` if the set of functions is not known or if any of them
+    /// is not inlined, in which case the entire call is approximated. An
+    /// unknown set is reported to the callback with
+    /// [`CallChangeCallback::on_unknown_fn_pointer_call`]. For a candidate that is not
+    /// inlined the callback will already have checked that approximating it
+    /// is safe.
+    fn dispatch_fn_pointer<'b>(
+        &'b self,
+        location: Location,
+        func: &Operand<'tcx>,
+        args: &Cow<'b, [Operand<'tcx>]>,
+        span: Span,
+    ) -> Option<Vec<&'b PartialGraph<'tcx>>> {
+        let tcx = self.tcx();
+        let pointer_ty = func.ty(&self.mono_body, tcx);
+        let Some(candidates) = func.place().and_then(|pointer| {
+            self.fn_pointer_targets()
+                .resolve(tcx, pointer, pointer_ty.fn_sig(tcx))
+        }) else {
+            if self.unknown_fn_pointer_calls.borrow_mut().insert(location) {
+                tcx.sess.span_warn(
+                    span,
+                    format!(
+                        "unable to determine the functions this call through `{pointer_ty}` may \
+                         target, as the pointer may originate outside of `{}`. The call is \
+                         approximated.",
+                        tcx.def_path_str(self.def_id)
+                    ),
+                );
+                if let Some(callback) = self.approximation_callback() {
+                    callback.on_unknown_fn_pointer_call(self.root, location, span);
+                }
+            }
+            return None;
+        };
+        let mut descriptors = Vec::with_capacity(candidates.len());
+        for (def_id, generic_args) in candidates.iter().copied() {
+            let target = Operand::function_handle(tcx, def_id, generic_args.iter(), span);
+            match self.determine_call_handling(location, Cow::Owned(target), args.clone(), span) {
+                Some(CallHandling::Ready {
+                    descriptor,
+                    precise: true,
+                    calling_convention: CallingConvention::Direct(_),
+                }) => descriptors.push(descriptor),
+                _ => {
+                    trace!("  Approximating because candidate {def_id:?} is not inlined");
+                    return None;
+                }
+            }
+        }
+        self.memo.record_call_candidates(
+            GlobalLocation {
+                function: self.def_id,
+                location: RichLocation::Location(location),
+            },
+            candidates.iter().map(|(def_id, _)| *def_id),
+        );
+        Some(descriptors)
+    }
+
     /// Attempt to inline a call to a function.
     ///
     /// The return indicates whether we were successfully able to perform the inlining.
@@ -575,12 +1050,18 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
 
         trace!("Call handling is {}", preamble.as_ref());
 
-        let (child_constructor, calling_convention, precise) = match preamble {
+        let (child_constructors, calling_convention, precise, approximated) = match preamble {
             CallHandling::Ready {
                 descriptor,
                 calling_convention,
                 precise,
-            } => (descriptor, calling_convention, precise),
+            } => (vec![descriptor], calling_convention, precise, false),
+            CallHandling::Dispatched {
+                descriptors,
+                calling_convention,
+                precise,
+                approximated,
+            } => (descriptors, calling_convention, precise, approximated),
             CallHandling::ApproxAsyncFn => {
                 // Register a synthetic assignment of `future = (arg0, arg1, ...)`.
                 let rvalue = Rvalue::Aggregate(
@@ -601,9 +1082,27 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
                 );
                 return true;
             }
+            CallHandling::Summarized(summary) => {
+                self.modular_mutation_visitor(state).visit_summarized_call(
+                    args,
+                    destination,
+                    location,
+                    &summary,
+                );
+                return true;
+            }
+            CallHandling::InteriorMutability(model) => {
+                self.modular_mutation_visitor(state)
+                    .visit_interior_mutability_call(args, destination, location, model);
+                return true;
+            }
+            CallHandling::Conservative => {
+                self.modular_mutation_visitor(state)
+                    .visit_conservative_call(args, destination, location);
+                return true;
+            }
         };
 
-        let parentable_dsts = child_constructor.parentable_dsts(|n| n.len() == 1);
         let parent_body = &self.mono_body;
 
         let place_translator = PlaceTranslator::new(
@@ -622,13 +1121,17 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         // PRECISION TODO: for a given child place, we only want to connect
         // the *last* nodes in the child function to the parent, not *all* of them.
         trace!("CHILD -> PARENT EDGES:");
-        for (child_dst, _) in parentable_dsts {
+        for (child_dst, _) in child_constructors
+            .iter()
+            .flat_map(|child| child.parentable_dsts(|n| n.len() == 1))
+        {
             if let Some(parent_place) = place_translator.translate_to_parent(child_dst.place) {
                 self.apply_mutation(state, location, parent_place);
             }
         }
 
-        true
+        // The candidates that are not inlined are handled by the fallback
+        !approximated
     }
 
     fn modular_mutation_visitor<'b: 'a>(
@@ -648,6 +1151,22 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
     }
 
     pub(crate) fn construct_partial(&'a self) -> PartialGraph<'tcx> {
+        let _ = self.guards.set(self.find_guards());
+        if let (Some(ops), Some(callback)) =
+            (&self.unsafe_operations, self.approximation_callback())
+        {
+            // Opaque calls are reported when their handling is determined,
+            // unless they are replaced by a stub.
+            for (location, operation) in ops
+                .locations
+                .iter()
+                .filter(|(location, _)| ops.opaque_call(*location).is_none())
+            {
+                let span = self.mono_body.source_info(*location).span;
+                callback.on_unsafe_operation(self.root, *location, *operation, span);
+            }
+        }
+
         let mut analysis = self
             .into_engine(self.tcx(), &self.mono_body)
             .iterate_to_fixpoint();
@@ -694,7 +1213,8 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         final_state
     }
 
-    /// Determine the type of call-site.
+    /// Determine the type of call-site and the function that is actually
+    /// called there (see [`Self::try_poll_call_kind`]).
     ///
     /// The error case is if we tried to resolve this as async and failed. We
     /// know it *is* async but we couldn't determine the information needed to
@@ -705,12 +1225,14 @@ impl<'tcx, 'a> LocalAnalysis<'tcx, 'a> {
         resolved_fn: Instance<'tcx>,
         original_args: &'b [Operand<'tcx>],
         span: Span,
-    ) -> CallKind<'tcx> {
+    ) -> (Instance<'tcx>, CallKind<'tcx>) {
         match self.try_poll_call_kind(def_id, resolved_fn, original_args) {
             AsyncDeterminationResult::Resolved(r) => r,
-            AsyncDeterminationResult::NotAsync => self
-                .try_indirect_call_kind(resolved_fn.def_id())
-                .unwrap_or(CallKind::Direct),
+            AsyncDeterminationResult::NotAsync => (
+                resolved_fn,
+                self.try_indirect_call_kind(resolved_fn.def_id())
+                    .unwrap_or(CallKind::Direct),
+            ),
             AsyncDeterminationResult::Unresolvable(reason) => {
                 self.tcx().sess.span_fatal(span, reason)
             }
@@ -830,6 +1352,23 @@ pub(crate) enum CallHandling<'tcx, 'a> {
         precise: bool,
     },
     ApproxAsyncSM(ApproximationHandler<'tcx, 'a>),
+    Summarized(CallSummary),
+    InteriorMutability(InteriorMutability),
+    /// A foreign call or transmute, approximated conservatively, see
+    /// [`ModularMutationVisitor::visit_conservative_call`].
+    Conservative,
+    /// A call through a trait object or function pointer resolved to several
+    /// candidate functions. For trait objects the translation between caller
+    /// and callees is imprecise, because the callee's `self` does not have the
+    /// type of the trait object.
+    Dispatched {
+        calling_convention: CallingConvention<'tcx>,
+        descriptors: Vec<&'a PartialGraph<'tcx>>,
+        precise: bool,
+        /// Some candidates are not inlined, so the call is also approximated
+        /// as if it were not inlined.
+        approximated: bool,
+    },
 }
 
 fn other_as_arg<'tcx>(place: Place<'tcx>, body: &Body<'tcx>) -> Option<u8> {
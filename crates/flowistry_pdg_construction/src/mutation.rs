@@ -16,7 +16,7 @@ use flowistry::mir::{
     utils::{self, AsyncHack},
 };
 
-use crate::utils::ty_resolve;
+use crate::{utils::ty_resolve, CallSummary, InteriorMutability};
 
 /// Indicator of certainty about whether a place is being mutated.
 /// Used to determine whether an update should be strong or weak.
@@ -299,6 +299,251 @@ where
         );
     }
 
+    /// Perform the effects of a call to a function with a [`CallSummary`].
+    ///
+    /// Arguments are combined with their reachable values like in the modular
+    /// approximation but afterwards only the flows described in the summary
+    /// are created. The values read from an argument are the argument and all
+    /// places reachable through it.
+    pub fn visit_summarized_call(
+        &mut self,
+        args: &[Operand<'tcx>],
+        destination: Place<'tcx>,
+        location: Location,
+        summary: &CallSummary,
+    ) {
+        let arg_places = utils::arg_places(args);
+        if matches!(self.time, Time::Unspecified | Time::Before) {
+            for (_, arg) in arg_places.iter().copied() {
+                let inputs = self
+                    .place_info
+                    .reachable_values(arg, Mutability::Not)
+                    .iter()
+                    .map(|v| (*v, None))
+                    .collect();
+                (self.f)(
+                    location,
+                    Mutation {
+                        mutated: arg,
+                        mutation_reason: TargetUse::Assign,
+                        inputs,
+                        status: MutationStatus::Definitely,
+                    },
+                );
+            }
+        }
+        if matches!(self.time, Time::Unspecified | Time::After) {
+            let place_info = self.place_info;
+            let inputs_from = |indices: &[u8]| {
+                arg_places
+                    .iter()
+                    .filter(|(i, _)| indices.contains(&(*i as u8)))
+                    .flat_map(|(i, arg)| {
+                        std::iter::once(*arg)
+                            .chain(
+                                place_info
+                                    .reachable_values(*arg, Mutability::Not)
+                                    .iter()
+                                    .copied(),
+                            )
+                            .unique()
+                            .map(move |v| (v, Some(*i as u8)))
+                    })
+                    .collect::<Vec<_>>()
+            };
+            for (target, sources) in &summary.writes {
+                let inputs = inputs_from(sources);
+                for (num, arg) in arg_places.iter().copied() {
+                    if num as u8 != *target {
+                        continue;
+                    }
+                    for arg_mut in self.place_info.reachable_values(arg, Mutability::Mut) {
+                        if *arg_mut != arg {
+                            (self.f)(
+                                location,
+                                Mutation {
+                                    mutated: *arg_mut,
+                                    mutation_reason: TargetUse::MutArg(*target),
+                                    inputs: inputs.clone(),
+                                    status: MutationStatus::Possibly,
+                                },
+                            )
+                        }
+                    }
+                }
+            }
+            (self.f)(
+                location,
+                Mutation {
+                    mutated: destination,
+                    inputs: inputs_from(&summary.return_from),
+                    mutation_reason: TargetUse::Return,
+                    status: MutationStatus::Definitely,
+                },
+            );
+        }
+    }
+
+    /// Perform the effects of a call that Flowistry cannot see through, such
+    /// as a foreign function or `transmute`.
+    ///
+    /// In addition to the modular approximation the call reads and writes the
+    /// pointees of raw pointer arguments.
+    pub fn visit_conservative_call(
+        &mut self,
+        args: &[Operand<'tcx>],
+        destination: Place<'tcx>,
+        location: Location,
+    ) {
+        let tcx = self.place_info.tcx;
+        let body = self.place_info.body;
+        let arg_places = utils::arg_places(args);
+        let pointees = arg_places
+            .iter()
+            .filter(|(_, arg)| arg.ty(body, tcx).ty.is_unsafe_ptr())
+            .map(|(num, arg)| (*num, arg.project_deeper(&[ProjectionElem::Deref], tcx)))
+            .collect::<Vec<_>>();
+        if matches!(self.time, Time::Unspecified | Time::Before) {
+            for (_, arg) in arg_places.iter().copied() {
+                let inputs = self
+                    .place_info
+                    .reachable_values(arg, Mutability::Not)
+                    .iter()
+                    .map(|v| (*v, None))
+                    .collect();
+                (self.f)(
+                    location,
+                    Mutation {
+                        mutated: arg,
+                        mutation_reason: TargetUse::Assign,
+                        inputs,
+                        status: MutationStatus::Definitely,
+                    },
+                );
+            }
+        }
+        if matches!(self.time, Time::Unspecified | Time::After) {
+            let inputs = arg_places
+                .iter()
+                .chain(&pointees)
+                .map(|(num, place)| (*place, Some(*num as u8)))
+                .collect::<Vec<_>>();
+            for (num, arg) in arg_places.iter().copied() {
+                for arg_mut in self.place_info.reachable_values(arg, Mutability::Mut) {
+                    if *arg_mut != arg {
+                        (self.f)(
+                            location,
+                            Mutation {
+                                mutated: *arg_mut,
+                                mutation_reason: TargetUse::MutArg(num as u8),
+                                inputs: inputs.clone(),
+                                status: MutationStatus::Possibly,
+                            },
+                        )
+                    }
+                }
+            }
+            for (num, pointee) in pointees.iter().copied() {
+                (self.f)(
+                    location,
+                    Mutation {
+                        mutated: pointee,
+                        mutation_reason: TargetUse::MutArg(num as u8),
+                        inputs: inputs.clone(),
+                        status: MutationStatus::Possibly,
+                    },
+                )
+            }
+            (self.f)(
+                location,
+                Mutation {
+                    mutated: destination,
+                    inputs,
+                    mutation_reason: TargetUse::Return,
+                    status: MutationStatus::Definitely,
+                },
+            );
+        }
+    }
+
+    /// Perform the effects of a call that accesses an interior-mutable value
+    /// behind a shared reference, as described by `model`.
+    ///
+    /// Guards are handled like the modular approximation, the writes through
+    /// them are resolved by the aliases of the caller.
+    pub fn visit_interior_mutability_call(
+        &mut self,
+        args: &[Operand<'tcx>],
+        destination: Place<'tcx>,
+        location: Location,
+        model: InteriorMutability,
+    ) {
+        let arg_places = utils::arg_places(args);
+        let (target, overwrite) = match model {
+            InteriorMutability::Set { target } => (target, true),
+            InteriorMutability::Update { target } => (target, false),
+            InteriorMutability::Guard => {
+                return self.handle_call_with_combine_on_args(arg_places, location, destination)
+            }
+        };
+        let Some((_, target_place)) = arg_places
+            .iter()
+            .copied()
+            .find(|(num, _)| *num as u8 == target)
+        else {
+            return self.handle_call_with_combine_on_args(arg_places, location, destination);
+        };
+        if matches!(self.time, Time::Unspecified | Time::Before) {
+            for (_, arg) in arg_places.iter().copied() {
+                let inputs = self
+                    .place_info
+                    .reachable_values(arg, Mutability::Not)
+                    .iter()
+                    .map(|v| (*v, None))
+                    .collect();
+                (self.f)(
+                    location,
+                    Mutation {
+                        mutated: arg,
+                        mutation_reason: TargetUse::Assign,
+                        inputs,
+                        status: MutationStatus::Definitely,
+                    },
+                );
+            }
+        }
+        if matches!(self.time, Time::Unspecified | Time::After) {
+            let inputs = arg_places
+                .iter()
+                .map(|(num, arg)| (*arg, Some(*num as u8)))
+                .collect::<Vec<_>>();
+            // The return value reads the old value, so it is written first.
+            (self.f)(
+                location,
+                Mutation {
+                    mutated: destination,
+                    inputs: inputs.clone(),
+                    mutation_reason: TargetUse::Return,
+                    status: MutationStatus::Definitely,
+                },
+            );
+            let new_value = inputs
+                .into_iter()
+                .filter(|(_, num)| !overwrite || *num != Some(target))
+                .collect();
+            let tcx = self.place_info.tcx;
+            (self.f)(
+                location,
+                Mutation {
+                    mutated: target_place.project_deeper(&[ProjectionElem::Deref], tcx),
+                    inputs: new_value,
+                    mutation_reason: TargetUse::MutArg(target),
+                    status: MutationStatus::Possibly,
+                },
+            );
+        }
+    }
+
     #[allow(dead_code)]
     fn handle_call_with_combine_on_args(
         &mut self,
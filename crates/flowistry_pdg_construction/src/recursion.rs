@@ -0,0 +1,196 @@
+//! Summaries for recursive calls.
+//!
+//! When the graph for a function is under construction and we encounter a
+//! call to that same function (directly or through other functions) we cannot
+//! inline it. Instead such a call is handled with a [`CallSummary`] describing
+//! which arguments flow into the return value and into which mutable
+//! arguments.
+//!
+//! The summary of a function is computed from a graph of its body in which
+//! every call is itself summarized. For functions in a recursive cycle we
+//! start with empty summaries for the back edges of the cycle and recompute
+//! the summaries of the cycle until none of them change. Because summaries
+//! only grow and are bounded by the number of arguments this terminates. Once
+//! the function that started the cycle reaches its fixpoint the summaries of
+//! all functions in the cycle are final.
+//!
+//! Summaries are computed independently of the PDG cache, so the graphs that
+//! are cached only ever contain final summaries. The call change callback is
+//! consulted once per call, no matter how often the body is visited.
+
+use std::cell::RefCell;
+
+use flowistry_pdg::CallString;
+use log::trace;
+use rustc_hash::{FxHashMap, FxHashSet};
+use rustc_middle::mir::Location;
+
+use crate::{
+    construct::PdgCacheKey, graph::PartialGraph, local_analysis::LocalAnalysis, CallChanges,
+    CallSummary, MemoPdgConstructor,
+};
+
+#[derive(Default)]
+pub(crate) struct SummaryCache<'tcx> {
+    /// Summaries that have reached their fixpoint.
+    done: RefCell<FxHashMap<PdgCacheKey<'tcx>, CallSummary>>,
+    /// The latest summaries of functions in a cycle that is still being
+    /// iterated.
+    provisional: RefCell<FxHashMap<PdgCacheKey<'tcx>, CallSummary>>,
+    /// The functions whose summaries are being computed.
+    stack: RefCell<Vec<StackEntry<'tcx>>>,
+    /// The changes the callback requested, by caller and call site.
+    decisions: RefCell<FxHashMap<(PdgCacheKey<'tcx>, Location), CallChanges<'tcx>>>,
+}
+
+/// A function whose summary is being computed, see [`SummaryCache::stack`].
+struct StackEntry<'tcx> {
+    key: PdgCacheKey<'tcx>,
+    /// The lowest stack index whose summary this one depends on.
+    depends_on: usize,
+    /// Functions that were above this one on the stack and are part of the
+    /// same cycle. Their summaries become final together with this one.
+    members: FxHashSet<PdgCacheKey<'tcx>>,
+    /// Whether the summary of one of the [`Self::members`] changed since the
+    /// summary of this function was last computed.
+    members_changed: bool,
+}
+
+impl<'tcx> MemoPdgConstructor<'tcx> {
+    /// The changes for the call at `location` in `caller`, as decided by
+    /// `decide`.
+    ///
+    /// The body of a function is visited repeatedly while its summary is
+    /// computed and once more when its graph is constructed, in either order.
+    /// The callback only sees the first visit of each call, later visits reuse
+    /// its decision.
+    pub(crate) fn call_changes(
+        &self,
+        caller: PdgCacheKey<'tcx>,
+        location: Location,
+        decide: impl FnOnce() -> CallChanges<'tcx>,
+    ) -> CallChanges<'tcx> {
+        let decisions = &self.summaries.decisions;
+        if let Some(changes) = decisions.borrow().get(&(caller, location)) {
+            return changes.clone();
+        }
+        let changes = decide();
+        decisions
+            .borrow_mut()
+            .insert((caller, location), changes.clone());
+        changes
+    }
+
+    /// The summary of the function `key` for a call that cannot be inlined
+    /// because it is recursive.
+    pub(crate) fn recursive_summary(&self, key: PdgCacheKey<'tcx>) -> CallSummary {
+        let cache = &self.summaries;
+        if let Some(summary) = cache.done.borrow().get(&key) {
+            return summary.clone();
+        }
+        if let Some(index) = cache.stack.borrow().iter().position(|e| e.key == key) {
+            // A cycle, the function on top of the stack depends on one that is
+            // still being computed.
+            let mut stack = cache.stack.borrow_mut();
+            let top = stack.last_mut().unwrap();
+            top.depends_on = top.depends_on.min(index);
+            return cache
+                .provisional
+                .borrow()
+                .get(&key)
+                .cloned()
+                .unwrap_or_default();
+        }
+        let index = {
+            let mut stack = cache.stack.borrow_mut();
+            stack.push(StackEntry {
+                key,
+                depends_on: stack.len(),
+                members: Default::default(),
+                members_changed: false,
+            });
+            stack.len() - 1
+        };
+        loop {
+            let summary = LocalAnalysis::new(self, key.0, key.1, true)
+                .construct_partial()
+                .summarize();
+            let changed = cache
+                .provisional
+                .borrow_mut()
+                .insert(key, summary.clone())
+                .map_or(true, |previous| previous != summary);
+            let (depends_on, changed) = {
+                let mut stack = cache.stack.borrow_mut();
+                let entry = &mut stack[index];
+                (
+                    entry.depends_on,
+                    changed | std::mem::take(&mut entry.members_changed),
+                )
+            };
+            if depends_on == index && changed {
+                trace!("Summary of {:?} or its cycle changed, iterating", key.0);
+                continue;
+            }
+            let entry = cache.stack.borrow_mut().pop().unwrap();
+            if depends_on < index {
+                // Part of a cycle that starts further down the stack. This
+                // summary is recomputed when the start of the cycle iterates.
+                let mut stack = cache.stack.borrow_mut();
+                let top = stack.last_mut().unwrap();
+                top.depends_on = top.depends_on.min(depends_on);
+                top.members.extend(entry.members);
+                top.members.insert(key);
+                top.members_changed |= changed;
+            } else {
+                let mut provisional = cache.provisional.borrow_mut();
+                let mut done = cache.done.borrow_mut();
+                for member in entry.members.into_iter().chain([key]) {
+                    if let Some(summary) = provisional.remove(&member) {
+                        done.insert(member, summary);
+                    }
+                }
+            }
+            return summary;
+        }
+    }
+}
+
+impl<'tcx> PartialGraph<'tcx> {
+    /// Summarize the flows from the arguments of this function to its return
+    /// value and mutable arguments along all edges of the graph.
+    pub(crate) fn summarize(&self) -> CallSummary {
+        let is_at_root = |cs: CallString| cs.is_at_root();
+        let mut successors: FxHashMap<_, Vec<_>> = FxHashMap::default();
+        for (src, dst, _) in &self.edges {
+            successors.entry(*src).or_default().push(*dst);
+        }
+        let targets = self
+            .parentable_dsts(is_at_root)
+            .into_iter()
+            .collect::<FxHashMap<_, _>>();
+        let mut summary = CallSummary::default();
+        let mut writes: FxHashMap<u8, Vec<u8>> = FxHashMap::default();
+        for (source, arg) in self.parentable_srcs(is_at_root) {
+            let Some(arg) = arg else {
+                continue;
+            };
+            let mut seen = FxHashSet::default();
+            let mut queue = vec![source];
+            while let Some(node) = queue.pop() {
+                if !seen.insert(node) {
+                    continue;
+                }
+                match targets.get(&node) {
+                    Some(None) => summary.return_from.push(arg),
+                    Some(Some(target)) => writes.entry(*target).or_default().push(arg),
+                    None => (),
+                }
+                queue.extend(successors.get(&node).into_iter().flatten().copied());
+            }
+        }
+        summary.writes = writes.into_iter().collect();
+        summary.normalize();
+        summary
+    }
+}
@@ -99,11 +99,18 @@ pub fn type_as_fn<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<(DefId, Gener
     }
 }
 
+/// Recompute the types in the projection of `orig` in the context of `body`,
+/// stopping at types that cannot be projected further.
+///
+/// Trait objects show up when the generator of a boxed future is inlined, its
+/// fields are projected onto the `dyn Future`. If `dyn_target` is given such
+/// trait objects are treated as being of that concrete type instead.
 pub fn retype_place<'tcx>(
     orig: Place<'tcx>,
     tcx: TyCtxt<'tcx>,
     body: &Body<'tcx>,
     def_id: DefId,
+    dyn_target: Option<Ty<'tcx>>,
 ) -> Place<'tcx> {
     trace!("Retyping {orig:?} in context of {def_id:?}");
 
@@ -111,9 +118,16 @@ pub fn retype_place<'tcx>(
     let mut ty = PlaceTy::from_ty(body.local_decls()[orig.local].ty);
     let param_env = tcx.param_env_reveal_all_normalized(def_id);
     for elem in orig.projection.iter() {
+        if let (TyKind::Dynamic(..), Some(target)) = (ty.ty.kind(), dyn_target) {
+            ty = PlaceTy::from_ty(target);
+        }
         if matches!(
             ty.ty.kind(),
-            TyKind::Alias(..) | TyKind::Param(..) | TyKind::Bound(..) | TyKind::Placeholder(..)
+            TyKind::Alias(..)
+                | TyKind::Param(..)
+                | TyKind::Bound(..)
+                | TyKind::Placeholder(..)
+                | TyKind::Dynamic(..)
         ) {
             break;
         }
@@ -223,10 +237,28 @@ pub fn ty_resolve<'tcx>(ty: Ty<'tcx>, tcx: TyCtxt<'tcx>) -> Ty<'tcx> {
     }
 }
 
+/// Types chosen explicitly for generic type parameters, keyed by the [`DefId`]
+/// of the parameter declaration.
+///
+/// Because closures and generators share the parameters of their parent, an
+/// instantiation for a function also applies to the generator of an `async
+/// fn`.
+pub type Instantiation<'tcx> = FxHashMap<DefId, Ty<'tcx>>;
+
 pub fn manufacture_substs_for(
     tcx: TyCtxt<'_>,
     function: DefId,
 ) -> Result<GenericArgsRef<'_>, ErrorGuaranteed> {
+    instantiate_substs_for(tcx, function, &Instantiation::default())
+}
+
+/// Like [`manufacture_substs_for`] but the type parameters in `explicit` are
+/// instantiated with the given type instead of a trait object.
+pub fn instantiate_substs_for<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    function: DefId,
+    explicit: &Instantiation<'tcx>,
+) -> Result<GenericArgsRef<'tcx>, ErrorGuaranteed> {
     use rustc_middle::ty::{
         BoundRegionKind, DynKind, ExistentialPredicate, ExistentialProjection, ExistentialTraitRef,
         GenericParamDefKind, ImplPolarity, ParamTy, TraitPredicate,
@@ -241,6 +273,9 @@ pub fn manufacture_substs_for(
     let lang_items = tcx.lang_items();
     let types = (0..generics.count()).map(|gidx| {
         let param = generics.param_at(gidx, tcx);
+        if let Some(ty) = explicit.get(&param.def_id) {
+            return Ok(GenericArg::from(*ty));
+        }
         if let Some(default_val) = param.default_value(tcx) {
             return Ok(default_val.instantiate_identity());
         }
@@ -10,12 +10,12 @@ use std::{collections::HashSet, rc::Rc};
 use either::Either;
 use flowistry::mir::FlowistryInput;
 use flowistry_pdg_construction::{
-    body_cache::{dump_mir_and_borrowck_facts, BodyCache},
+    body_cache::{dump_mir_and_borrowck_facts, local_or_remote_paths, BodyCache},
     graph::{DepEdge, DepGraph},
     CallChangeCallback, CallChangeCallbackFn, CallChanges, MemoPdgConstructor, SkipCall,
 };
 use itertools::Itertools;
-use rustc_hir::def_id::LocalDefId;
+use rustc_hir::def_id::{LocalDefId, LOCAL_CRATE};
 use rustc_middle::{
     mir::{Terminator, TerminatorKind},
     ty::TyCtxt,
@@ -59,7 +59,7 @@ fn pdg(
     rustc_utils::test_utils::CompileBuilder::new(input)
         .with_query_override(None)
         .expect_compile(move |CompileResult { tcx, .. }| {
-            dump_mir_and_borrowck_facts(tcx);
+            dump_mir_and_borrowck_facts(tcx).unwrap();
             let def_id = get_main(tcx);
             let mut memo = MemoPdgConstructor::new(tcx);
             configure(tcx, &mut memo);
@@ -863,3 +863,58 @@ pdg_test! {
   },
   (i -> h)
 }
+
+#[test]
+fn body_archive_round_trip() {
+    let input = stringify!(
+        fn helper(x: u32, y: u32) -> u32 {
+            x + y
+        }
+
+        fn main() {
+            let z = helper(1, 2);
+        }
+    );
+    rustc_utils::test_utils::CompileBuilder::new(input)
+        .with_query_override(None)
+        .expect_compile(|CompileResult { tcx, .. }| {
+            dump_mir_and_borrowck_facts(tcx).unwrap();
+            let cache = BodyCache::new(tcx);
+            for (_, body_id) in find_bodies(tcx) {
+                let def_id = tcx.hir().body_owner_def_id(body_id).to_def_id();
+                let body = cache.try_get(def_id).expect("body was not dumped").body();
+                assert_eq!(body.source.def_id(), def_id);
+                assert_eq!(
+                    body.arg_count,
+                    tcx.fn_sig(def_id)
+                        .skip_binder()
+                        .inputs()
+                        .skip_binder()
+                        .len()
+                );
+            }
+
+            // Archives whose index points past the bodies are rejected
+            // instead of being decoded.
+            // The local artifact comes after the one in the body store
+            let path = &local_or_remote_paths(LOCAL_CRATE, tcx, "bwbf")
+                .pop()
+                .unwrap();
+            let original = std::fs::read(path).unwrap();
+            let footer_start = original.len() - 8;
+            let index_start =
+                u64::from_le_bytes(original[footer_start..].try_into().unwrap()) as usize;
+            let main = get_main(tcx).to_def_id();
+
+            let mut data = original.clone();
+            let first_length = index_start + 8 + 4 + 8;
+            data[first_length..first_length + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+            std::fs::write(path, data).unwrap();
+            assert!(BodyCache::new(tcx).try_get(main).is_none());
+
+            let mut data = original;
+            data[footer_start..].copy_from_slice(&(footer_start as u64 + 8).to_le_bytes());
+            std::fs::write(path, data).unwrap();
+            assert!(BodyCache::new(tcx).try_get(main).is_none());
+        })
+}
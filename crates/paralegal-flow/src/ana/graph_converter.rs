@@ -1,24 +1,23 @@
 use std::{rc::Rc, time::Instant};
 
-use self::call_string_resolver::CallStringResolver;
+pub(super) use self::call_string_resolver::CallStringResolver;
 use super::{default_index, path_for_item, src_loc_for_span, SPDGGenerator};
 use crate::{
-    ann::MarkerAnnotation, desc::*, discover::FnToAnalyze, stats::TimedStat, utils::*, HashMap,
-    HashSet, MarkerCtx,
+    ann::MarkerAnnotation, args::ChannelModel, desc::*, discover::FnToAnalyze, stats::TimedStat,
+    utils::*, HashMap, HashSet, MarkerCtx,
 };
-use flowistry_pdg::SourceUse;
+use flowistry_pdg::{SourceUse, TargetUse};
 use flowistry_pdg_construction::{
+    async_fn_of_generator,
     body_cache::BodyCache,
     graph::{DepEdge, DepEdgeKind, DepGraph, DepNode},
-    is_async_trait_fn, match_async_trait_assign,
+    is_async_wrapper_fn, match_async_trait_assign,
     utils::{try_monomorphize, try_resolve_function, type_as_fn},
+    SkipCall,
 };
-use paralegal_spdg::{Node, SPDGStats};
+use paralegal_spdg::{MarkerProvenance, Node, SPDGStats};
 
-use rustc_hir::{
-    def,
-    def_id::{DefId, LocalDefId},
-};
+use rustc_hir::{def, def_id::DefId};
 use rustc_middle::{
     mir,
     ty::{self, TyCtxt},
@@ -41,7 +40,7 @@ pub struct GraphConverter<'tcx, 'a, C> {
     /// The parent generator
     generator: &'a SPDGGenerator<'tcx>,
     /// Information about the function this PDG belongs to
-    target: &'a FnToAnalyze,
+    target: &'a FnToAnalyze<'tcx>,
     /// The flowistry graph we are converting
     dep_graph: Rc<DepGraph<'tcx>>,
     /// Same as the ID stored in self.target, but as a local def id
@@ -58,7 +57,7 @@ pub struct GraphConverter<'tcx, 'a, C> {
     index_map: Box<[Node]>,
     /// The converted graph we are creating
     spdg: SPDGImpl,
-    marker_assignments: HashMap<Node, HashSet<Identifier>>,
+    marker_assignments: HashMap<Node, HashMap<Identifier, HashSet<MarkerProvenance>>>,
     call_string_resolver: call_string_resolver::CallStringResolver<'tcx, 'a>,
     stats: SPDGStats,
 }
@@ -68,11 +67,11 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
     pub fn new_with_flowistry(
         generator: &'a SPDGGenerator<'tcx>,
         known_def_ids: &'a mut C,
-        target: &'a FnToAnalyze,
+        target: &'a FnToAnalyze<'tcx>,
     ) -> Result<Self> {
         let local_def_id = target.def_id;
         let start = Instant::now();
-        let (dep_graph, stats) = Self::create_flowistry_graph(generator, local_def_id)?;
+        let (dep_graph, stats) = Self::create_flowistry_graph(generator, target)?;
         generator
             .stats
             .record_timed(TimedStat::Flowistry, start.elapsed());
@@ -99,7 +98,8 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
             call_string_resolver: CallStringResolver::new(
                 generator.tcx,
                 def_id,
-                generator.pdg_constructor.body_cache(),
+                &target.instantiation,
+                &generator.pdg_constructor,
                 generator.marker_ctx().clone(),
             ),
             stats,
@@ -137,14 +137,18 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
         res
     }
 
-    fn register_markers(&mut self, node: Node, markers: impl IntoIterator<Item = Identifier>) {
+    fn register_markers(
+        &mut self,
+        node: Node,
+        markers: impl IntoIterator<Item = (Identifier, MarkerProvenance)>,
+    ) {
         let mut markers = markers.into_iter().peekable();
 
         if markers.peek().is_some() {
-            self.marker_assignments
-                .entry(node)
-                .or_default()
-                .extend(markers);
+            let assignments = self.marker_assignments.entry(node).or_default();
+            for (marker, provenance) in markers {
+                assignments.entry(marker).or_default().insert(provenance);
+            }
         }
     }
 
@@ -162,7 +166,16 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
                 if matches!(body.local_kind(weight.place.local), mir::LocalKind::Arg) =>
             {
                 let function_id = leaf_loc.function;
-                let arg_num = weight.place.local.as_u32() - 1;
+                // Closure bodies receive their environment as first argument,
+                // which is not a parameter the user can refer to.
+                let num_hidden_args = if self.tcx().is_closure(function_id) {
+                    2
+                } else {
+                    1
+                };
+                let Some(arg_num) = weight.place.local.as_u32().checked_sub(num_hidden_args) else {
+                    return;
+                };
                 self.known_def_ids.extend(Some(function_id));
 
                 self.register_annotations_for_function(node, function_id, |ann| {
@@ -177,14 +190,21 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
                 });
             }
             RichLocation::Location(loc) => {
-                let crate::Either::Right(
-                    term @ mir::Terminator {
-                        kind: mir::TerminatorKind::Call { func, .. },
+                let (term, func) = match body.stmt_at(loc) {
+                    crate::Either::Right(
+                        term @ mir::Terminator {
+                            kind: mir::TerminatorKind::Call { func, .. },
+                            ..
+                        },
+                    ) => (term, func),
+                    crate::Either::Left(mir::Statement {
+                        kind: mir::StatementKind::Assign(box (dest, rvalue)),
                         ..
-                    },
-                ) = body.stmt_at(loc)
-                else {
-                    return;
+                    }) if dest.local == weight.place.local => {
+                        self.static_read_annotations(node, rvalue, loc);
+                        return;
+                    }
+                    _ => return,
                 };
                 let res = self.call_string_resolver.resolve(weight.at);
                 let param_env = self.tcx().param_env(res.def_id());
@@ -199,7 +219,8 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
                     self.tcx().param_env(leaf_loc.function),
                     args,
                 )
-                .map_or(inst, |i| i.def_id());
+                .map_or(inst, |i| i.marker_target());
+                let callee_is_closure = self.tcx().is_closure(f);
                 self.known_def_ids.extend(Some(f));
 
                 // Question: Could a function with no input produce an
@@ -233,6 +254,24 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
                     let SourceUse::Argument(arg) = e.weight().source_use else {
                         continue;
                     };
+                    if callee_is_closure {
+                        // Closures are called as `Fn::call(closure, (args..))`.
+                        // The first argument is the environment, the second
+                        // the tuple of the actual arguments, so we use the
+                        // tuple field to find the parameter index.
+                        if arg != 1 {
+                            continue;
+                        }
+                        let field = match weight.place.projection.first() {
+                            Some(mir::ProjectionElem::Field(idx, _)) => Some(idx.as_u32()),
+                            _ => None,
+                        };
+                        self.register_annotations_for_function(node, f, |ann| {
+                            let on_argument = ann.refinement.on_argument();
+                            field.map_or(!on_argument.is_empty(), |idx| on_argument.is_set(idx))
+                        });
+                        continue;
+                    }
                     self.register_annotations_for_function(node, f, |ann| {
                         ann.refinement.on_argument().contains(arg as u32).unwrap()
                     });
@@ -242,6 +281,28 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
         }
     }
 
+    /// Register markers for `static` and `const` items read by this
+    /// assignment.
+    fn static_read_annotations(
+        &mut self,
+        node: Node,
+        rvalue: &mir::Rvalue<'tcx>,
+        loc: mir::Location,
+    ) {
+        use mir::visit::Visitor;
+        let tcx = self.tcx();
+        let mut items = vec![];
+        ConstantVisitor(|c: &mir::Constant<'tcx>| items.extend(referenced_static_or_const(tcx, c)))
+            .visit_rvalue(rvalue, loc);
+        for item in items {
+            if !self.marker_ctx().is_marked(item) {
+                continue;
+            }
+            self.known_def_ids.extend(Some(item));
+            self.register_annotations_for_function(node, item, |ann| ann.refinement.on_self());
+        }
+    }
+
     /// Reconstruct the type for the data this node represents.
     fn determine_place_type(
         &self,
@@ -302,17 +363,28 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
     ) {
         let parent = get_parent(self.tcx(), function);
         let marker_ctx = self.marker_ctx().clone();
+        let annotations_on = |item: DefId, via_trait: bool| {
+            marker_ctx
+                .combined_markers_with_source(item)
+                .map(move |(ann, source)| {
+                    let provenance = MarkerProvenance {
+                        item,
+                        source,
+                        via_trait,
+                    };
+                    (ann, provenance)
+                })
+        };
         self.register_markers(
             node,
-            marker_ctx
-                .combined_markers(function)
+            annotations_on(function, false)
                 .chain(
                     parent
                         .into_iter()
-                        .flat_map(|parent| marker_ctx.combined_markers(parent)),
+                        .flat_map(|parent| annotations_on(parent, true)),
                 )
-                .filter(|ann| filter(ann))
-                .map(|ann| ann.marker),
+                .filter(|(ann, _)| filter(ann))
+                .map(|(ann, provenance)| (ann.marker, provenance)),
         );
         self.known_def_ids.extend(parent);
     }
@@ -349,20 +421,31 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
     }
 
     /// Create an initial flowistry graph for the function identified by
-    /// `local_def_id`.
+    /// `target`, using its instantiation.
     fn create_flowistry_graph(
         generator: &SPDGGenerator<'tcx>,
-        local_def_id: LocalDefId,
+        target: &FnToAnalyze<'tcx>,
     ) -> Result<(DepGraph<'tcx>, SPDGStats)> {
-        let pdg = generator.pdg_constructor.construct_graph(local_def_id);
+        let local_def_id = target.def_id;
+        generator.start_controller_stats();
+        let start = Instant::now();
+        let pdg = generator
+            .pdg_constructor
+            .construct_instantiated_graph(local_def_id, &target.instantiation);
+        let construction_time = start.elapsed();
+        let stats = SPDGStats {
+            construction_time,
+            ..generator.controller_stats(target, &pdg)
+        };
 
-        Ok((pdg, Default::default()))
+        Ok((pdg, stats))
     }
 
     /// Consume the generator and compile the [`SPDG`].
     pub fn make_spdg(mut self) -> SPDG {
         let start = Instant::now();
         self.make_spdg_impl();
+        self.connect_channels();
         let arguments = self.determine_arguments();
         let return_ = self.determine_return();
         self.generator
@@ -374,11 +457,21 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
             graph: self.spdg,
             id: self.def_id,
             name: Identifier::new(self.target.name()),
+            tags: self.target.tags.clone().into(),
+            route: self.target.route,
+            attributes: self.target.attributes.clone().into(),
             arguments,
             markers: self
                 .marker_assignments
                 .into_iter()
-                .map(|(k, v)| (k, v.into_iter().collect()))
+                .map(|(k, v)| {
+                    (
+                        k,
+                        v.into_iter()
+                            .map(|(marker, provenance)| (marker, provenance.into_iter().collect()))
+                            .collect(),
+                    )
+                })
                 .collect(),
             return_,
             type_assigns: self
@@ -442,6 +535,102 @@ impl<'a, 'tcx, C: Extend<DefId>> GraphConverter<'tcx, 'a, C> {
         }
     }
 
+    /// Add data edges from the values sent into channels to the results of
+    /// receiving from a channel with the same element type (see
+    /// [`BuildConfig::channels`](crate::BuildConfig::channels)).
+    ///
+    /// The inline judge approximates channel operations, so the sent values
+    /// are the sources of the edges with the argument use of the model at the
+    /// call site and the received values are the targets of the edges with the
+    /// return use.
+    fn connect_channels(&mut self) {
+        use petgraph::prelude::*;
+        let graph = self.dep_graph.clone();
+        let mut ends = HashMap::new();
+        let mut sent: HashMap<_, HashSet<Node>> = HashMap::new();
+        let mut received: HashMap<_, HashSet<(Node, CallString)>> = HashMap::new();
+        for e in graph.graph.edge_references() {
+            let weight = e.weight();
+            if weight.kind != DepEdgeKind::Data {
+                continue;
+            }
+            let end = *ends
+                .entry(weight.at)
+                .or_insert_with(|| self.channel_end(weight.at));
+            match end {
+                Some((ChannelModel::Send { value }, ty))
+                    if weight.source_use == SourceUse::Argument(value) =>
+                {
+                    sent.entry(ty)
+                        .or_default()
+                        .insert(self.new_node_for(e.source()));
+                }
+                Some((ChannelModel::Receive, ty)) if weight.target_use == TargetUse::Return => {
+                    received
+                        .entry(ty)
+                        .or_default()
+                        .insert((self.new_node_for(e.target()), weight.at));
+                }
+                _ => (),
+            }
+        }
+        for (ty, sources) in sent {
+            for &(target, at) in received.get(&ty).into_iter().flatten() {
+                for &source in &sources {
+                    self.spdg.add_edge(
+                        source,
+                        target,
+                        EdgeInfo {
+                            at,
+                            kind: EdgeKind::Data,
+                            source_use: SourceUse::Operand,
+                            target_use: TargetUse::Assign,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// The channel model of the function called at `at` and the element type
+    /// of the channel. The `.await` of a modeled `async fn` is the call to
+    /// `poll` on its future, the value it sends is part of that future.
+    fn channel_end(&self, at: CallString) -> Option<(ChannelModel, ty::Ty<'tcx>)> {
+        let tcx = self.tcx();
+        let leaf = at.leaf();
+        let RichLocation::Location(loc) = leaf.location else {
+            return None;
+        };
+        let body = self.body_cache().get(leaf.function).body();
+        let Either::Right(mir::Terminator {
+            kind: mir::TerminatorKind::Call { func, .. },
+            source_info,
+        }) = body.stmt_at(loc)
+        else {
+            return None;
+        };
+        let res = self.call_string_resolver.resolve(at);
+        let func = try_monomorphize(
+            res,
+            tcx,
+            tcx.param_env(res.def_id()),
+            func,
+            source_info.span,
+        )
+        .ok()?;
+        let (inst, args) = type_as_fn(tcx, ty_of_const(func.constant()?))?;
+        let callee = try_resolve_function(tcx, inst, tcx.param_env(leaf.function), args)?;
+        let (callee, model) = match async_fn_of_generator(tcx, callee) {
+            Some(async_fn) => match self.generator.judge.channel_model(async_fn.def_id())? {
+                ChannelModel::Send { .. } => (async_fn, ChannelModel::Send { value: 0 }),
+                receive => (async_fn, receive),
+            },
+            None => (callee, self.generator.judge.channel_model(callee.def_id())?),
+        };
+        let ty = callee.args.types().next()?;
+        Some((model, tcx.erase_regions(ty)))
+    }
+
     /// Return the (sub)types of this type that are marked.
     fn type_is_marked(
         &'a self,
@@ -585,7 +774,7 @@ fn entrypoint_is_async<'tcx>(
     def_id: DefId,
 ) -> bool {
     tcx.asyncness(def_id).is_async()
-        || is_async_trait_fn(tcx, def_id, body_cache.get(def_id).body())
+        || is_async_wrapper_fn(tcx, def_id, body_cache.get(def_id).body())
 }
 
 mod call_string_resolver {
@@ -596,10 +785,11 @@ mod call_string_resolver {
 
     use flowistry_pdg::CallString;
     use flowistry_pdg_construction::{
-        body_cache::BodyCache,
-        utils::{manufacture_substs_for, try_monomorphize, try_resolve_function},
+        utils::{instantiate_substs_for, try_monomorphize, try_resolve_function, Instantiation},
+        MemoPdgConstructor,
     };
     use paralegal_spdg::Endpoint;
+    use rustc_hir::def_id::DefId;
     use rustc_middle::{mir::TerminatorKind, ty::Instance};
     use rustc_utils::cache::Cache;
 
@@ -615,7 +805,9 @@ mod call_string_resolver {
         cache: Cache<CallString, Instance<'tcx>>,
         tcx: TyCtxt<'tcx>,
         entrypoint_is_async: bool,
-        body_cache: &'a BodyCache<'tcx>,
+        /// Explicitly chosen types for the generics of the entrypoint
+        instantiation: &'a Instantiation<'tcx>,
+        pdg_constructor: &'a MemoPdgConstructor<'tcx>,
         marker_context: MarkerCtx<'tcx>,
     }
 
@@ -630,7 +822,7 @@ mod call_string_resolver {
             let (this, opt_prior_loc) = cs.pop();
             if let Some(prior_loc) = opt_prior_loc {
                 if prior_loc.len() != 1 || !self.entrypoint_is_async {
-                    return self.resolve_internal(prior_loc);
+                    return self.resolve_internal(prior_loc, this.function);
                 }
             }
             let def_id = this.function;
@@ -638,40 +830,55 @@ mod call_string_resolver {
                 self.tcx,
                 def_id,
                 self.tcx.param_env(def_id),
-                manufacture_substs_for(self.tcx, def_id).unwrap(),
+                instantiate_substs_for(self.tcx, def_id, self.instantiation).unwrap(),
             )
             .unwrap()
         }
 
+        /// Whether the entrypoint is an `async fn` or a wrapper around an async
+        /// block, in which case the roots of all call strings are the location
+        /// where the generator is created.
+        pub fn entrypoint_is_async(&self) -> bool {
+            self.entrypoint_is_async
+        }
+
         pub fn new(
             tcx: TyCtxt<'tcx>,
             entrypoint: Endpoint,
-            body_cache: &'a BodyCache<'tcx>,
+            instantiation: &'a Instantiation<'tcx>,
+            pdg_constructor: &'a MemoPdgConstructor<'tcx>,
             marker_context: MarkerCtx<'tcx>,
         ) -> Self {
             Self {
                 cache: Default::default(),
                 tcx,
-                entrypoint_is_async: super::entrypoint_is_async(body_cache, tcx, entrypoint),
-                body_cache,
+                entrypoint_is_async: super::entrypoint_is_async(
+                    pdg_constructor.body_cache(),
+                    tcx,
+                    entrypoint,
+                ),
+                instantiation,
+                pdg_constructor,
                 marker_context,
             }
         }
 
         /// This resolves the monomorphized function *being called at* this call
-        /// site.
+        /// site. `callee` is the function the graph construction inlined here,
+        /// it only differs from the function called in the MIR if a boxed
+        /// future was polled (see [`MemoPdgConstructor::polled_generators`]).
         ///
         /// This function is internal because it panics if `cs.leaf().location`
         /// is not either a function call or a statement where an async closure
         /// is created and assigned.
-        fn resolve_internal(&self, cs: CallString) -> Instance<'tcx> {
+        fn resolve_internal(&self, cs: CallString, callee: DefId) -> Instance<'tcx> {
             *self.cache.get(cs, |_| {
                 let this = cs.leaf();
                 let prior = self.resolve(cs);
 
                 let tcx = self.tcx;
 
-                let base_stmt = super::expect_stmt_at(self.body_cache, this);
+                let base_stmt = super::expect_stmt_at(self.pdg_constructor.body_cache(), this);
                 let param_env = tcx.param_env_reveal_all_normalized(prior.def_id());
                 let normalized = map_either(
                     base_stmt,
@@ -688,14 +895,20 @@ mod call_string_resolver {
                     Either::Right(term) => {
                         let (def_id, args) = func_of_term(tcx, &term).unwrap();
                         let instance = Instance::expect_resolve(tcx, param_env, def_id, args);
-                        if let Some(model) = self.marker_context.has_stub(def_id) {
+                        if let Some(model) = self
+                            .marker_context
+                            .applicable_stub(instance, term.source_info.span)
+                        {
                             let TerminatorKind::Call { args, .. } = &term.kind else {
                                 unreachable!()
                             };
-                            model
+                            match model
                                 .apply(tcx, instance, param_env, args, term.source_info.span)
                                 .unwrap()
-                                .0
+                            {
+                                SkipCall::Replace { instance, .. } => instance,
+                                _ => instance,
+                            }
                         } else {
                             term.as_instance_and_args(tcx).unwrap().0
                         }
@@ -705,7 +918,14 @@ mod call_string_resolver {
                         try_resolve_function(tcx, def_id, param_env, generics).unwrap()
                     }
                 };
-                res
+                if res.def_id() == callee {
+                    return res;
+                }
+                self.pdg_constructor
+                    .polled_generators(this)
+                    .into_iter()
+                    .find(|generator| generator.def_id() == callee)
+                    .unwrap_or(res)
             })
         }
     }
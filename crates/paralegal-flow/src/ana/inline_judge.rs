@@ -1,12 +1,18 @@
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
-use flowistry_pdg_construction::{body_cache::BodyCache, CallInfo};
+use flowistry_pdg_construction::{
+    body_cache::{has_stored_bodies, BodyCache},
+    CallInfo, InteriorMutability,
+};
 use paralegal_spdg::{utils::write_sep, Identifier};
-use rustc_hash::FxHashSet;
-use rustc_hir::def_id::{CrateNum, DefId, LOCAL_CRATE};
+use rustc_hash::{FxHashMap, FxHashSet};
+use rustc_hir::{
+    def_id::{CrateNum, DefId, LOCAL_CRATE},
+    definitions::DefPathData,
+};
 use rustc_middle::ty::{
-    AssocKind, BoundVariableKind, Clause, ClauseKind, ImplPolarity, Instance, ParamEnv,
-    ProjectionPredicate, TraitPredicate,
+    AssocKind, BoundVariableKind, Clause, ClauseKind, ImplPolarity, Instance, InstanceDef,
+    ParamEnv, ProjectionPredicate, TraitPredicate,
 };
 use rustc_span::{Span, Symbol};
 use rustc_type_ir::TyKind;
@@ -14,7 +20,8 @@ use rustc_type_ir::TyKind;
 use crate::{
     ana::Print,
     ann::db::MarkerDatabase,
-    args::{InliningDepth, Stub},
+    args::{ChannelModel, InliningDepth, InliningOverride, InteriorMutabilityModel, Stub},
+    utils::InstanceExt,
     Args, MarkerCtx, TyCtxt,
 };
 
@@ -29,8 +36,110 @@ pub struct InlineJudge<'tcx> {
     opts: &'static Args,
     included_crates: FxHashSet<CrateNum>,
     tcx: TyCtxt<'tcx>,
+    /// Parsed [`BuildConfig::inlining`](crate::BuildConfig::inlining)
+    overrides: Vec<(PathPattern, &'static str, InliningOverride)>,
+    /// Memoized results of [`Self::inlining_override`]
+    override_cache: RefCell<FxHashMap<DefId, Option<InliningOverride>>>,
+    /// Parsed [`BuildConfig::interior_mutability`](crate::BuildConfig::interior_mutability)
+    interior_mutability: Vec<(PathPattern, &'static str, InteriorMutabilityModel)>,
+    /// Parsed [`BUILTIN_INTERIOR_MUTABILITY`]
+    builtin_interior_mutability: Vec<(PathPattern, InteriorMutabilityModel)>,
+    /// Memoized results of [`Self::interior_mutability`]
+    interior_mutability_cache: RefCell<FxHashMap<DefId, Option<InteriorMutability>>>,
+    /// Parsed [`BuildConfig::channels`](crate::BuildConfig::channels) followed
+    /// by [`BUILTIN_CHANNELS`]
+    channels: Vec<(PathPattern, ChannelModel)>,
+    /// Memoized results of [`Self::channel_model`]
+    channel_cache: RefCell<FxHashMap<DefId, Option<ChannelModel>>>,
+    body_cache: Rc<BodyCache<'tcx>>,
+    /// Crates we already warned about because bodies were missing
+    crates_missing_mir: RefCell<FxHashSet<CrateNum>>,
 }
 
+/// Models for the interior-mutable types of the standard library.
+const BUILTIN_INTERIOR_MUTABILITY: &[(&str, InteriorMutabilityModel)] = {
+    use InteriorMutabilityModel::*;
+    &[
+        ("core::cell::Cell::set", Set { target: 0 }),
+        ("core::cell::Cell::replace", Set { target: 0 }),
+        ("core::cell::Cell::swap", Update { target: 0 }),
+        ("core::cell::RefCell::borrow_mut", Guard),
+        ("core::cell::RefCell::try_borrow_mut", Guard),
+        ("core::cell::RefCell::replace", Set { target: 0 }),
+        ("core::cell::RefCell::replace_with", Update { target: 0 }),
+        ("core::cell::RefCell::swap", Update { target: 0 }),
+        ("std::sync::mutex::Mutex::lock", Guard),
+        ("std::sync::mutex::Mutex::try_lock", Guard),
+        ("std::sync::rwlock::RwLock::write", Guard),
+        ("std::sync::rwlock::RwLock::try_write", Guard),
+        ("core::sync::atomic::*::store", Set { target: 0 }),
+        ("core::sync::atomic::*::swap", Set { target: 0 }),
+        (
+            "core::sync::atomic::*::compare_exchange",
+            Update { target: 0 },
+        ),
+        (
+            "core::sync::atomic::*::compare_exchange_weak",
+            Update { target: 0 },
+        ),
+        ("core::sync::atomic::*::fetch_update", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_add", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_sub", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_and", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_nand", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_or", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_xor", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_max", Update { target: 0 }),
+        ("core::sync::atomic::*::fetch_min", Update { target: 0 }),
+    ]
+};
+
+/// Models for the channels of the standard library and `tokio`.
+const BUILTIN_CHANNELS: &[(&str, ChannelModel)] = {
+    use ChannelModel::*;
+    &[
+        ("std::sync::mpsc::Sender::send", Send { value: 1 }),
+        ("std::sync::mpsc::SyncSender::send", Send { value: 1 }),
+        ("std::sync::mpsc::SyncSender::try_send", Send { value: 1 }),
+        ("std::sync::mpsc::Receiver::recv", Receive),
+        ("std::sync::mpsc::Receiver::try_recv", Receive),
+        ("std::sync::mpsc::Receiver::recv_timeout", Receive),
+        (
+            "tokio::sync::mpsc::bounded::Sender::send",
+            Send { value: 1 },
+        ),
+        (
+            "tokio::sync::mpsc::bounded::Sender::try_send",
+            Send { value: 1 },
+        ),
+        (
+            "tokio::sync::mpsc::bounded::Sender::blocking_send",
+            Send { value: 1 },
+        ),
+        ("tokio::sync::mpsc::bounded::Receiver::recv", Receive),
+        ("tokio::sync::mpsc::bounded::Receiver::try_recv", Receive),
+        (
+            "tokio::sync::mpsc::bounded::Receiver::blocking_recv",
+            Receive,
+        ),
+        (
+            "tokio::sync::mpsc::unbounded::UnboundedSender::send",
+            Send { value: 1 },
+        ),
+        (
+            "tokio::sync::mpsc::unbounded::UnboundedReceiver::recv",
+            Receive,
+        ),
+        (
+            "tokio::sync::mpsc::unbounded::UnboundedReceiver::try_recv",
+            Receive,
+        ),
+        ("tokio::sync::oneshot::Sender::send", Send { value: 1 }),
+        ("tokio::sync::oneshot::Receiver::poll", Receive),
+        ("tokio::sync::oneshot::Receiver::try_recv", Receive),
+    ]
+};
+
 /// Describes the type of inlining to perform
 #[derive(strum::AsRefStr)]
 pub enum InlineJudgement {
@@ -38,6 +147,8 @@ pub enum InlineJudgement {
     Inline,
     /// Use a stub instead of the call
     UseStub(&'static Stub),
+    /// The call accesses an interior-mutable value
+    InteriorMutability(InteriorMutability),
     /// Abstract the call via type signature
     AbstractViaType(&'static str),
 }
@@ -54,16 +165,211 @@ impl<'tcx> InlineJudge<'tcx> {
             .crates(())
             .iter()
             .copied()
-            .filter(|cnum| included_crate_names.contains(&tcx.crate_name(*cnum)))
+            .filter(|cnum| {
+                // Crates from the body store are inlinable without `--include`
+                included_crate_names.contains(&tcx.crate_name(*cnum))
+                    || has_stored_bodies(tcx, *cnum)
+            })
             .chain(Some(LOCAL_CRATE))
             .collect::<FxHashSet<_>>();
         let marker_ctx =
-            MarkerDatabase::init(tcx, opts, body_cache, included_crates.clone()).into();
+            MarkerDatabase::init(tcx, opts, body_cache.clone(), included_crates.clone()).into();
+        let overrides = opts
+            .build_config()
+            .inlining
+            .iter()
+            .map(|(pattern, mode)| (PathPattern::parse(pattern), pattern.as_str(), *mode))
+            .collect();
+        let interior_mutability = opts
+            .build_config()
+            .interior_mutability
+            .iter()
+            .map(|(pattern, model)| (PathPattern::parse(pattern), pattern.as_str(), *model))
+            .collect();
+        let builtin_interior_mutability = BUILTIN_INTERIOR_MUTABILITY
+            .iter()
+            .map(|(pattern, model)| (PathPattern::parse(pattern), *model))
+            .collect();
+        let channels = opts
+            .build_config()
+            .channels
+            .iter()
+            .map(|(pattern, model)| (pattern.as_str(), *model))
+            .chain(BUILTIN_CHANNELS.iter().copied())
+            .map(|(pattern, model)| (PathPattern::parse(pattern), model))
+            .collect();
         Self {
             marker_ctx,
             included_crates,
             opts,
             tcx,
+            overrides,
+            override_cache: Default::default(),
+            interior_mutability,
+            builtin_interior_mutability,
+            interior_mutability_cache: Default::default(),
+            channels,
+            channel_cache: Default::default(),
+            body_cache,
+            crates_missing_mir: Default::default(),
+        }
+    }
+
+    /// Whether we would inline this call but cannot, because the body of the
+    /// callee was not dumped. This happens if the crate of the callee was not
+    /// compiled by paralegal-flow in this run, e.g. because the build is
+    /// stale. Warns once for each such crate.
+    fn is_missing_mir(&self, info: &CallInfo<'tcx, '_>) -> bool {
+        let def_id = info.callee.def_id();
+        if def_id.is_local()
+            || !matches!(info.callee.def, InstanceDef::Item(_))
+            || self.tcx.is_foreign_item(def_id)
+            || self.body_cache.try_get(def_id).is_some()
+        {
+            return false;
+        }
+        if self.crates_missing_mir.borrow_mut().insert(def_id.krate) {
+            let name = self.tcx.crate_name(def_id.krate);
+            let mut diagnostic = self.tcx.sess.struct_span_warn(
+                info.span,
+                format!("no MIR bodies found for crate `{name}`, calls into it are approximated"),
+            );
+            diagnostic.help(format!(
+                "make sure `{name}` is passed to `--include` and rebuild it with \
+                 `cargo clean -p {name}`"
+            ));
+            diagnostic.emit();
+        }
+        true
+    }
+
+    /// The interior mutability model for this function, if any. Configured
+    /// models take precedence over the built-in ones. Configured patterns
+    /// that match the function with different models are reported and none
+    /// of them is used.
+    pub(crate) fn interior_mutability(
+        &self,
+        def_id: DefId,
+        span: Span,
+    ) -> Option<InteriorMutability> {
+        if let Some(known) = self.interior_mutability_cache.borrow().get(&def_id) {
+            return *known;
+        }
+        let path = item_path(self.tcx, def_id);
+        let matching = self
+            .interior_mutability
+            .iter()
+            .filter(|(pattern, ..)| pattern.matches(&path))
+            .collect::<Vec<_>>();
+        let model = match matching.as_slice() {
+            [] => self
+                .builtin_interior_mutability
+                .iter()
+                .find(|(pattern, _)| pattern.matches(&path))
+                .map(|(_, model)| *model),
+            [(_, _, model), rest @ ..] if rest.iter().all(|(_, _, other)| other == model) => {
+                Some(*model)
+            }
+            _ => {
+                let mut diagnostic = self.struct_config_diagnostic(
+                    span,
+                    format!(
+                        "conflicting interior mutability models for '{}'",
+                        self.tcx.def_path_str(def_id)
+                    ),
+                );
+                for (_, pattern, model) in matching {
+                    diagnostic.note(format!("'{pattern}' requests {model:?}"));
+                }
+                diagnostic.emit();
+                None
+            }
+        };
+        let result = model.map(|model| match model {
+            InteriorMutabilityModel::Set { target } => InteriorMutability::Set { target },
+            InteriorMutabilityModel::Update { target } => InteriorMutability::Update { target },
+            InteriorMutabilityModel::Guard => InteriorMutability::Guard,
+        });
+        self.interior_mutability_cache
+            .borrow_mut()
+            .insert(def_id, result);
+        result
+    }
+
+    /// The channel model for this function, if any. Configured models take
+    /// precedence over the built-in ones.
+    pub fn channel_model(&self, def_id: DefId) -> Option<ChannelModel> {
+        if let Some(known) = self.channel_cache.borrow().get(&def_id) {
+            return *known;
+        }
+        let path = item_path(self.tcx, def_id);
+        let result = self
+            .channels
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&path))
+            .map(|(_, model)| *model);
+        self.channel_cache.borrow_mut().insert(def_id, result);
+        result
+    }
+
+    /// The override configured for this function, if any. Reports conflicting
+    /// overrides and overrides that cannot be honored (once per function).
+    fn inlining_override(&self, def_id: DefId, span: Span) -> Option<InliningOverride> {
+        if let Some(known) = self.override_cache.borrow().get(&def_id) {
+            return *known;
+        }
+        let path = item_path(self.tcx, def_id);
+        let matching = self
+            .overrides
+            .iter()
+            .filter(|(pattern, ..)| pattern.matches(&path))
+            .collect::<Vec<_>>();
+        let result = match matching.as_slice() {
+            [] => None,
+            [(_, _, mode), rest @ ..] if rest.iter().all(|(_, _, other)| other == mode) => {
+                Some(*mode)
+            }
+            _ => {
+                let mut diagnostic = self.struct_config_diagnostic(
+                    span,
+                    format!(
+                        "conflicting inlining overrides for '{}'",
+                        self.tcx.def_path_str(def_id)
+                    ),
+                );
+                for (_, pattern, mode) in matching {
+                    diagnostic.note(format!("'{pattern}' requests {mode:?}"));
+                }
+                diagnostic.emit();
+                None
+            }
+        };
+        if result == Some(InliningOverride::Inline) && !self.included_crates.contains(&def_id.krate)
+        {
+            self.struct_config_diagnostic(
+                span,
+                format!(
+                    "cannot inline '{}' as requested by the build configuration, crate '{}' is not included",
+                    self.tcx.def_path_str(def_id),
+                    self.tcx.crate_name(def_id.krate)
+                ),
+            )
+            .emit();
+        }
+        self.override_cache.borrow_mut().insert(def_id, result);
+        result
+    }
+
+    fn struct_config_diagnostic(
+        &self,
+        span: Span,
+        msg: String,
+    ) -> rustc_errors::DiagnosticBuilder<'tcx, ()> {
+        let sess = self.tcx.sess;
+        if self.opts.relaxed() {
+            sess.struct_span_warn(span, msg)
+        } else {
+            sess.struct_span_err(span, msg).forget_guarantee()
         }
     }
 
@@ -78,8 +384,8 @@ impl<'tcx> InlineJudge<'tcx> {
     /// Should we perform inlining on this function?
     pub fn should_inline(&self, info: &CallInfo<'tcx, '_>) -> InlineJudgement {
         let marker_target = info.async_parent.unwrap_or(info.callee);
-        let marker_target_def_id = marker_target.def_id();
-        if let Some(model) = self.marker_ctx().has_stub(marker_target_def_id) {
+        let marker_target_def_id = marker_target.marker_target();
+        if let Some(model) = self.marker_ctx().applicable_stub(marker_target, info.span) {
             // If we're replacing an async function skip the poll call.
             //
             // I tried to have it replace the poll call only but that didn't seem to work.
@@ -89,16 +395,57 @@ impl<'tcx> InlineJudge<'tcx> {
                 InlineJudgement::UseStub(model)
             };
         }
+        if self.channel_model(marker_target_def_id).is_some() {
+            // The channel ends are connected when the graph is converted,
+            // which relies on the arguments of the call being approximated.
+            return InlineJudgement::AbstractViaType("channel operation");
+        }
+        if info.async_parent.is_none() {
+            if let Some(model) = self.interior_mutability(info.callee.def_id(), info.span) {
+                return InlineJudgement::InteriorMutability(model);
+            }
+        }
         let is_marked = self.marker_ctx.is_marked(marker_target_def_id);
+        let forced = self.inlining_override(marker_target_def_id, info.span);
+        let mut missing_mir = false;
+        // Overrides for a specific function take precedence over the crate
+        // and marker based defaults, but we cannot inline without a body.
         let judgement = match self.opts.anactrl().inlining_depth() {
+            _ if forced == Some(InliningOverride::Abstract) => {
+                InlineJudgement::AbstractViaType("abstracted by configuration")
+            }
+            _ if forced == Some(InliningOverride::Inline) => {
+                if self.is_missing_mir(info) {
+                    missing_mir = true;
+                    InlineJudgement::AbstractViaType("missing MIR")
+                } else {
+                    InlineJudgement::Inline
+                }
+            }
             _ if !self.included_crates.contains(&marker_target_def_id.krate) => {
                 InlineJudgement::AbstractViaType("inlining for crate disabled")
             }
             _ if is_marked => InlineJudgement::AbstractViaType("marked"),
+            _ if self.is_missing_mir(info) => {
+                missing_mir = true;
+                InlineJudgement::AbstractViaType("missing MIR")
+            }
+            // Each candidate of a dispatched call is judged separately.
+            _ if self.opts.anactrl().closed_world_dyn_dispatch()
+                && matches!(marker_target.def, InstanceDef::Virtual(..)) =>
+            {
+                InlineJudgement::Inline
+            }
+            // If bodies are missing we cannot tell whether markers are
+            // reachable and inline to approximate only the unknown calls.
             InliningDepth::Adaptive
                 if self
                     .marker_ctx
-                    .has_transitive_reachable_markers(marker_target) =>
+                    .has_transitive_reachable_markers(marker_target)
+                    || !self
+                        .marker_ctx
+                        .reachable_missing_bodies(marker_target)
+                        .is_empty() =>
             {
                 InlineJudgement::Inline
             }
@@ -106,16 +453,20 @@ impl<'tcx> InlineJudge<'tcx> {
             InliningDepth::Shallow => {
                 InlineJudgement::AbstractViaType("shallow inlining configured")
             }
-            InliningDepth::Unconstrained => InlineJudgement::Inline,
+            // The depth is limited by the PDG constructor
+            InliningDepth::Unconstrained | InliningDepth::Bounded(_) => InlineJudgement::Inline,
         };
         if let InlineJudgement::AbstractViaType(reason) = judgement {
-            let emit_err = !(is_marked || self.opts.relaxed());
+            // Explicitly requested abstractions are only warned about, as are
+            // calls we cannot inline because the body is missing.
+            let emit_err = !(is_marked || forced.is_some() || missing_mir || self.opts.relaxed());
             self.ensure_is_safe_to_approximate(
                 info.param_env,
                 info.callee,
                 info.span,
                 emit_err,
                 reason,
+                Some(info),
             )
         }
         judgement
@@ -125,6 +476,9 @@ impl<'tcx> InlineJudge<'tcx> {
         &self.marker_ctx
     }
 
+    /// Check that approximating the call of `resolved` cannot lose markers.
+    /// If the call site is given, function pointers passed to the call are
+    /// resolved with [`CallInfo::fn_pointer_candidates`].
     pub fn ensure_is_safe_to_approximate(
         &self,
         param_env: ParamEnv<'tcx>,
@@ -132,6 +486,7 @@ impl<'tcx> InlineJudge<'tcx> {
         call_span: Span,
         emit_err: bool,
         reason: &'static str,
+        call_site: Option<&CallInfo<'tcx, '_>>,
     ) {
         SafetyChecker {
             tcx: self.tcx(),
@@ -141,11 +496,74 @@ impl<'tcx> InlineJudge<'tcx> {
             call_span,
             marker_ctx: self.marker_ctx.clone(),
             reason,
+            call_site,
         }
         .check()
     }
 }
 
+/// A pattern from [`BuildConfig::inlining`](crate::BuildConfig::inlining) or
+/// one of the other path based options of the build configuration.
+pub(crate) struct PathPattern {
+    /// `None` is a `*` that matches any one segment
+    segments: Vec<Option<Symbol>>,
+    /// Ends in `*`, matches any item below [`Self::segments`]
+    prefix: bool,
+}
+
+impl PathPattern {
+    pub(crate) fn parse(pattern: &str) -> Self {
+        let mut segments = pattern.split("::").collect::<Vec<_>>();
+        let prefix = segments.last() == Some(&"*");
+        if prefix {
+            segments.pop();
+        }
+        Self {
+            segments: segments
+                .into_iter()
+                .map(|segment| (segment != "*").then(|| Symbol::intern(segment)))
+                .collect(),
+            prefix,
+        }
+    }
+
+    pub(crate) fn matches(&self, path: &[Symbol]) -> bool {
+        let length_matches = if self.prefix {
+            path.len() > self.segments.len()
+        } else {
+            path.len() == self.segments.len()
+        };
+        length_matches
+            && self
+                .segments
+                .iter()
+                .zip(path)
+                .all(|(pattern, segment)| pattern.map_or(true, |pattern| pattern == *segment))
+    }
+}
+
+/// The path of an item as matched by a [`PathPattern`]: the crate name,
+/// followed by the names of the enclosing modules and items. Impl blocks are
+/// named after their self type.
+pub(crate) fn item_path(tcx: TyCtxt, def_id: DefId) -> Vec<Symbol> {
+    let mut segments = vec![];
+    let mut current = Some(def_id);
+    while let Some(id) = current {
+        match tcx.def_key(id).disambiguated_data.data {
+            DefPathData::CrateRoot => segments.push(tcx.crate_name(id.krate)),
+            DefPathData::Impl => {
+                if let Some(adt) = tcx.type_of(id).instantiate_identity().ty_adt_def() {
+                    segments.push(tcx.item_name(adt.did()))
+                }
+            }
+            data => segments.extend(data.get_opt_name()),
+        }
+        current = tcx.opt_parent(id);
+    }
+    segments.reverse();
+    segments
+}
+
 /// A check for the abstraction safety of a given instance.
 ///
 /// It looks at each trait predicate on the function and how they are
@@ -154,7 +572,7 @@ impl<'tcx> InlineJudge<'tcx> {
 /// message is emitted.
 ///
 /// The main entrypoint is [`Self::check`].
-struct SafetyChecker<'tcx> {
+struct SafetyChecker<'tcx, 'a> {
     tcx: TyCtxt<'tcx>,
     /// Emit errors if `true`, otherwise emit warnings
     emit_err: bool,
@@ -165,9 +583,11 @@ struct SafetyChecker<'tcx> {
     marker_ctx: MarkerCtx<'tcx>,
     /// Why a call we check wasn't inlined
     reason: &'static str,
+    /// The call, if known
+    call_site: Option<&'a CallInfo<'tcx, 'a>>,
 }
 
-impl<'tcx> SafetyChecker<'tcx> {
+impl<'tcx> SafetyChecker<'tcx, '_> {
     /// Emit an error or a warning with some preformatted messaging.
     fn err(&self, s: &str, span: Span) {
         let sess = self.tcx.sess;
@@ -233,13 +653,17 @@ impl<'tcx> SafetyChecker<'tcx> {
         };
 
         if self.tcx.is_fn_trait(trait_ref.def_id) {
-            let instance = match self_ty.kind() {
-                TyKind::Closure(id, args) | TyKind::FnDef(id, args) => {
-                    Instance::resolve(self.tcx, ParamEnv::reveal_all(), *id, args)
-                }
-                TyKind::FnPtr(_) => {
-                    self.err(&format!("unresolvable function pointer {self_ty:?}"), span);
-                    return;
+            let callees = match self_ty.kind() {
+                TyKind::Closure(id, args) | TyKind::FnDef(id, args) => vec![(*id, *args)],
+                TyKind::FnPtr(sig) => {
+                    let Some(candidates) = self
+                        .call_site
+                        .and_then(|info| info.fn_pointer_candidates(self.tcx, self_ty, *sig))
+                    else {
+                        self.err(&format!("unresolvable function pointer {self_ty:?}"), span);
+                        return;
+                    };
+                    candidates
                 }
                 _ => {
                     self.err(
@@ -250,16 +674,19 @@ impl<'tcx> SafetyChecker<'tcx> {
                     );
                     return;
                 }
-            }
-            .unwrap()
-            .unwrap();
-            let markers = self.marker_ctx.get_reachable_markers(instance);
-            if !markers.is_empty() {
-                self.err_markers(
-                    &format!("closure {instance:?} is not approximation safe"),
-                    markers,
-                    span,
-                );
+            };
+            for (id, args) in callees {
+                let instance = Instance::resolve(self.tcx, ParamEnv::reveal_all(), id, args)
+                    .unwrap()
+                    .unwrap();
+                let markers = self.marker_ctx.get_reachable_markers(instance);
+                if !markers.is_empty() {
+                    self.err_markers(
+                        &format!("closure {instance:?} is not approximation safe"),
+                        markers,
+                        span,
+                    );
+                }
             }
         } else {
             self.tcx
@@ -311,6 +738,21 @@ impl<'tcx> SafetyChecker<'tcx> {
 
     /// Main entry point for the check
     fn check(&self) {
+        let missing = self.marker_ctx.reachable_missing_bodies(self.resolved);
+        // A callee without a body is already reported when it is judged.
+        if !missing.is_empty() && missing != [self.resolved.def_id()] {
+            let names = missing
+                .iter()
+                .map(|def_id| format!("'{}'", self.tcx.def_path_str(*def_id)))
+                .collect::<Vec<_>>();
+            self.err(
+                &format!(
+                    "unknown reachable markers, the bodies of {} are missing",
+                    names.join(", ")
+                ),
+                self.tcx.def_span(self.resolved.def_id()),
+            );
+        }
         self.tcx
             .predicates_of(self.resolved.def_id())
             .instantiate(self.tcx, self.resolved.args)
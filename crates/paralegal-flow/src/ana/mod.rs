@@ -6,40 +6,47 @@
 
 use crate::{
     ann::{Annotation, MarkerAnnotation},
-    args::Stub,
+    args::{ClosureArgumentSource, Stub},
     desc::*,
-    discover::FnToAnalyze,
+    discover::{Discovery, FnToAnalyze},
     stats::{Stats, TimedStat},
     utils::*,
     HashMap, HashSet, LogLevelConfig, MarkerCtx,
 };
 
-use std::{rc::Rc, time::Instant};
+use std::{cell::RefCell, collections::hash_map::Entry, rc::Rc, time::Instant};
 
 use anyhow::Result;
 use either::Either;
 use flowistry::mir::FlowistryInput;
 use flowistry_pdg_construction::{
-    body_cache::BodyCache, calling_convention::CallingConvention, CallChangeCallback, CallChanges,
-    CallInfo, InlineMissReason, MemoPdgConstructor, SkipCall,
+    body_cache::BodyCache,
+    calling_convention::{CallingConvention, ClosureArgument, ClosureArguments},
+    graph::DepGraph,
+    CallChangeCallback, CallChanges, CallInfo, CallSummary, InlineMissReason, InteriorMutability,
+    MemoPdgConstructor, SkipCall, UnsafeOperation,
 };
 use inline_judge::InlineJudgement;
 use itertools::Itertools;
 use petgraph::visit::GraphBase;
 
-use rustc_hir::{self as hir, def, def_id::DefId};
+use rustc_hir::{
+    self as hir, def,
+    def_id::{DefId, LOCAL_CRATE},
+};
 use rustc_middle::{
     mir::{Location, Operand},
-    ty::{Instance, ParamEnv, TyCtxt},
+    ty::{self, Instance, ParamEnv, TyCtxt},
 };
 use rustc_span::{ErrorGuaranteed, FileNameDisplayPreference, Span as RustSpan, Symbol};
 
 mod graph_converter;
 mod inline_judge;
 
-use graph_converter::GraphConverter;
+use graph_converter::{CallStringResolver, GraphConverter};
 
 pub use self::inline_judge::InlineJudge;
+pub(crate) use self::inline_judge::{item_path, PathPattern};
 
 /// Read-only database of information the analysis needs.
 ///
@@ -50,6 +57,7 @@ pub struct SPDGGenerator<'tcx> {
     stats: Stats,
     pdg_constructor: MemoPdgConstructor<'tcx>,
     judge: Rc<InlineJudge<'tcx>>,
+    inlining_stats: Rc<RefCell<InliningStats<'tcx>>>,
 }
 
 impl<'tcx> SPDGGenerator<'tcx> {
@@ -61,19 +69,25 @@ impl<'tcx> SPDGGenerator<'tcx> {
         stats: Stats,
     ) -> Self {
         let judge = Rc::new(inline_judge);
+        let inlining_stats: Rc<RefCell<InliningStats>> = Default::default();
         let mut pdg_constructor = MemoPdgConstructor::new_with_cache(tcx, body_cache);
         pdg_constructor
             .with_call_change_callback(MyCallback {
                 judge: judge.clone(),
                 tcx,
+                stats: inlining_stats.clone(),
             })
-            .with_dump_mir(opts.dbg().dump_mir());
+            .with_dump_mir(opts.dbg().dump_mir())
+            .with_closed_world_dyn_dispatch(opts.anactrl().closed_world_dyn_dispatch())
+            .with_call_string_limit(opts.anactrl().inlining_depth().call_string_limit())
+            .with_conservative_unsafe(opts.anactrl().conservative_unsafe());
         Self {
             pdg_constructor,
             opts,
             tcx,
             stats,
             judge,
+            inlining_stats,
         }
     }
 
@@ -88,7 +102,7 @@ impl<'tcx> SPDGGenerator<'tcx> {
     fn handle_target(
         &mut self,
         //_hash_verifications: &mut HashVerifications,
-        target: &FnToAnalyze,
+        target: &FnToAnalyze<'tcx>,
         known_def_ids: &mut impl Extend<DefId>,
     ) -> Result<(Endpoint, SPDG)> {
         info!("Handling target {}", self.tcx.def_path_str(target.def_id));
@@ -104,8 +118,13 @@ impl<'tcx> SPDGGenerator<'tcx> {
     /// once for every function in `self.functions_to_analyze` after doing some
     /// other setup necessary for the flow graph creation.
     ///
-    /// Should only be called after the visit.
-    pub fn analyze(&mut self, targets: Vec<FnToAnalyze>) -> Result<ProgramDescription> {
+    /// Should only be called after the visit. `discovered` are the functions
+    /// the discovery rules selected, to be reported in the output.
+    pub fn analyze(
+        &mut self,
+        targets: Vec<FnToAnalyze<'tcx>>,
+        discovered: Vec<Discovery>,
+    ) -> Result<ProgramDescription> {
         if let LogLevelConfig::Targeted(s) = self.opts.direct_debug() {
             assert!(
                 targets.iter().any(|target| target.name().as_str() == s),
@@ -132,10 +151,11 @@ impl<'tcx> SPDGGenerator<'tcx> {
                     )
                 })
             })
-            .collect::<Result<HashMap<Endpoint, SPDG>>>()
+            .collect::<Result<Vec<(Endpoint, SPDG)>>>()
             .map(|controllers| {
                 let start = Instant::now();
-                let desc = self.make_program_description(controllers, known_def_ids, &targets);
+                let desc =
+                    self.make_program_description(controllers, known_def_ids, &targets, discovered);
                 self.stats
                     .record_timed(TimedStat::Conversion, start.elapsed());
                 desc
@@ -145,14 +165,38 @@ impl<'tcx> SPDGGenerator<'tcx> {
     /// Given the PDGs and a record of all [`DefId`]s we've seen, compile
     /// auxillary information the policies will need into the artifact to be
     /// emitted.
+    ///
+    /// `graphs` holds the PDG of each of the `targets`. The PDGs of the
+    /// instantiations of a function that was selected with more than one
+    /// instantiation are merged into a single controller, see
+    /// [`merge_instantiation`].
     fn make_program_description(
         &self,
-        controllers: HashMap<Endpoint, SPDG>,
+        graphs: Vec<(Endpoint, SPDG)>,
         mut known_def_ids: HashSet<DefId>,
-        _targets: &[FnToAnalyze],
+        targets: &[FnToAnalyze<'tcx>],
+        discovered: Vec<Discovery>,
     ) -> ProgramDescription {
         let tcx = self.tcx;
 
+        let approximations = self.collect_approximations(&graphs, targets);
+        known_def_ids.extend(
+            approximations
+                .iter()
+                .flat_map(|a| a.callee.into_iter().chain(a.missing_bodies.iter().copied())),
+        );
+        let unsafe_approximations = self.collect_unsafe_approximations(&graphs, targets);
+
+        let mut controllers: HashMap<Endpoint, SPDG> = HashMap::new();
+        for (controller, spdg) in graphs {
+            match controllers.entry(controller) {
+                Entry::Occupied(mut merged) => merge_instantiation(merged.get_mut(), spdg),
+                Entry::Vacant(slot) => {
+                    slot.insert(spdg);
+                }
+            }
+        }
+
         let instruction_info = self.collect_instruction_info(&controllers);
 
         let inlined_functions = instruction_info
@@ -162,6 +206,21 @@ impl<'tcx> SPDGGenerator<'tcx> {
 
         known_def_ids.extend(&inlined_functions);
 
+        let discovered_controllers = discovered
+            .into_iter()
+            .map(|discovery| {
+                let function = discovery.function.to_def_id();
+                DiscoveredController {
+                    function,
+                    rule: discovery.rule,
+                    via: discovery.via,
+                    span: src_loc_for_span(tcx.def_span(function), tcx),
+                    analyzed: controllers.contains_key(&function),
+                }
+            })
+            .collect::<Vec<_>>();
+        known_def_ids.extend(discovered_controllers.iter().map(|d| d.function));
+
         let type_info = self.collect_type_info();
         known_def_ids.extend(type_info.keys());
         let def_info = known_def_ids
@@ -169,10 +228,37 @@ impl<'tcx> SPDGGenerator<'tcx> {
             .map(|id| (*id, def_info_for_item(*id, self.marker_ctx(), tcx)))
             .collect();
 
-        let dedup_locs = 0;
-        let dedup_functions = 0;
-        let seen_locs = 0;
-        let seen_functions = 0;
+        let dedup_functions = inlined_functions.len() as u32;
+        let dedup_locs = inlined_functions.iter().map(|f| self.body_lines(*f)).sum();
+        // Functions we approximated have been inspected for markers.
+        let approximated_functions = approximations
+            .iter()
+            .filter_map(|approximation| approximation.callee)
+            .filter(|callee| !inlined_functions.contains(callee))
+            .collect::<HashSet<_>>();
+        let seen_functions = dedup_functions + approximated_functions.len() as u32;
+        let seen_locs = dedup_locs
+            + approximated_functions
+                .iter()
+                .filter_map(|f| f.as_local())
+                .map(|f| self.span_lines(tcx.hir().span_with_body(tcx.local_def_id_to_hir_id(f))))
+                .sum::<u32>();
+        let analyzed_spans = inlined_functions
+            .iter()
+            .filter_map(|f| f.as_local())
+            .map(|f| {
+                let body = self.pdg_constructor.body_for_def_id(f.to_def_id()).body();
+                (f.to_def_id(), src_loc_for_span(body_span(body), tcx))
+            })
+            .collect();
+        let crates = std::iter::once(LOCAL_CRATE)
+            .chain(tcx.crates(()).iter().copied())
+            .map(|krate| CrateInfo {
+                number: krate.as_u32(),
+                name: Identifier::new(tcx.crate_name(krate)),
+                stable_id: tcx.stable_crate_id(krate).as_u64(),
+            })
+            .collect();
 
         type_info_sanity_check(&controllers, &type_info);
         ProgramDescription {
@@ -190,7 +276,206 @@ impl<'tcx> SPDGGenerator<'tcx> {
             dedup_functions,
             seen_functions,
             seen_locs,
-            analyzed_spans: Default::default(),
+            analyzed_spans,
+            approximations,
+            unsafe_approximations,
+            crates,
+            discovered_controllers,
+        }
+    }
+
+    /// Find the operations that were approximated conservatively in the PDGs
+    /// of the controllers, like [`Self::collect_approximations`].
+    fn collect_unsafe_approximations(
+        &self,
+        graphs: &[(Endpoint, SPDG)],
+        targets: &[FnToAnalyze<'tcx>],
+    ) -> Vec<UnsafeApproximation> {
+        let stats = self.inlining_stats.borrow();
+        let mut seen = HashSet::new();
+        targets
+            .iter()
+            .zip(graphs)
+            .flat_map(|(target, (controller, spdg))| {
+                let controller = *controller;
+                let resolver = self.call_string_resolver(target);
+                graph_call_strings(spdg)
+                    .into_iter()
+                    .filter_map(|call_string| {
+                        let (operation, span) = stats
+                            .unsafe_operations
+                            .get(&call_string.leaf())?
+                            .get(&resolver.resolve(call_string))?;
+                        Some(UnsafeApproximation {
+                            controller,
+                            operation: Identifier::new_intern(operation),
+                            call_string,
+                            span: src_loc_for_span(*span, self.tcx),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|a| seen.insert((a.controller, a.call_string, a.operation)))
+            .collect()
+    }
+
+    /// Find the approximated calls in the PDGs of the controllers. Because
+    /// PDGs are shared between controllers we match the locations at which
+    /// calls were approximated against the call strings in each graph. Calls
+    /// that do not leave any node or edge in the graph are not reported.
+    ///
+    /// `graphs` holds the PDG of each target. A call approximated the same
+    /// way in more than one instantiation of a controller is reported once.
+    fn collect_approximations(
+        &self,
+        graphs: &[(Endpoint, SPDG)],
+        targets: &[FnToAnalyze<'tcx>],
+    ) -> Vec<Approximation> {
+        let stats = self.inlining_stats.borrow();
+        let mut seen = HashSet::new();
+        targets
+            .iter()
+            .zip(graphs)
+            .flat_map(|(target, (controller, spdg))| {
+                let controller = *controller;
+                self.approximated_calls(&stats, target, &graph_call_strings(spdg))
+                    .into_iter()
+                    .map(|(call_string, call)| Approximation {
+                        controller,
+                        callee: call.callee,
+                        reason: Identifier::new_intern(call.reason),
+                        call_string,
+                        span: src_loc_for_span(call.span, self.tcx),
+                        markers_reachable: call.markers_reachable,
+                        missing_bodies: call.missing_bodies.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|a| seen.insert((a.controller, a.call_string, a.callee, a.reason)))
+            .collect()
+    }
+
+    /// The approximated calls at `call_strings`, the call sites in the graph
+    /// of `target`. Each call string is resolved to the instance of the
+    /// function containing the call, so that calls approximated only in
+    /// another instantiation of a generic function are left out.
+    ///
+    /// For async entrypoints this includes the prelude of the wrapper, which
+    /// is reported at the root of the call strings.
+    fn approximated_calls<'s>(
+        &self,
+        stats: &'s InliningStats<'tcx>,
+        target: &FnToAnalyze<'tcx>,
+        call_strings: &HashSet<CallString>,
+    ) -> Vec<(CallString, &'s ApproximatedCall<'tcx>)> {
+        let resolver = self.call_string_resolver(target);
+        let roots = if resolver.entrypoint_is_async() {
+            call_strings
+                .iter()
+                .map(|call_string| CallString::single(call_string.root()))
+                .filter(|root| !call_strings.contains(root))
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        call_strings
+            .iter()
+            .chain(&roots)
+            .flat_map(|&call_string| {
+                let calls = stats
+                    .approximated
+                    .get(&call_string.leaf())
+                    .map_or(&[][..], Vec::as_slice);
+                let caller = (!calls.is_empty()).then(|| resolver.resolve(call_string));
+                calls
+                    .iter()
+                    .filter(move |call| Some(call.caller) == caller)
+                    .map(move |call| (call_string, call))
+            })
+            .collect()
+    }
+
+    /// Resolves the call strings in the graph of `target` to the instances
+    /// containing their locations.
+    fn call_string_resolver<'s>(
+        &'s self,
+        target: &'s FnToAnalyze<'tcx>,
+    ) -> CallStringResolver<'tcx, 's> {
+        CallStringResolver::new(
+            self.tcx,
+            target.def_id.to_def_id(),
+            &target.instantiation,
+            &self.pdg_constructor,
+            self.marker_ctx().clone(),
+        )
+    }
+
+    /// Number of source lines spanned by the body of this function.
+    fn body_lines(&self, function: DefId) -> u32 {
+        self.span_lines(body_span(
+            self.pdg_constructor.body_for_def_id(function).body(),
+        ))
+    }
+
+    fn span_lines(&self, span: RustSpan) -> u32 {
+        let (_, start_line, _, end_line, _) =
+            self.tcx.sess.source_map().span_to_location_info(span);
+        (end_line + 1).saturating_sub(start_line) as u32
+    }
+
+    /// Forget the PDGs constructed for the last controller. Call before
+    /// constructing the PDG for a new controller.
+    fn start_controller_stats(&self) {
+        self.inlining_stats.borrow_mut().constructed.clear();
+    }
+
+    /// Compute the statistics for the PDG of `controller`. Constructed PDGs
+    /// count the work performed since the last call to
+    /// [`Self::start_controller_stats`], the rest is determined by the call
+    /// strings in the final graph, so that callee PDGs served from the cache
+    /// count the same as freshly constructed ones.
+    fn controller_stats(&self, target: &FnToAnalyze<'tcx>, pdg: &DepGraph<'tcx>) -> SPDGStats {
+        let call_strings = pdg
+            .graph
+            .node_weights()
+            .map(|n| n.at)
+            .chain(pdg.graph.edge_weights().map(|e| e.at))
+            .collect::<HashSet<_>>();
+        let functions = call_strings
+            .iter()
+            .flat_map(|at| at.iter().map(|loc| loc.function).collect::<Vec<_>>())
+            .collect::<HashSet<_>>();
+        // Every caller prefix of a call string is a call site at which the
+        // function of the next location was inlined.
+        let mut inlinings = HashSet::new();
+        for at in &call_strings {
+            let mut at = *at;
+            while let Some(caller) = at.caller() {
+                inlinings.insert((caller, at.leaf().function));
+                at = caller;
+            }
+        }
+        let stats = self.inlining_stats.borrow();
+        let analyzed = stats
+            .constructed
+            .iter()
+            .map(|i| i.def_id())
+            .chain([target.def_id.to_def_id()])
+            .collect::<Vec<_>>();
+        let mut approximations = HashMap::new();
+        for (_, call) in self.approximated_calls(&stats, target, &call_strings) {
+            *approximations
+                .entry(Identifier::new_intern(call.reason))
+                .or_insert(0) += 1;
+        }
+        SPDGStats {
+            unique_locs: functions.iter().map(|f| self.body_lines(*f)).sum(),
+            unique_functions: functions.len() as u32,
+            analyzed_locs: analyzed.iter().map(|f| self.body_lines(*f)).sum(),
+            analyzed_functions: analyzed.len() as u32,
+            inlinings_performed: inlinings.len() as u32,
+            approximations,
+            ..Default::default()
         }
     }
 
@@ -253,6 +538,7 @@ impl<'tcx> SPDGGenerator<'tcx> {
                         kind,
                         span: src_loc_for_span(rust_span, self.tcx),
                         description: Identifier::new_intern(&description),
+                        call_candidates: self.pdg_constructor.call_candidates(i).into(),
                     },
                 )
             })
@@ -267,16 +553,22 @@ impl<'tcx> SPDGGenerator<'tcx> {
             .filter(|(id, _)| def_kind_for_item(*id, self.tcx).is_type())
             .into_grouping_map()
             .fold_with(
-                |id, _| (format!("{id:?}"), vec![], vec![]),
+                |id, _| (format!("{id:?}"), vec![], vec![], HashMap::new()),
                 |mut desc, _, ann| {
                     match ann {
-                        Either::Right(MarkerAnnotation { refinement, marker })
+                        Either::Right(MarkerAnnotation {
+                            refinement,
+                            marker,
+                            propagate,
+                        })
                         | Either::Left(Annotation::Marker(MarkerAnnotation {
                             refinement,
                             marker,
+                            propagate,
                         })) => {
                             assert!(refinement.on_self());
-                            desc.2.push(*marker)
+                            desc.2.push(*marker);
+                            desc.3.insert(*marker, *propagate);
                         }
                         Either::Left(Annotation::OType(id)) => desc.1.push(*id),
                         _ => panic!("Unexpected type of annotation {ann:?}"),
@@ -285,13 +577,14 @@ impl<'tcx> SPDGGenerator<'tcx> {
                 },
             )
             .into_iter()
-            .map(|(k, (rendering, otypes, markers))| {
+            .map(|(k, (rendering, otypes, markers, propagation))| {
                 (
                     k,
                     TypeDescription {
                         rendering,
                         otypes: otypes.into(),
                         markers,
+                        propagation,
                     },
                 )
             })
@@ -330,6 +623,67 @@ fn src_loc_for_span(span: RustSpan, tcx: TyCtxt) -> Span {
     }
 }
 
+/// The call strings of all nodes and edges in the graph.
+/// Add the PDG of another instantiation of the same controller to `spdg`. The
+/// graphs are kept disjoint, so the merged controller has a flow if any of its
+/// instantiations has it. Statistics are added up per instantiation.
+fn merge_instantiation(spdg: &mut SPDG, other: SPDG) {
+    let offset = spdg.graph.node_count();
+    let shift = |node: Node| Node::new(node.index() + offset);
+    let (nodes, edges) = other.graph.into_nodes_edges();
+    for node in nodes {
+        spdg.graph.add_node(node.weight);
+    }
+    for edge in edges {
+        spdg.graph
+            .add_edge(shift(edge.source()), shift(edge.target()), edge.weight);
+    }
+    spdg.markers.extend(
+        other
+            .markers
+            .into_iter()
+            .map(|(node, markers)| (shift(node), markers)),
+    );
+    spdg.type_assigns.extend(
+        other
+            .type_assigns
+            .into_iter()
+            .map(|(node, types)| (shift(node), types)),
+    );
+    spdg.arguments = spdg
+        .arguments
+        .iter()
+        .copied()
+        .chain(other.arguments.iter().copied().map(shift))
+        .collect();
+    spdg.return_ = spdg
+        .return_
+        .iter()
+        .copied()
+        .chain(other.return_.iter().copied().map(shift))
+        .collect();
+    let stats = &mut spdg.statistics;
+    let other_stats = other.statistics;
+    stats.unique_locs += other_stats.unique_locs;
+    stats.unique_functions += other_stats.unique_functions;
+    stats.analyzed_locs += other_stats.analyzed_locs;
+    stats.analyzed_functions += other_stats.analyzed_functions;
+    stats.inlinings_performed += other_stats.inlinings_performed;
+    stats.construction_time += other_stats.construction_time;
+    stats.conversion_time += other_stats.conversion_time;
+    for (reason, count) in other_stats.approximations {
+        *stats.approximations.entry(reason).or_insert(0) += count;
+    }
+}
+
+fn graph_call_strings(spdg: &SPDG) -> HashSet<CallString> {
+    spdg.graph
+        .node_weights()
+        .map(|n| n.at)
+        .chain(spdg.graph.edge_weights().map(|e| e.at))
+        .collect()
+}
+
 fn default_index() -> <SPDGImpl as GraphBase>::NodeId {
     <SPDGImpl as GraphBase>::NodeId::end()
 }
@@ -360,6 +714,8 @@ fn def_kind_for_item(id: DefId, tcx: TyCtxt) -> DefKind {
         | def::DefKind::OpaqueTy
         | def::DefKind::TyAlias { .. }
         | def::DefKind::Enum => DefKind::Type,
+        def::DefKind::Static(_) => DefKind::Static,
+        def::DefKind::Const | def::DefKind::AssocConst => DefKind::Const,
         kind => unreachable!("{} ({:?})", tcx.def_path_debug_str(id), kind),
     }
 }
@@ -408,13 +764,102 @@ fn with_reset_level_if_target<R, F: FnOnce() -> R>(opts: &crate::Args, target: S
     }
 }
 
+/// What happened at the call sites we encountered during PDG construction,
+/// recorded by [`MyCallback`].
+#[derive(Default)]
+struct InliningStats<'tcx> {
+    /// Callees for which a PDG was constructed (rather than served from the
+    /// cache) since the last controller started.
+    constructed: HashSet<Instance<'tcx>>,
+    /// The calls approximated at each call site. A call site in a generic
+    /// function has an entry for each instantiation in which a call was
+    /// approximated, see [`ApproximatedCall::caller`].
+    approximated: HashMap<GlobalLocation, Vec<ApproximatedCall<'tcx>>>,
+    /// Each location at which an operation was approximated conservatively,
+    /// with the kind of operation for every instance of the containing
+    /// function in which it was approximated.
+    unsafe_operations: HashMap<GlobalLocation, HashMap<Instance<'tcx>, (&'static str, RustSpan)>>,
+}
+
+/// A call that was not inlined, see [`InliningStats::approximated`].
+struct ApproximatedCall<'tcx> {
+    /// The instance of the function containing the call
+    caller: Instance<'tcx>,
+    /// `None` for a call through a function pointer with unknown targets
+    callee: Option<DefId>,
+    reason: &'static str,
+    span: RustSpan,
+    /// Whether the callee transitively calls marked functions.
+    markers_reachable: bool,
+    /// See [`Approximation::missing_bodies`].
+    missing_bodies: Box<[DefId]>,
+}
+
 struct MyCallback<'tcx> {
     judge: Rc<InlineJudge<'tcx>>,
-    // stat_wrap: StatStracker,
     tcx: TyCtxt<'tcx>,
+    stats: Rc<RefCell<InliningStats<'tcx>>>,
+}
+
+impl<'tcx> MyCallback<'tcx> {
+    fn record_approximation(
+        &self,
+        caller: Instance<'tcx>,
+        at: GlobalLocation,
+        callee: Option<Instance<'tcx>>,
+        reason: &'static str,
+        span: RustSpan,
+    ) {
+        let marker_ctx = self.judge.marker_ctx();
+        let markers_reachable = callee.map_or(false, |callee| {
+            marker_ctx.has_transitive_reachable_markers(callee)
+        });
+        let missing_bodies = callee
+            .map_or(&[][..], |callee| {
+                marker_ctx.reachable_missing_bodies(callee)
+            })
+            .into();
+        let callee = callee.map(|callee| callee.def_id());
+        let mut stats = self.stats.borrow_mut();
+        let calls = stats.approximated.entry(at).or_default();
+        // Call handling may be determined more than once per call site
+        if calls
+            .iter()
+            .any(|call| call.caller == caller && call.callee == callee)
+        {
+            return;
+        }
+        calls.push(ApproximatedCall {
+            caller,
+            callee,
+            reason,
+            span,
+            markers_reachable,
+            missing_bodies,
+        });
+    }
 }
 
 impl Stub {
+    /// Whether this stub can replace a call to `function`. Closure and future
+    /// stubs need the generic parameter they name to be instantiated with a
+    /// closure, `async` block or function item.
+    pub fn is_applicable<'tcx>(&self, tcx: TyCtxt<'tcx>, function: Instance<'tcx>) -> bool {
+        let (Stub::SubClosure { generic_name, .. } | Stub::SubFuture { generic_name }) = self
+        else {
+            return true;
+        };
+        let name = Symbol::intern(generic_name);
+        let generics = tcx.generics_of(function.def_id());
+        // A missing parameter is reported by `resolve_alternate_instance`
+        (0..generics.count())
+            .find(|&idx| generics.param_at(idx, tcx).name == name)
+            .and_then(|idx| function.args.get(idx)?.as_type())
+            .map_or(true, |ty| {
+                flowistry_pdg_construction::utils::type_as_fn(tcx, ty).is_some()
+            })
+    }
+
     pub fn resolve_alternate_instance<'tcx>(
         &self,
         tcx: TyCtxt<'tcx>,
@@ -423,7 +868,7 @@ impl Stub {
         at: RustSpan,
     ) -> Result<Instance<'tcx>, ErrorGuaranteed> {
         match self {
-            Stub::SubClosure { generic_name } | Stub::SubFuture { generic_name } => {
+            Stub::SubClosure { generic_name, .. } | Stub::SubFuture { generic_name } => {
                 let name = Symbol::intern(generic_name);
                 let generics = tcx.generics_of(function.def_id());
                 let Some(param_index) = (0..generics.count()).find(|&idx| {
@@ -442,9 +887,47 @@ impl Stub {
                     .unwrap()
                     .unwrap())
             }
+            Stub::Summary { .. } => Err(tcx
+                .sess
+                .span_err(at, "Summary stubs do not replace the called function")),
         }
     }
 
+    /// The flows described by a [`Stub::Summary`], `None` for other stubs.
+    ///
+    /// Fails if the summary refers to an argument the call does not have.
+    fn call_summary(
+        &self,
+        tcx: TyCtxt,
+        num_args: usize,
+        at: RustSpan,
+    ) -> Option<Result<CallSummary, ErrorGuaranteed>> {
+        let Stub::Summary {
+            return_from,
+            writes,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let out_of_range = return_from
+            .iter()
+            .chain(writes.iter().flat_map(|w| w.from.iter().chain([&w.to])))
+            .find(|i| **i as usize >= num_args);
+        if let Some(idx) = out_of_range {
+            return Some(Err(tcx.sess.span_err(
+                at,
+                format!(
+                    "Call summary refers to argument {idx} but the call has {num_args} argument(s)"
+                ),
+            )));
+        }
+        Some(Ok(CallSummary {
+            return_from: return_from.clone(),
+            writes: writes.iter().map(|w| (w.to, w.from.clone())).collect(),
+        }))
+    }
+
     fn indirect_required(
         &self,
         tcx: TyCtxt,
@@ -469,6 +952,7 @@ impl Stub {
                 assert!(tcx.generator_is_async(def_id));
                 true
             }
+            Stub::Summary { .. } => false,
         };
         Ok(bool)
     }
@@ -478,7 +962,8 @@ impl Stub {
     /// `function` is what was to be called but for which a stub exists,
     /// `arguments` are the arguments to that call.
     ///
-    /// Returns a new instance to call instead and how it should be called.
+    /// Returns the flows of the call for a [`Stub::Summary`] and otherwise a
+    /// new instance to call instead and how it should be called.
     pub fn apply<'tcx>(
         &self,
         tcx: TyCtxt<'tcx>,
@@ -486,44 +971,124 @@ impl Stub {
         param_env: ParamEnv<'tcx>,
         arguments: &[Operand<'tcx>],
         at: RustSpan,
-    ) -> Result<(Instance<'tcx>, CallingConvention<'tcx>), ErrorGuaranteed> {
+    ) -> Result<SkipCall<'tcx>, ErrorGuaranteed> {
+        if let Some(summary) = self.call_summary(tcx, arguments.len(), at) {
+            return summary.map(SkipCall::Summary);
+        }
         let instance = self.resolve_alternate_instance(tcx, function, param_env, at)?;
         let def_id = instance.def_id();
 
         let expect_indirect = self.indirect_required(tcx, def_id, at)?;
-        let poll = tcx.lang_items().poll();
+        let closure_arguments = self.closure_arguments(tcx, arguments, at)?;
         let calling_convention = if expect_indirect {
-            let clj = match arguments {
-                [clj] => clj,
-                [gen, _]
-                    if tcx.def_kind(function.def_id()) == hir::def::DefKind::AssocFn
-                        && tcx.associated_item(function.def_id()).trait_item_def_id == poll =>
-                {
-                    gen
-                }
-                _ => {
-                    return Err(tcx.sess.span_err(
-                        at,
-                        format!(
-                            "this function ({:?}) should have only one argument but it has {}",
-                            function.def_id(),
-                            arguments.len()
-                        ),
-                    ))
-                }
-            };
+            let clj = self.closure_operand(tcx, function, arguments, at)?;
             CallingConvention::Indirect {
                 once_shim: false,
                 closure_arg: clj.clone(),
-                // This is incorrect, but we only support
-                // non-argument closures at the moment so this
-                // will never be used.
-                tupled_arguments: clj.clone(),
+                arguments: ClosureArguments::Spread(closure_arguments.into()),
             }
-        } else {
+        } else if closure_arguments.is_empty() {
             CallingConvention::Direct(arguments.into())
+        } else {
+            CallingConvention::Direct(
+                closure_arguments
+                    .into_iter()
+                    .map(|arg| match arg {
+                        ClosureArgument::Exact(op) => Ok(op),
+                        ClosureArgument::ElementOf(_) => Err(tcx.sess.span_err(
+                            at,
+                            "elements of a collection can only be passed to closures, not functions",
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
+        };
+        Ok(SkipCall::Replace {
+            instance,
+            calling_convention,
+        })
+    }
+
+    /// The operand of the call that holds the closure or future this stub
+    /// calls instead: the only argument, the future of a `poll` or the
+    /// argument whose type is the generic parameter of this stub.
+    fn closure_operand<'a, 'tcx>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        function: Instance<'tcx>,
+        arguments: &'a [Operand<'tcx>],
+        at: RustSpan,
+    ) -> Result<&'a Operand<'tcx>, ErrorGuaranteed> {
+        let poll = tcx.lang_items().poll();
+        match arguments {
+            [clj] => return Ok(clj),
+            [gen, _]
+                if tcx.def_kind(function.def_id()) == hir::def::DefKind::AssocFn
+                    && tcx.associated_item(function.def_id()).trait_item_def_id == poll =>
+            {
+                return Ok(gen)
+            }
+            _ => (),
+        }
+        let (Stub::SubClosure { generic_name, .. } | Stub::SubFuture { generic_name }) = self
+        else {
+            unreachable!("only closure and future stubs are called indirectly")
+        };
+        let name = Symbol::intern(generic_name);
+        let sig = tcx.fn_sig(function.def_id()).instantiate_identity();
+        sig.skip_binder()
+            .inputs()
+            .iter()
+            .position(
+                |input| matches!(input.kind(), ty::TyKind::Param(param) if param.name == name),
+            )
+            .and_then(|idx| arguments.get(idx))
+            .ok_or_else(|| {
+                tcx.sess.span_err(
+                    at,
+                    format!(
+                        "this function ({:?}) has no argument of type {generic_name}",
+                        function.def_id(),
+                    ),
+                )
+            })
+    }
+
+    /// The arguments the closure of a [`Stub::SubClosure`] is called with.
+    ///
+    /// Fails if the stub refers to an argument the call does not have.
+    fn closure_arguments<'tcx>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        arguments: &[Operand<'tcx>],
+        at: RustSpan,
+    ) -> Result<Vec<ClosureArgument<'tcx>>, ErrorGuaranteed> {
+        let Stub::SubClosure {
+            arguments: sources, ..
+        } = self
+        else {
+            return Ok(vec![]);
         };
-        Ok((instance, calling_convention))
+        sources
+            .iter()
+            .map(|source| {
+                let (ClosureArgumentSource::Argument(idx) | ClosureArgumentSource::ElementOf(idx)) =
+                    *source;
+                let Some(arg) = arguments.get(idx as usize) else {
+                    return Err(tcx.sess.span_err(
+                        at,
+                        format!(
+                            "Stub refers to argument {idx} but the call has {} argument(s)",
+                            arguments.len()
+                        ),
+                    ));
+                };
+                Ok(match source {
+                    ClosureArgumentSource::Argument(_) => ClosureArgument::Exact(arg.clone()),
+                    ClosureArgumentSource::ElementOf(_) => ClosureArgument::ElementOf(arg.clone()),
+                })
+            })
+            .collect()
     }
 }
 
@@ -532,24 +1097,32 @@ impl<'tcx> CallChangeCallback<'tcx> for MyCallback<'tcx> {
         let changes = CallChanges::default();
 
         let skip = match self.judge.should_inline(&info) {
-            InlineJudgement::AbstractViaType(_) => SkipCall::Skip,
-            InlineJudgement::UseStub(model) => {
-                if let Ok((instance, calling_convention)) = model.apply(
+            InlineJudgement::AbstractViaType(reason) => {
+                self.record_approximation(
+                    info.caller,
+                    info.call_string.leaf(),
+                    Some(info.async_parent.unwrap_or(info.callee)),
+                    reason,
+                    info.span,
+                );
+                SkipCall::Skip
+            }
+            InlineJudgement::UseStub(model) => model
+                .apply(
                     self.tcx,
                     info.callee,
                     info.param_env,
                     info.arguments,
                     info.span,
-                ) {
-                    SkipCall::Replace {
-                        instance,
-                        calling_convention,
-                    }
-                } else {
-                    SkipCall::Skip
+                )
+                .unwrap_or(SkipCall::Skip),
+            InlineJudgement::InteriorMutability(model) => SkipCall::InteriorMutability(model),
+            InlineJudgement::Inline => {
+                if !info.is_cached {
+                    self.stats.borrow_mut().constructed.insert(info.callee);
                 }
+                SkipCall::NoSkip
             }
-            InlineJudgement::Inline => SkipCall::NoSkip,
         };
         changes.with_skip(skip)
     }
@@ -557,46 +1130,94 @@ impl<'tcx> CallChangeCallback<'tcx> for MyCallback<'tcx> {
         &self,
         resolution: Instance<'tcx>,
         param_env: ParamEnv<'tcx>,
-        _loc: Location,
-        _parent: Instance<'tcx>,
+        loc: Location,
+        parent: Instance<'tcx>,
         reason: InlineMissReason,
         call_span: rustc_span::Span,
     ) {
-        self.judge.ensure_is_safe_to_approximate(
-            param_env,
-            resolution,
+        let reason = match reason {
+            InlineMissReason::Async(_) => "async",
+            InlineMissReason::TraitMethod => "virtual trait method",
+            InlineMissReason::CallStringLimit => "call string limit",
+        };
+        self.record_approximation(
+            parent,
+            GlobalLocation {
+                function: parent.def_id(),
+                location: RichLocation::Location(loc),
+            },
+            Some(resolution),
+            reason,
             call_span,
-            false,
-            match reason {
-                InlineMissReason::Async(_) => "async",
-                InlineMissReason::TraitMethod => "virtual trait method",
+        );
+        self.judge
+            .ensure_is_safe_to_approximate(param_env, resolution, call_span, false, reason, None);
+    }
+
+    fn interior_mutability(
+        &self,
+        callee: Instance<'tcx>,
+        span: rustc_span::Span,
+    ) -> Option<InteriorMutability> {
+        self.judge.interior_mutability(callee.def_id(), span)
+    }
+
+    fn on_unknown_fn_pointer_call(
+        &self,
+        under_analysis: Instance<'tcx>,
+        loc: Location,
+        call_span: rustc_span::Span,
+    ) {
+        self.record_approximation(
+            under_analysis,
+            GlobalLocation {
+                function: under_analysis.def_id(),
+                location: RichLocation::Location(loc),
             },
+            None,
+            "unknown function pointer",
+            call_span,
+        );
+    }
+
+    fn on_async_wrapper_prelude(
+        &self,
+        wrapper: Instance<'tcx>,
+        loc: Location,
+        span: rustc_span::Span,
+    ) {
+        self.record_approximation(
+            wrapper,
+            GlobalLocation {
+                function: wrapper.def_id(),
+                location: RichLocation::Location(loc),
+            },
+            Some(wrapper),
+            "async wrapper prelude",
+            span,
         );
     }
-}
 
-//type StatStracker = Rc<RefCell<(SPDGStats, HashSet<LocalDefId>)>>;
-
-// fn record_inlining(tracker: &StatStracker, tcx: TyCtxt<'_>, def_id: LocalDefId, is_in_cache: bool) {
-//     let mut borrow = tracker.borrow_mut();
-//     let (stats, loc_set) = &mut *borrow;
-//     stats.inlinings_performed += 1;
-//     let is_new = loc_set.insert(def_id);
-
-//     if !is_new || is_in_cache {
-//         return;
-//     }
-
-//     let src_map = tcx.sess.source_map();
-//     let span = body_span(&tcx.body_for_def_id(def_id).unwrap().body);
-//     let (_, start_line, _, end_line, _) = src_map.span_to_location_info(span);
-//     let body_lines = (end_line - start_line + 1) as u32;
-//     if is_new {
-//         stats.unique_functions += 1;
-//         stats.unique_locs += body_lines;
-//     }
-//     if !is_in_cache {
-//         stats.analyzed_functions += 1;
-//         stats.analyzed_locs += body_lines;
-//     }
-// }
+    fn on_unsafe_operation(
+        &self,
+        under_analysis: Instance<'tcx>,
+        loc: Location,
+        operation: UnsafeOperation,
+        span: rustc_span::Span,
+    ) {
+        let operation = match operation {
+            UnsafeOperation::RawPointerDeref => "raw pointer dereference",
+            UnsafeOperation::Transmute => "transmute",
+            UnsafeOperation::ForeignCall => "foreign call",
+        };
+        self.stats
+            .borrow_mut()
+            .unsafe_operations
+            .entry(GlobalLocation {
+                function: under_analysis.def_id(),
+                location: RichLocation::Location(loc),
+            })
+            .or_default()
+            .insert(under_analysis, (operation, span));
+    }
+}
@@ -0,0 +1,225 @@
+//! Validation of the external annotations file (`--check-annotations`).
+//!
+//! Every entry of the file is resolved and its refinements are checked against
+//! the signature of the item it resolved to. If all entries are valid the
+//! graph of the current run is used to find markers that never end up on any
+//! node or type.
+
+use std::fmt::{self, Display};
+
+use paralegal_spdg::{Identifier, ProgramDescription};
+use rustc_hir::{def::DefKind, def_id::DefId};
+use rustc_middle::ty::TyCtxt;
+
+use crate::{
+    ann::{
+        db::{read_external_markers, ExternalEntry},
+        MarkerAnnotation,
+    },
+    args::Args,
+    utils::resolve::resolve_string_to_def_id,
+    HashSet,
+};
+
+/// Something that is wrong with an entry in the external annotations file.
+#[derive(Debug, Clone)]
+pub enum EntryProblem {
+    /// The path did not resolve. Contains the resolution error.
+    Unresolved(String),
+    /// A marker targets an argument index the function does not have.
+    ArgumentOutOfRange {
+        marker: Identifier,
+        index: u32,
+        arity: usize,
+    },
+    /// A marker uses `on_argument` or `on_return` but the item is not a
+    /// function.
+    RefinementOnNonFunction {
+        marker: Identifier,
+        kind: &'static str,
+    },
+}
+
+impl Display for EntryProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryProblem::Unresolved(err) => write!(f, "unresolved: {err}"),
+            EntryProblem::ArgumentOutOfRange {
+                marker,
+                index,
+                arity,
+            } => write!(
+                f,
+                "marker '{marker}' targets argument {index} but the function has {arity} argument(s)"
+            ),
+            EntryProblem::RefinementOnNonFunction { marker, kind } => write!(
+                f,
+                "marker '{marker}' is refined to arguments or return but the item is a {kind}"
+            ),
+        }
+    }
+}
+
+/// Outcome of checking a single entry of the annotations file.
+#[derive(Debug)]
+pub struct EntryReport {
+    pub path: String,
+    /// Line of the entry in the annotations file
+    pub line: usize,
+    pub resolved: Option<DefId>,
+    pub markers: Vec<MarkerAnnotation>,
+    pub problems: Vec<EntryProblem>,
+}
+
+/// Result of [`check_external_annotations`]. Print it with [`Display`].
+#[derive(Debug, Default)]
+pub struct AnnotationReport {
+    pub entries: Vec<EntryReport>,
+    /// Markers from the file that appear on no node or type in the analyzed
+    /// graph. `None` if they were not checked, see
+    /// [`Self::find_unused_markers`].
+    pub unused_markers: Option<Vec<Identifier>>,
+}
+
+impl AnnotationReport {
+    /// Did any entry fail to resolve or validate?
+    pub fn has_problems(&self) -> bool {
+        self.entries.iter().any(|e| !e.problems.is_empty())
+    }
+
+    /// Record the markers from the file that appear on no node or type in
+    /// `desc`, the graph produced with these annotations.
+    pub fn find_unused_markers(&mut self, desc: &ProgramDescription) {
+        let used = desc
+            .controllers
+            .values()
+            .flat_map(|ctrl| ctrl.markers.values().flat_map(|m| m.keys()))
+            .chain(desc.type_info.values().flat_map(|t| t.markers.iter()))
+            .copied()
+            .collect::<HashSet<_>>();
+        let mut unused = self
+            .entries
+            .iter()
+            .flat_map(|e| e.markers.iter().map(|m| m.marker))
+            .filter(|m| !used.contains(m))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        unused.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        self.unused_markers = Some(unused);
+    }
+}
+
+impl Display for AnnotationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num_bad = self
+            .entries
+            .iter()
+            .filter(|e| !e.problems.is_empty())
+            .count();
+        writeln!(
+            f,
+            "Checked {} external annotation entries, {num_bad} with problems",
+            self.entries.len()
+        )?;
+        for entry in &self.entries {
+            let status = if entry.problems.is_empty() {
+                "ok"
+            } else {
+                "error"
+            };
+            write!(f, "  [{status}] {} (line {})", entry.path, entry.line)?;
+            if let Some(did) = entry.resolved {
+                write!(f, " ({did:?})")?;
+            }
+            writeln!(f)?;
+            for problem in &entry.problems {
+                writeln!(f, "      {problem}")?;
+            }
+        }
+        match &self.unused_markers {
+            None => writeln!(f, "Marker usage not checked because of the problems above")?,
+            Some(unused) if unused.is_empty() => {
+                writeln!(f, "All markers appear in the analyzed graph")?
+            }
+            Some(unused) => {
+                writeln!(
+                    f,
+                    "Markers that appear on no node or type in the analyzed graph:"
+                )?;
+                for marker in unused {
+                    writeln!(f, "  {marker}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolve and validate every entry of the external annotations file. Returns
+/// an empty report if no such file was configured.
+///
+/// Unused markers are not checked here, because that requires the graph, see
+/// [`AnnotationReport::find_unused_markers`].
+pub fn check_external_annotations(tcx: TyCtxt, opts: &Args) -> AnnotationReport {
+    let Some(annotation_file) = opts.marker_control().external_annotations() else {
+        return AnnotationReport::default();
+    };
+    let mut raw = read_external_markers(annotation_file)
+        .into_iter()
+        .collect::<Vec<_>>();
+    raw.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let entries = raw
+        .into_iter()
+        .map(|(path, ExternalEntry { line, markers })| {
+            let (resolved, problems) = match resolve_string_to_def_id(tcx, &path) {
+                Ok(did) => (Some(did), check_refinements(tcx, did, &markers)),
+                Err(err) => (None, vec![EntryProblem::Unresolved(err)]),
+            };
+            EntryReport {
+                path,
+                line,
+                resolved,
+                markers,
+                problems,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    AnnotationReport {
+        entries,
+        unused_markers: None,
+    }
+}
+
+fn check_refinements(tcx: TyCtxt, did: DefId, markers: &[MarkerAnnotation]) -> Vec<EntryProblem> {
+    let kind = tcx.def_kind(did);
+    let arity = matches!(kind, DefKind::Fn | DefKind::AssocFn)
+        .then(|| tcx.fn_sig(did).skip_binder().skip_binder().inputs().len());
+    markers
+        .iter()
+        .flat_map(|ann| {
+            let refinement = &ann.refinement;
+            match arity {
+                Some(arity) => refinement
+                    .on_argument()
+                    .into_iter_set_in_domain()
+                    .filter(|i| *i as usize >= arity)
+                    .map(|index| EntryProblem::ArgumentOutOfRange {
+                        marker: ann.marker,
+                        index,
+                        arity,
+                    })
+                    .collect::<Vec<_>>(),
+                None if !refinement.on_self() => {
+                    vec![EntryProblem::RefinementOnNonFunction {
+                        marker: ann.marker,
+                        kind: tcx.def_kind_descr(kind, did),
+                    }]
+                }
+                None => vec![],
+            }
+        })
+        .collect()
+}
@@ -14,8 +14,9 @@ use crate::{
     ann::{Annotation, MarkerAnnotation},
     args::{Args, Stub},
     utils::{
-        func_of_term, resolve::expect_resolve_string_to_def_id, FunctionKind, InstanceExt,
-        IntoDefId, TyExt,
+        func_of_term, referenced_static_or_const,
+        resolve::{expect_resolve_string_to_def_id, resolve_string_to_def_id},
+        ConstantVisitor, FunctionKind, InstanceExt, IntoDefId, TyExt,
     },
     Either, HashMap, HashSet,
 };
@@ -25,8 +26,9 @@ use flowistry_pdg_construction::{
     determine_async,
     encoder::ParalegalDecoder,
     utils::{is_virtual, try_monomorphize, try_resolve_function},
+    AsyncType,
 };
-use paralegal_spdg::Identifier;
+use paralegal_spdg::{AnnotationSource, Identifier, MarkerPropagation};
 
 use rustc_errors::DiagnosticMessage;
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -41,11 +43,11 @@ use rustc_serialize::Decodable;
 use rustc_span::Span;
 use rustc_utils::cache::Cache;
 
-use std::{borrow::Cow, fs::File, io::Read, rc::Rc};
+use std::{borrow::Cow, cell::RefCell, fs::File, io::Read, rc::Rc};
 
 use super::{MarkerMeta, MARKER_META_EXT};
 
-type ExternalMarkers = HashMap<DefId, Vec<MarkerAnnotation>>;
+type ExternalMarkers = HashMap<DefId, ExternalEntry>;
 
 /// The marker context is a database which can be queried as to whether
 /// functions or types carry markers, whether markers are reachable in bodies,
@@ -96,10 +98,63 @@ impl<'tcx> MarkerCtx<'tcx> {
         self.db()
             .external_annotations
             .get(&self.defid_rewrite(did.into_def_id(self.tcx())))
-            .map_or(&[], |v| v.as_slice())
+            .map_or(&[], |e| e.markers.as_slice())
     }
 
-    /// All markers reachable for this item (local and external).
+    /// Line of the entry in the external annotation file that marked this
+    /// item, if any.
+    pub fn external_marker_line<D: IntoDefId>(&self, did: D) -> Option<usize> {
+        self.db()
+            .external_annotations
+            .get(&self.defid_rewrite(did.into_def_id(self.tcx())))
+            .map(|e| e.line)
+    }
+
+    /// All markers reachable for this item (local and external) together with
+    /// where they were declared.
+    pub fn combined_markers_with_source(
+        &self,
+        def_id: DefId,
+    ) -> impl Iterator<Item = (&MarkerAnnotation, AnnotationSource)> {
+        let external_source =
+            self.external_marker_line(def_id)
+                .map(|line| AnnotationSource::External {
+                    file: self
+                        .db()
+                        .config
+                        .marker_control()
+                        .external_annotations()
+                        .unwrap()
+                        .display()
+                        .to_string(),
+                    line,
+                });
+        self.source_annotations(def_id)
+            .iter()
+            .filter_map(Annotation::as_marker)
+            .map(|ann| (ann, AnnotationSource::Attribute))
+            .chain(
+                self.external_markers(def_id)
+                    .iter()
+                    .map(move |ann| (ann, external_source.clone().unwrap())),
+            )
+            .chain(
+                self.stub_markers(def_id)
+                    .iter()
+                    .map(|ann| (ann, AnnotationSource::Stub)),
+            )
+    }
+
+    /// Markers declared for this function by a [`Stub::Summary`]. If there
+    /// are no such markers an empty slice is returned.
+    pub fn stub_markers(&self, def_id: DefId) -> &[MarkerAnnotation] {
+        match self.0.stubs.get(&def_id) {
+            Some(Stub::Summary { markers, .. }) => markers,
+            _ => &[],
+        }
+    }
+
+    /// All markers reachable for this item (local, external and from stubs).
     ///
     /// Queries are cached/precomputed so calling this repeatedly is cheap.
     pub fn combined_markers(&self, def_id: DefId) -> impl Iterator<Item = &MarkerAnnotation> {
@@ -107,6 +162,7 @@ impl<'tcx> MarkerCtx<'tcx> {
             .iter()
             .filter_map(Annotation::as_marker)
             .chain(self.external_markers(def_id).iter())
+            .chain(self.stub_markers(def_id).iter())
     }
 
     /// For async handling. If this id corresponds to an async closure we try to
@@ -137,13 +193,15 @@ impl<'tcx> MarkerCtx<'tcx> {
             .any(Annotation::is_marker)
     }
 
-    /// Are there any markers (local or external) on this item?
+    /// Are there any markers (local, external or from a stub) on this item?
     ///
     /// This is in contrast to [`Self::marker_is_reachable`] which also reports
     /// if markers are reachable from the body of this function (if it is one).
     pub fn is_marked<D: IntoDefId + Copy>(&self, did: D) -> bool {
         let defid = did.into_def_id(self.tcx());
-        self.is_locally_marked(defid) || self.is_externally_marked(defid)
+        self.is_locally_marked(defid)
+            || self.is_externally_marked(defid)
+            || !self.stub_markers(defid).is_empty()
     }
 
     /// Return a complete set of local annotations that were discovered.
@@ -183,47 +241,83 @@ impl<'tcx> MarkerCtx<'tcx> {
     }
 
     pub fn get_reachable_markers(&self, res: impl Into<MaybeMonomorphized<'tcx>>) -> &[Identifier] {
-        let res = res.into();
+        self.get_reachable(res.into())
+            .map_or(&[], |reachable| &reachable.markers)
+    }
+
+    /// Functions reachable from `res` (including `res` itself) whose bodies
+    /// are missing. If there are any, the markers reachable from `res` are not
+    /// known.
+    pub fn reachable_missing_bodies(&self, res: impl Into<MaybeMonomorphized<'tcx>>) -> &[DefId] {
+        self.get_reachable(res.into())
+            .map_or(&[], |reachable| &reachable.missing_bodies)
+    }
+
+    /// The entry for `res` in the transitive marker cache, `None` for
+    /// functions whose reachable markers are not tracked.
+    fn get_reachable(&self, res: MaybeMonomorphized<'tcx>) -> Option<&Reachable> {
         let def_id = res.def_id();
         if self.is_marked(def_id) {
             trace!("  Is marked");
-            return &[];
+            return None;
         }
         if is_virtual(self.tcx(), def_id) {
             trace!("  Is virtual");
-            return &[];
+            return None;
         }
         if !self.0.included_crates.contains(&def_id.krate) {
-            return &[];
+            return None;
         }
         self.db()
             .reachable_markers
-            .get_maybe_recursive(res, |_| self.compute_reachable_markers(res))
-            .map_or(&[], Box::as_ref)
+            .get_maybe_recursive(res, |_| self.compute_reachable(res))
     }
 
+    /// The markers of `res` itself or, if there are none, the markers
+    /// reachable from it. Functions with missing bodies that are reachable
+    /// from it are added to `missing`.
     fn get_reachable_and_self_markers(
         &self,
         res: impl Into<MaybeMonomorphized<'tcx>>,
-    ) -> impl Iterator<Item = Identifier> + '_ {
+        missing: &mut Vec<DefId>,
+    ) -> Vec<Identifier> {
         let res = res.into();
-        let mut direct_markers = self
+        let direct_markers = self
             .combined_markers(res.def_id())
             .map(|m| m.marker)
-            .peekable();
-        let non_direct = direct_markers
-            .peek()
-            .is_none()
-            .then(|| self.get_reachable_markers(res));
-
-        direct_markers.chain(non_direct.into_iter().flatten().copied())
+            .collect::<Vec<_>>();
+        if !direct_markers.is_empty() {
+            return direct_markers;
+        }
+        let Some(reachable) = self.get_reachable(res) else {
+            return vec![];
+        };
+        missing.extend_from_slice(&reachable.missing_bodies);
+        reachable.markers.to_vec()
     }
 
     /// If the transitive marker cache did not contain the answer, this is what
     /// computes it.
-    fn compute_reachable_markers(&self, res: MaybeMonomorphized<'tcx>) -> Box<[Identifier]> {
+    fn compute_reachable(&self, res: MaybeMonomorphized<'tcx>) -> Reachable {
         trace!("Computing reachable markers for {res:?}");
-        let body = self.0.body_cache.get(res.def_id());
+        let Some(body) = self.0.body_cache.try_get(res.def_id()) else {
+            let is_shim = matches!(
+                res,
+                MaybeMonomorphized::Monomorphized(instance)
+                    if !matches!(instance.def, ty::InstanceDef::Item(_))
+            );
+            if is_shim || self.tcx().is_foreign_item(res.def_id()) {
+                // These never have a body
+                return Reachable::default();
+            }
+            // The crate was not compiled with paralegal-flow, calls to this
+            // function are approximated by the inline judge.
+            warn!("No body found for {res:?}, reachable markers are unknown");
+            return Reachable {
+                markers: Box::new([]),
+                missing_bodies: Box::new([res.def_id()]),
+            };
+        };
         let mono_body = match res {
             MaybeMonomorphized::Monomorphized(res) => Cow::Owned(
                 try_monomorphize(
@@ -237,11 +331,24 @@ impl<'tcx> MarkerCtx<'tcx> {
             ),
             MaybeMonomorphized::Plain(_) => Cow::Borrowed(body.body()),
         };
-        if let Some((async_fn, _, _)) = determine_async(self.tcx(), res.def_id(), &mono_body) {
-            return self.get_reachable_markers(async_fn).into();
+        let mut wrapped_markers = vec![];
+        let mut missing = vec![];
+        if let Some((generator, _, asyncness)) =
+            determine_async(self.tcx(), res.def_id(), &mono_body)
+        {
+            let reachable = self.get_reachable(generator.into());
+            if asyncness == AsyncType::Fn {
+                return reachable.cloned().unwrap_or_default();
+            }
+            // Other than an `async fn`, a wrapper may do work of its own
+            // before creating the future.
+            if let Some(reachable) = reachable {
+                wrapped_markers.extend_from_slice(&reachable.markers);
+                missing.extend_from_slice(&reachable.missing_bodies);
+            }
         }
         let expect_resolve = res.is_monomorphized();
-        mono_body
+        let markers = mono_body
             .basic_blocks
             .iter()
             .flat_map(|bbdat| {
@@ -249,11 +356,22 @@ impl<'tcx> MarkerCtx<'tcx> {
                     &mono_body.local_decls,
                     bbdat.terminator(),
                     expect_resolve,
+                    &mut missing,
                 )
             })
+            .chain(self.static_reachable_markers(&mono_body))
+            .chain(wrapped_markers)
             .collect::<HashSet<_>>()
             .into_iter()
-            .collect()
+            .collect();
+        Reachable {
+            markers,
+            missing_bodies: missing
+                .into_iter()
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect(),
+        }
     }
 
     fn span_err(&self, span: Span, msg: impl Into<DiagnosticMessage>) {
@@ -264,12 +382,27 @@ impl<'tcx> MarkerCtx<'tcx> {
         }
     }
 
-    /// Does this terminator carry a marker?
+    /// Markers on `static` and `const` items that are read in this body.
+    fn static_reachable_markers(&self, body: &mir::Body<'tcx>) -> Vec<Identifier> {
+        use mir::visit::Visitor;
+        let tcx = self.tcx();
+        let mut items = vec![];
+        ConstantVisitor(|c: &mir::Constant<'tcx>| items.extend(referenced_static_or_const(tcx, c)))
+            .visit_body(body);
+        items
+            .into_iter()
+            .flat_map(|item| self.combined_markers(item).map(|m| m.marker))
+            .collect()
+    }
+
+    /// Does this terminator carry a marker? Functions with missing bodies
+    /// that are reachable from it are added to `missing`.
     fn terminator_reachable_markers(
         &self,
         local_decls: &mir::LocalDecls,
         terminator: &mir::Terminator<'tcx>,
         expect_resolve: bool,
+        missing: &mut Vec<DefId>,
     ) -> impl Iterator<Item = Identifier> + '_ {
         let param_env = ty::ParamEnv::reveal_all();
         let mut v = vec![];
@@ -298,7 +431,13 @@ impl<'tcx> MarkerCtx<'tcx> {
             self.tcx().def_path_debug_str(res.def_id())
         );
 
-        if let Some(model) = self.has_stub(res.def_id()) {
+        let stub = match res {
+            MaybeMonomorphized::Monomorphized(instance) => {
+                self.applicable_stub(instance, terminator.source_info.span)
+            }
+            MaybeMonomorphized::Plain(def_id) => self.has_stub(def_id),
+        };
+        if let Some(model) = stub {
             if let MaybeMonomorphized::Monomorphized(instance) = &mut res {
                 if let Ok(new_instance) = model.resolve_alternate_instance(
                     self.tcx(),
@@ -306,7 +445,7 @@ impl<'tcx> MarkerCtx<'tcx> {
                     param_env,
                     terminator.source_info.span,
                 ) {
-                    v.extend(self.get_reachable_and_self_markers(new_instance));
+                    v.extend(self.get_reachable_and_self_markers(new_instance, missing));
                 }
             } else {
                 self.span_err(
@@ -317,7 +456,7 @@ impl<'tcx> MarkerCtx<'tcx> {
             return v.into_iter();
         }
 
-        v.extend(self.get_reachable_and_self_markers(res));
+        v.extend(self.get_reachable_and_self_markers(res, missing));
 
         // We have to proceed differently than graph construction,
         // because we are not given the closure function, instead
@@ -330,7 +469,8 @@ impl<'tcx> MarkerCtx<'tcx> {
             trace!("    fits opaque type");
             v.extend(
             self.get_reachable_and_self_markers(
-                try_resolve_function(self.tcx(), *closure_fn, ty::ParamEnv::reveal_all(), substs).unwrap()
+                try_resolve_function(self.tcx(), *closure_fn, ty::ParamEnv::reveal_all(), substs).unwrap(),
+                missing,
             ))
         };
         v.into_iter()
@@ -372,12 +512,52 @@ impl<'tcx> MarkerCtx<'tcx> {
             .flatten()
     }
 
+    /// All markers on types contained in `key` (including `key` itself) that
+    /// propagate to `key` according to the rule the marker was declared with
+    /// (see [`MarkerPropagation`]).
     pub fn deep_type_markers<'a>(&'a self, key: ty::Ty<'tcx>) -> &'a TypeMarkers {
         self.0
             .type_markers
+            .get(key, |key| {
+                self.reachable_type_markers(key)
+                    .iter()
+                    .filter(|(elem, reach)| reach.admits(self.type_marker_propagation(*elem)))
+                    .map(|(elem, _)| *elem)
+                    .collect::<FxHashSet<_>>()
+                    .into_iter()
+                    .collect()
+            })
+            .as_ref()
+    }
+
+    /// The propagation rule the marker `elem.1` was declared with on the type
+    /// `elem.0`.
+    fn type_marker_propagation(&self, (def_id, marker): TypeMarkerElem) -> MarkerPropagation {
+        self.combined_markers(def_id)
+            .find(|ann| ann.marker == marker)
+            .map_or_else(Default::default, |ann| ann.propagate)
+    }
+
+    /// All markers on types contained in `key`, together with how the type
+    /// carrying the marker was reached. The propagation rules are applied
+    /// afterwards in [`Self::deep_type_markers`].
+    fn reachable_type_markers<'a>(
+        &'a self,
+        key: ty::Ty<'tcx>,
+    ) -> &'a [(TypeMarkerElem, TypeReach)] {
+        self.0
+            .type_reach
             .get_maybe_recursive(key, |key| {
                 use ty::*;
-                let mut markers = self.shallow_type_markers(key).collect::<FxHashSet<_>>();
+                let mut markers = self
+                    .shallow_type_markers(key)
+                    .map(|elem| (elem, TypeReach::default()))
+                    .collect::<FxHashSet<_>>();
+                let nested = |ty, reach: TypeReach| {
+                    self.reachable_type_markers(ty)
+                        .iter()
+                        .map(move |(elem, inner)| (*elem, inner.through(reach)))
+                };
                 match key.kind() {
                     Bool
                     | Char
@@ -395,9 +575,16 @@ impl<'tcx> MarkerCtx<'tcx> {
                     | Never
                     | Bound { .. }
                     | Error(_) => (),
+                    Adt(def, generics) if def.is_phantom_data() => markers.extend(nested(
+                        generics.type_at(0),
+                        TypeReach {
+                            through_phantom: true,
+                            ..TypeReach::OWNED
+                        },
+                    )),
                     Adt(def, generics) => markers.extend(self.type_markers_for_adt(def, generics)),
                     Tuple(tys) => {
-                        markers.extend(tys.iter().flat_map(|ty| self.deep_type_markers(ty)))
+                        markers.extend(tys.iter().flat_map(|ty| nested(ty, TypeReach::OWNED)))
                     }
                     Alias(_, _) => {
                         trace!("Alias type {key:?} remains. Was not normalized.");
@@ -405,11 +592,22 @@ impl<'tcx> MarkerCtx<'tcx> {
                     }
                     // We can't track indices so we simply overtaint to the entire array
                     Array(inner, _) | Slice(inner) => {
-                        markers.extend(self.deep_type_markers(*inner))
-                    }
-                    RawPtr(ty::TypeAndMut { ty, .. }) | Ref(_, ty, _) => {
-                        markers.extend(self.deep_type_markers(*ty))
+                        markers.extend(nested(*inner, TypeReach::OWNED))
                     }
+                    RawPtr(ty::TypeAndMut { ty, .. }) => markers.extend(nested(
+                        *ty,
+                        TypeReach {
+                            through_raw_pointer: true,
+                            ..TypeReach::OWNED
+                        },
+                    )),
+                    Ref(_, ty, _) => markers.extend(nested(
+                        *ty,
+                        TypeReach {
+                            through_reference: true,
+                            ..TypeReach::OWNED
+                        },
+                    )),
                     Param(_) | Dynamic { .. } => self
                         .tcx()
                         .sess
@@ -424,21 +622,44 @@ impl<'tcx> MarkerCtx<'tcx> {
             .map_or(&[], Box::as_ref)
     }
 
+    /// Markers reachable from the fields of this ADT.
+    ///
+    /// A type behind a raw pointer in one field is considered owned if another
+    /// field is a `PhantomData` of exactly that type. This is the convention
+    /// owning pointers like `Box`, `Vec` or `Arc` use to tell the drop checker
+    /// that they own the pointee, whereas e.g. `PhantomData<&T>` next to a
+    /// `*const T` marks a borrow.
     fn type_markers_for_adt<'a>(
         &'a self,
         adt: &'a ty::AdtDef<'tcx>,
         generics: &'tcx ty::List<ty::GenericArg<'tcx>>,
-    ) -> impl Iterator<Item = &'a TypeMarkerElem> {
+    ) -> impl Iterator<Item = (TypeMarkerElem, TypeReach)> {
         let tcx = self.tcx();
-        adt.variants()
-            .iter_enumerated()
-            .flat_map(move |(_, vdef)| {
-                vdef.fields.iter_enumerated().flat_map(move |(_, fdef)| {
-                    let f_ty = fdef.ty(tcx, generics);
-                    self.deep_type_markers(f_ty)
-                })
+        let field_tys = adt
+            .all_fields()
+            .map(|fdef| fdef.ty(tcx, generics))
+            .collect::<Vec<_>>();
+        let phantom_tys = field_tys
+            .iter()
+            .filter_map(|ty| match ty.kind() {
+                ty::Adt(def, generics) if def.is_phantom_data() => Some(generics.type_at(0)),
+                _ => None,
+            })
+            .collect::<FxHashSet<_>>();
+        let owned_pointees = field_tys
+            .iter()
+            .flat_map(|ty| raw_pointees(tcx, *ty))
+            .filter(|pointee| phantom_tys.contains(pointee))
+            .collect::<FxHashSet<_>>();
+        field_tys
+            .into_iter()
+            .chain(owned_pointees)
+            .flat_map(move |ty| {
+                self.reachable_type_markers(ty)
+                    .iter()
+                    .map(|(elem, reach)| (*elem, reach.through(TypeReach::OWNED)))
             })
-            .collect::<Vec<_>>()
+            .collect::<FxHashSet<_>>()
             .into_iter()
     }
 
@@ -494,12 +715,12 @@ impl<'tcx> MarkerCtx<'tcx> {
         self.source_annotations_found()
             .into_iter()
             .flat_map(|(key, anns)| anns.iter().map(move |a| (key, Either::Left(a))))
-            .chain(
-                self.0
-                    .external_annotations
+            .chain(self.0.external_annotations.iter().flat_map(|(&id, entry)| {
+                entry
+                    .markers
                     .iter()
-                    .flat_map(|(&id, anns)| anns.iter().map(move |ann| (id, Either::Right(ann)))),
-            )
+                    .map(move |ann| (id, Either::Right(ann)))
+            }))
     }
 
     pub fn functions_seen(&self) -> Vec<MaybeMonomorphized<'tcx>> {
@@ -518,11 +739,130 @@ impl<'tcx> MarkerCtx<'tcx> {
             .find_map(|def_id| self.0.stubs.get(&def_id))
             .copied()
     }
+
+    /// The stub for `function` if it can replace a call to this instance, see
+    /// [`Stub::is_applicable`].
+    ///
+    /// Built-in stubs are skipped silently, a configured stub that does not
+    /// apply is reported once per function.
+    pub fn applicable_stub(&self, function: Instance<'tcx>, span: Span) -> Option<&'static Stub> {
+        let stub = self.has_stub(function.def_id())?;
+        if stub.is_applicable(self.tcx(), function) {
+            return Some(stub);
+        }
+        let is_builtin = BUILTIN_STUBS.iter().any(|(_, b)| std::ptr::eq(b, stub));
+        if !is_builtin
+            && self
+                .0
+                .non_applicable_stubs
+                .borrow_mut()
+                .insert(function.def_id())
+        {
+            let (Stub::SubClosure { generic_name, .. } | Stub::SubFuture { generic_name }) = stub
+            else {
+                unreachable!("only closure and future stubs can be inapplicable")
+            };
+            let mut diagnostic = self.tcx().sess.struct_span_warn(
+                span,
+                format!(
+                    "configured stub for `{}` is not used",
+                    self.tcx().def_path_str(function.def_id())
+                ),
+            );
+            diagnostic.note(format!(
+                "`{generic_name}` is not instantiated with a closure, `async` block or function item"
+            ));
+            diagnostic.emit();
+        }
+        None
+    }
+}
+
+lazy_static! {
+    /// Stubs for functions that run a closure or future as a separate task, so
+    /// that the task is treated as if it ran at the spawn site. A stub
+    /// configured for one of these takes precedence.
+    static ref BUILTIN_STUBS: Vec<(&'static str, Stub)> = {
+        let sub_future = || Stub::SubFuture {
+            generic_name: "F".to_string(),
+        };
+        let sub_closure = || Stub::SubClosure {
+            generic_name: "F".to_string(),
+            arguments: vec![],
+        };
+        vec![
+            ("std::thread::spawn", sub_closure()),
+            ("tokio::spawn", sub_future()),
+            ("tokio::task::spawn_local", sub_future()),
+            ("tokio::task::spawn_blocking", sub_closure()),
+        ]
+    };
 }
 
 pub type TypeMarkerElem = (DefId, Identifier);
 pub type TypeMarkers = [TypeMarkerElem];
 
+/// How a marked type was reached while walking the structure of a containing
+/// type. Used to decide whether the marker propagates according to its
+/// [`MarkerPropagation`] rule.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+struct TypeReach {
+    /// The marked type is a proper part of the walked type.
+    nested: bool,
+    through_reference: bool,
+    through_raw_pointer: bool,
+    through_phantom: bool,
+}
+
+impl TypeReach {
+    /// A plain, owning containment step (field, tuple or array element).
+    const OWNED: Self = Self {
+        nested: true,
+        through_reference: false,
+        through_raw_pointer: false,
+        through_phantom: false,
+    };
+
+    /// Combine the steps of two paths.
+    fn through(self, other: Self) -> Self {
+        Self {
+            nested: self.nested || other.nested,
+            through_reference: self.through_reference || other.through_reference,
+            through_raw_pointer: self.through_raw_pointer || other.through_raw_pointer,
+            through_phantom: self.through_phantom || other.through_phantom,
+        }
+    }
+
+    /// Does a marker with this propagation rule apply if its type was reached
+    /// this way?
+    fn admits(self, rule: MarkerPropagation) -> bool {
+        match rule {
+            MarkerPropagation::All => true,
+            MarkerPropagation::NoPhantom => !self.through_phantom,
+            MarkerPropagation::Owned => {
+                !(self.through_phantom || self.through_raw_pointer || self.through_reference)
+            }
+            MarkerPropagation::None => !self.nested,
+        }
+    }
+}
+
+/// The types behind raw pointers that are part of `ty` itself, e.g. not behind
+/// a reference, another raw pointer or inside a `PhantomData`.
+fn raw_pointees<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> Vec<ty::Ty<'tcx>> {
+    match ty.kind() {
+        ty::RawPtr(ty::TypeAndMut { ty, .. }) => vec![*ty],
+        ty::Adt(def, _) if def.is_phantom_data() => vec![],
+        ty::Adt(def, generics) => def
+            .all_fields()
+            .flat_map(|fdef| raw_pointees(tcx, fdef.ty(tcx, generics)))
+            .collect(),
+        ty::Tuple(tys) => tys.iter().flat_map(|ty| raw_pointees(tcx, ty)).collect(),
+        ty::Array(inner, _) => raw_pointees(tcx, *inner),
+        _ => vec![],
+    }
+}
+
 /// Either we have an [`Instance`] or a [`DefId`] if we weren't able to resolve
 /// the generics.
 ///
@@ -565,6 +905,16 @@ impl<'tcx> From<Instance<'tcx>> for MaybeMonomorphized<'tcx> {
     }
 }
 
+/// What is reachable from the body of a function, see
+/// [`MarkerCtx::get_reachable_markers`].
+#[derive(Clone, Default)]
+struct Reachable {
+    markers: Box<[Identifier]>,
+    /// Reachable functions whose bodies are missing, see
+    /// [`MarkerCtx::reachable_missing_bodies`].
+    missing_bodies: Box<[DefId]>,
+}
+
 /// The structure inside of [`MarkerCtx`].
 pub struct MarkerDatabase<'tcx> {
     tcx: TyCtxt<'tcx>,
@@ -573,13 +923,17 @@ pub struct MarkerDatabase<'tcx> {
     annotations: FxHashMap<DefId, Vec<Annotation>>,
     external_annotations: ExternalMarkers,
     /// Cache whether markers are reachable transitively.
-    reachable_markers: Cache<MaybeMonomorphized<'tcx>, Box<[Identifier]>>,
+    reachable_markers: Cache<MaybeMonomorphized<'tcx>, Reachable>,
     /// Configuration options
     config: &'static Args,
     type_markers: Cache<ty::Ty<'tcx>, Box<TypeMarkers>>,
+    /// Unfiltered results of [`MarkerCtx::reachable_type_markers`].
+    type_reach: Cache<ty::Ty<'tcx>, Box<[(TypeMarkerElem, TypeReach)]>>,
     body_cache: Rc<BodyCache<'tcx>>,
     included_crates: FxHashSet<CrateNum>,
     stubs: FxHashMap<DefId, &'static Stub>,
+    /// Functions whose configured stub was already reported as inapplicable.
+    non_applicable_stubs: RefCell<FxHashSet<DefId>>,
 }
 
 impl<'tcx> MarkerDatabase<'tcx> {
@@ -590,7 +944,7 @@ impl<'tcx> MarkerDatabase<'tcx> {
         body_cache: Rc<BodyCache<'tcx>>,
         included_crates: FxHashSet<CrateNum>,
     ) -> Self {
-        let stubs = args
+        let mut stubs: FxHashMap<_, _> = args
             .build_config()
             .stubs
             .iter()
@@ -600,6 +954,13 @@ impl<'tcx> MarkerDatabase<'tcx> {
                 Some((res, v))
             })
             .collect();
+        // The crates of the built-in stubs may not be dependencies, so these
+        // are resolved silently.
+        for (path, stub) in BUILTIN_STUBS.iter() {
+            if let Ok(def_id) = resolve_string_to_def_id(tcx, path) {
+                stubs.entry(def_id).or_insert(stub);
+            }
+        }
         Self {
             tcx,
             annotations: load_annotations(tcx, included_crates.iter().copied()),
@@ -607,9 +968,11 @@ impl<'tcx> MarkerDatabase<'tcx> {
             reachable_markers: Default::default(),
             config: args,
             type_markers: Default::default(),
+            type_reach: Default::default(),
             body_cache,
             included_crates,
             stubs,
+            non_applicable_stubs: Default::default(),
         }
     }
 }
@@ -622,47 +985,73 @@ fn load_annotations(
         .into_iter()
         .flat_map(|krate| {
             let paths = local_or_remote_paths(krate, tcx, MARKER_META_EXT);
-            for path in &paths {
-                let Ok(mut file) = File::open(path) else {
-                    continue;
-                };
+            let meta = paths.iter().find_map(|path| {
+                let mut file = File::open(path).ok()?;
                 let mut buf = Vec::new();
                 file.read_to_end(&mut buf).unwrap();
                 let mut decoder = ParalegalDecoder::new(tcx, buf.as_slice());
-                let meta = MarkerMeta::decode(&mut decoder);
-                return meta
-                    .into_iter()
-                    .map(move |(index, v)| (DefId { krate, index }, v));
+                Some(MarkerMeta::decode(&mut decoder))
+            });
+            if meta.is_none() {
+                // The crate was not compiled with paralegal-flow. Calls into
+                // it are approximated because its bodies are missing too.
+                let name = tcx.crate_name(krate);
+                let mut diagnostic = tcx.sess.struct_warn(format!(
+                    "no marker metadata found for crate `{name}`, markers in it are unknown"
+                ));
+                diagnostic.note(format!("tried paths {paths:?}"));
+                diagnostic.emit();
             }
-            panic!("No marker metadata fund for crate {krate}, tried paths {paths:?}");
+            meta.unwrap_or_default()
+                .into_iter()
+                .map(move |(index, v)| (DefId { krate, index }, v))
         })
         .collect()
 }
 
-type RawExternalMarkers = HashMap<String, Vec<crate::ann::MarkerAnnotation>>;
+/// The markers of one entry in the external annotations file.
+#[derive(Clone, Debug)]
+pub struct ExternalEntry {
+    /// Line the entry starts on (1-based)
+    pub line: usize,
+    pub markers: Vec<MarkerAnnotation>,
+}
+
+pub(crate) type RawExternalMarkers = HashMap<String, ExternalEntry>;
+
+/// Parse the TOML file of external annotations without resolving the paths.
+pub(crate) fn read_external_markers(annotation_file: &std::path::Path) -> RawExternalMarkers {
+    let content = std::fs::read_to_string(annotation_file).unwrap_or_else(|_| {
+        panic!(
+            "Could not open file {}",
+            annotation_file
+                .canonicalize()
+                .unwrap_or_else(|_| annotation_file.to_path_buf())
+                .display()
+        )
+    });
+    let raw: HashMap<toml::Spanned<String>, Vec<MarkerAnnotation>> =
+        toml::from_str(&content).unwrap();
+    raw.into_iter()
+        .map(|(path, markers)| {
+            // Lines are 1-based
+            let line = content[..path.span().start].matches('\n').count() + 1;
+            (path.into_inner(), ExternalEntry { line, markers })
+        })
+        .collect()
+}
 
 /// Given the TOML of external annotations we have parsed, resolve the paths
 /// (keys of the map) to [`DefId`]s.
 fn resolve_external_markers(opts: &Args, tcx: TyCtxt) -> ExternalMarkers {
     if let Some(annotation_file) = opts.marker_control().external_annotations() {
-        let from_toml: RawExternalMarkers = toml::from_str(
-            &std::fs::read_to_string(annotation_file).unwrap_or_else(|_| {
-                panic!(
-                    "Could not open file {}",
-                    annotation_file
-                        .canonicalize()
-                        .unwrap_or_else(|_| annotation_file.to_path_buf())
-                        .display()
-                )
-            }),
-        )
-        .unwrap();
+        let from_toml = read_external_markers(annotation_file);
         let new_map: ExternalMarkers = from_toml
-            .iter()
-            .filter_map(|(path, marker)| {
+            .into_iter()
+            .filter_map(|(path, entry)| {
                 Some((
-                    expect_resolve_string_to_def_id(tcx, path, opts.relaxed())?,
-                    marker.clone(),
+                    expect_resolve_string_to_def_id(tcx, &path, opts.relaxed())?,
+                    entry,
                 ))
             })
             .collect();
@@ -1,24 +1,32 @@
 use either::Either;
-use flowistry_pdg_construction::{body_cache::intermediate_out_dir, encoder::ParalegalEncoder};
+use flowistry_pdg_construction::{
+    body_cache::{add_to_body_store, intermediate_out_dir},
+    encoder::ParalegalEncoder,
+};
 use rustc_ast::Attribute;
 
 use rustc_hir::{
-    def_id::DefIndex,
+    def::DefKind,
+    def_id::{DefIndex, LocalDefId},
     intravisit::{self, nested_filter::NestedFilter},
+    AsyncGeneratorKind, GeneratorKind,
 };
 use rustc_macros::{Decodable, Encodable};
 use rustc_middle::{hir::map::Map, ty::TyCtxt};
 use rustc_serialize::Encodable;
 use serde::{Deserialize, Serialize};
 
-use paralegal_spdg::{rustc_proxies, tiny_bitset_pretty, Identifier, TinyBitSet, TypeId};
+use paralegal_spdg::{
+    rustc_proxies, tiny_bitset_pretty, Identifier, MarkerPropagation, TinyBitSet, TypeId,
+};
 
+pub mod check;
 pub mod db;
 pub mod parse;
 
 use parse::*;
 
-use crate::{discover::AttrMatchT, sym_vec, utils::MetaItemMatch};
+use crate::{discover::AttrMatchT, sym_vec, utils::MetaItemMatch, HashSet};
 
 /// Types of annotations we support.
 ///
@@ -91,6 +99,10 @@ pub struct MarkerAnnotation {
     pub marker: Identifier,
     #[serde(flatten)]
     pub refinement: MarkerRefinement,
+    /// How this marker propagates to types containing the marked type. Only
+    /// meaningful on types.
+    #[serde(default)]
+    pub propagate: MarkerPropagation,
 }
 
 fn const_false() -> bool {
@@ -230,20 +242,97 @@ impl<'tcx> intravisit::Visitor<'tcx> for DumpingVisitor<'tcx> {
         let Some(owner) = hir_id.as_owner() else {
             return;
         };
-        let v: Vec<_> = self
-            .tcx
+        match self.tcx.def_kind(owner.def_id) {
+            // Markers on these are not recorded for the container itself but
+            // inherited by the functions within.
+            DefKind::Impl { .. } | DefKind::Mod => (),
+            DefKind::Fn | DefKind::AssocFn => {
+                let mut v = self.parse_annotations(hir_id);
+                self.add_inherited_markers(owner.def_id, &mut v);
+                self.push_annotations(owner.def_id.local_def_index, v);
+            }
+            _ => self.record_annotations(hir_id, owner.def_id.local_def_index),
+        }
+    }
+
+    /// Closures and `async` blocks are not HIR owners, so their annotations
+    /// (which sit on the expression) are not found by `visit_id`.
+    fn visit_expr(&mut self, ex: &'tcx rustc_hir::Expr<'tcx>) {
+        if let rustc_hir::ExprKind::Closure(closure) = ex.kind {
+            let tcx = self.tcx;
+            let mut v = self.parse_annotations(ex.hir_id);
+            // The body of an `async fn` is covered by the markers of the
+            // function itself.
+            if !matches!(
+                tcx.generator_kind(closure.def_id),
+                Some(GeneratorKind::Async(AsyncGeneratorKind::Fn))
+            ) {
+                let root = tcx.typeck_root_def_id(closure.def_id.to_def_id());
+                self.add_inherited_markers(root.expect_local(), &mut v);
+            }
+            self.push_annotations(closure.def_id.local_def_index, v);
+        }
+        intravisit::walk_expr(self, ex)
+    }
+}
+
+impl<'tcx> DumpingVisitor<'tcx> {
+    /// Parse the annotations attached to `hir_id` and store them for the item
+    /// `index`.
+    fn record_annotations(&mut self, hir_id: rustc_hir::HirId, index: DefIndex) {
+        let v = self.parse_annotations(hir_id);
+        self.push_annotations(index, v);
+    }
+
+    fn parse_annotations(&self, hir_id: rustc_hir::HirId) -> Vec<Annotation> {
+        self.tcx
             .hir()
             .attrs(hir_id)
             .iter()
             .flat_map(|ann| self.try_parse_annotation(ann).unwrap())
-            .collect();
+            .collect()
+    }
+
+    fn push_annotations(&mut self, index: DefIndex, v: Vec<Annotation>) {
         if !v.is_empty() {
-            self.annotations.push((owner.def_id.local_def_index, v));
+            self.annotations.push((index, v));
+        }
+    }
+
+    /// Add the markers placed on the `impl` blocks and modules enclosing the
+    /// function `def_id` (for closures and `async` blocks the function they
+    /// are defined in).
+    ///
+    /// A marker on the function itself overrides any marker of the same name
+    /// on a container and markers on inner containers override those on outer
+    /// ones.
+    fn add_inherited_markers(&self, def_id: LocalDefId, anns: &mut Vec<Annotation>) {
+        let tcx = self.tcx;
+        let mut seen = anns
+            .iter()
+            .filter_map(Annotation::as_marker)
+            .map(|m| m.marker)
+            .collect::<HashSet<_>>();
+        let mut parent = tcx.opt_local_parent(def_id);
+        while let Some(container) = parent {
+            if matches!(tcx.def_kind(container), DefKind::Impl { .. } | DefKind::Mod) {
+                let inherited = self
+                    .parse_annotations(tcx.local_def_id_to_hir_id(container))
+                    .into_iter()
+                    .filter(|ann| ann.as_marker().map_or(false, |m| !seen.contains(&m.marker)))
+                    .collect::<Vec<_>>();
+                seen.extend(
+                    inherited
+                        .iter()
+                        .filter_map(Annotation::as_marker)
+                        .map(|m| m.marker),
+                );
+                anns.extend(inherited);
+            }
+            parent = tcx.opt_local_parent(container);
         }
     }
-}
 
-impl<'tcx> DumpingVisitor<'tcx> {
     fn try_parse_annotation(
         &self,
         a: &Attribute,
@@ -279,7 +368,10 @@ pub fn dump_markers(tcx: TyCtxt) {
         symbols: Default::default(),
     };
     tcx.hir().visit_all_item_likes_in_crate(&mut vis);
-    let mut encoder = ParalegalEncoder::new(intermediate_out_dir(tcx, MARKER_META_EXT), tcx);
+    let path = intermediate_out_dir(tcx, MARKER_META_EXT);
+    let mut encoder = ParalegalEncoder::new(&path, tcx);
     vis.annotations.encode(&mut encoder);
     encoder.finish();
+    // Stored next to the bodies, consumers of the store need both.
+    add_to_body_store(tcx, &path, MARKER_META_EXT);
 }
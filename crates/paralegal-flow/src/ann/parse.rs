@@ -12,13 +12,18 @@ use super::{
     ExceptionAnnotation, MarkerAnnotation, MarkerRefinement, MarkerRefinementKind, VerificationHash,
 };
 use crate::{
-    utils::{resolve::def_path_res, TinyBitSet},
+    utils::{
+        resolve::{def_path_res, resolve_attribute_instantiation},
+        TinyBitSet,
+    },
     Symbol,
 };
-use paralegal_spdg::Identifier;
+use paralegal_spdg::{Identifier, MarkerPropagation};
 
+use flowistry_pdg_construction::utils::Instantiation;
 use rustc_ast::{self as ast, token, tokenstream, ExprKind};
-use rustc_hir::def_id::DefId;
+use rustc_ast_pretty::pprust;
+use rustc_hir::def_id::{DefId, LocalDefId};
 use rustc_middle::ty::TyCtxt;
 use rustc_parse::parser as rustc_parser;
 use token::*;
@@ -38,6 +43,9 @@ pub struct Symbols {
     /// The symbol `verification_hash` which we use for refinement in a
     /// `#[paralegal_flow::exception(...)]` annotation.
     verification_hash_sym: Symbol,
+    /// The symbol `propagate` which we use to select the propagation rule in a
+    /// `#[paralegal_flow::marker(...)]` annotation.
+    propagate_sym: Symbol,
 }
 
 impl Default for Symbols {
@@ -46,6 +54,7 @@ impl Default for Symbols {
             arg_sym: Symbol::intern("arguments"),
             return_sym: Symbol::intern("return"),
             verification_hash_sym: Symbol::intern("verification_hash"),
+            propagate_sym: Symbol::intern("propagate"),
         }
     }
 }
@@ -267,6 +276,90 @@ pub(crate) fn otype_ann_match(ann: &ast::AttrArgs, tcx: TyCtxt) -> Result<Vec<De
     }
 }
 
+/// The payload of a `#[paralegal_flow::analyze(...)]` annotation.
+#[derive(Default)]
+pub(crate) struct AnalyzeAnnotation<'tcx> {
+    /// Explicitly instantiated type parameters
+    pub instantiation: Instantiation<'tcx>,
+    /// Values of `tag = "..."`
+    pub tags: Vec<Identifier>,
+    /// Value of `route = "..."`
+    pub route: Option<Identifier>,
+}
+
+/// Parser for the payload of the `#[paralegal_flow::analyze(...)]`
+/// annotation on `function`. A comma separated list of `tag = "name"` (may be
+/// given several times), `route = "/path"` and instantiations of type
+/// parameters as in `T = crate::MyDb`, see [`resolve_attribute_instantiation`].
+pub(crate) fn analyze_ann_match<'tcx>(
+    ann: &ast::AttrArgs,
+    tcx: TyCtxt<'tcx>,
+    function: LocalDefId,
+) -> Result<AnalyzeAnnotation<'tcx>, String> {
+    let dargs = match ann {
+        ast::AttrArgs::Empty => return Ok(AnalyzeAnnotation::default()),
+        ast::AttrArgs::Delimited(dargs) => dargs,
+        _ => return Result::Err("Expected delimited annotation".to_owned()),
+    };
+    let tag_sym = Symbol::intern("tag");
+    let route_sym = Symbol::intern("route");
+    let invalid = || {
+        format!(
+            "Expected `tag = \"..\"`, `route = \"..\"` or type arguments, got {}",
+            pprust::tts_to_string(&dargs.tokens)
+        )
+    };
+    let mut parser = rustc_parser::Parser::new(&tcx.sess.parse_sess, dargs.tokens.clone(), None);
+    let mut annotation = AnalyzeAnnotation::default();
+    let mut type_args = vec![];
+    while parser.token.kind != TokenKind::Eof {
+        let key = if parser.token.is_ident() && parser.look_ahead(1, |t| t.kind == TokenKind::Eq) {
+            let key = parser.parse_ident().map_err(|e| {
+                e.cancel();
+                invalid()
+            })?;
+            parser.bump();
+            Some(key)
+        } else {
+            None
+        };
+        match key {
+            Some(key) if key.name == tag_sym || key.name == route_sym => {
+                let TokenKind::Literal(Lit {
+                    kind: LitKind::Str,
+                    symbol,
+                    ..
+                }) = parser.token.kind
+                else {
+                    return Result::Err(invalid());
+                };
+                parser.bump();
+                let value = Identifier::new(symbol);
+                if key.name == tag_sym {
+                    annotation.tags.push(value);
+                } else if annotation.route.replace(value).is_some() {
+                    return Result::Err("`route` is given more than once".to_owned());
+                }
+            }
+            key => {
+                let ty = parser.parse_ty().map_err(|e| {
+                    e.cancel();
+                    invalid()
+                })?;
+                type_args.push((key, ty));
+            }
+        }
+        if parser.token.kind != TokenKind::Eof {
+            parser.expect(&TokenKind::Comma).map_err(|e| {
+                e.cancel();
+                invalid()
+            })?;
+        }
+    }
+    annotation.instantiation = resolve_attribute_instantiation(tcx, function, &type_args)?;
+    Ok(annotation)
+}
+
 /// Parser for an [`ExceptionAnnotation`]
 pub(crate) fn match_exception(
     symbols: &Symbols,
@@ -296,34 +389,39 @@ pub(crate) fn match_exception(
     }
 }
 
-/// A parser for annotation refinements.
+/// A parser for a single annotation refinement (`arguments = [..]` or
+/// `return`).
 ///
 /// Is not guaranteed to consume the entire input if does not match. You may
 /// want to call [`nom::combinator::eof`] afterwards to guarantee all input has
 /// been consumed.
-fn refinements_parser<'a>(symbols: &Symbols, i: I<'a>) -> R<'a, MarkerRefinement> {
-    nom::combinator::map_res(
-        // nom::multi::separated_list0(
-        //     assert_token(TokenKind::Comma),
-        nom::branch::alt((
-            nom::sequence::preceded(
-                nom::sequence::tuple((
-                    assert_identifier(symbols.arg_sym),
-                    assert_token(TokenKind::Eq),
-                )),
-                nom::combinator::map(tiny_bitset, MarkerRefinementKind::Argument),
-            ),
-            nom::combinator::value(
-                MarkerRefinementKind::Return,
-                assert_identifier(symbols.return_sym),
-            ),
+fn refinement_parser<'a>(symbols: &Symbols, i: I<'a>) -> R<'a, MarkerRefinementKind> {
+    nom::branch::alt((
+        nom::sequence::preceded(
+            nom::sequence::tuple((
+                assert_identifier(symbols.arg_sym),
+                assert_token(TokenKind::Eq),
+            )),
+            nom::combinator::map(tiny_bitset, MarkerRefinementKind::Argument),
+        ),
+        nom::combinator::value(
+            MarkerRefinementKind::Return,
+            assert_identifier(symbols.return_sym),
+        ),
+    ))(i)
+}
+
+/// Parser for the `propagate = "..."` option of a marker annotation.
+fn propagation_parser<'a>(symbols: &Symbols, i: I<'a>) -> R<'a, MarkerPropagation> {
+    nom::sequence::preceded(
+        nom::sequence::tuple((
+            assert_identifier(symbols.propagate_sym),
+            assert_token(TokenKind::Eq),
         )),
-        //),
-        |refinements| {
-            vec![refinements]
-                .into_iter()
-                .try_fold(MarkerRefinement::empty(), MarkerRefinement::merge_kind)
-        },
+        lit(LitKind::Str, |s| {
+            s.parse::<MarkerPropagation>()
+                .map_err(|e: strum::ParseError| e.to_string())
+        }),
     )(i)
 }
 
@@ -338,17 +436,43 @@ pub(crate) fn ann_match_fn(
         ast::AttrArgs::Delimited(dargs) => {
             let p = |i| {
                 let (i, label) = identifier(i)?;
-                let (i, cont) = nom::combinator::opt(assert_token(TokenKind::Comma))(i)?;
-                let (i, refinement) =
-                    nom::combinator::cond(cont.is_some(), |c| refinements_parser(symbols, c))(i)?;
+                // Refinements and the propagation rule may come in any order
+                let (i, options) = nom::multi::many0(nom::sequence::preceded(
+                    assert_token(TokenKind::Comma),
+                    nom::branch::alt((
+                        nom::combinator::map(
+                            |c| propagation_parser(symbols, c),
+                            crate::Either::Left,
+                        ),
+                        nom::combinator::map(
+                            |c| refinement_parser(symbols, c),
+                            crate::Either::Right,
+                        ),
+                    )),
+                ))(i)?;
+                let (i, _) = nom::combinator::opt(assert_token(TokenKind::Comma))(i)?;
                 let (_, _) = nom::combinator::eof(i)?;
-                Ok(MarkerAnnotation {
-                    marker: Identifier::new(label),
-                    refinement: refinement.unwrap_or_else(MarkerRefinement::empty),
-                })
+                Ok((label, options))
             };
-            p(I::from_stream(&dargs.tokens))
-                .map_err(|err: nom::Err<_>| format!("parser failed with error {err:?}"))
+            let (label, options) = p(I::from_stream(&dargs.tokens))
+                .map_err(|err: nom::Err<_>| format!("parser failed with error {err:?}"))?;
+            let mut refinement = MarkerRefinement::empty();
+            let mut propagate = None;
+            for option in options {
+                match option {
+                    crate::Either::Left(rule) => {
+                        if propagate.replace(rule).is_some() {
+                            return Err("Double propagate annotation".to_string());
+                        }
+                    }
+                    crate::Either::Right(kind) => refinement = refinement.merge_kind(kind)?,
+                }
+            }
+            Ok(MarkerAnnotation {
+                marker: Identifier::new(label),
+                refinement,
+                propagate: propagate.unwrap_or_default(),
+            })
         }
         _ => Result::Err("Expected delimited annotation".to_owned()),
     }
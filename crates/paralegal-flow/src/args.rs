@@ -39,6 +39,27 @@ fn env_var_expect_unicode(k: impl AsRef<OsStr>) -> Result<Option<String>, VarErr
     }
 }
 
+/// Split a comma separated list of paths, ignoring commas inside the generic
+/// arguments of a path, such as in `crate::f::<A, B>`.
+fn split_target_paths(s: &str) -> Vec<String> {
+    let mut paths = vec![];
+    let mut depth = 0_usize;
+    let mut start = 0;
+    for (idx, c) in s.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                paths.push(s[start..idx].trim().to_owned());
+                start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    paths.push(s[start..].trim().to_owned());
+    paths
+}
+
 impl TryFrom<ClapArgs> for Args {
     type Error = Error;
     fn try_from(value: ClapArgs) -> Result<Self, Self::Error> {
@@ -47,6 +68,7 @@ impl TryFrom<ClapArgs> for Args {
             debug,
             debug_target,
             result_path,
+            stats_json,
             relaxed,
             target,
             abort_after_analysis,
@@ -56,6 +78,8 @@ impl TryFrom<ClapArgs> for Args {
             cargo_args,
             trace,
             attach_to_debugger,
+            body_store,
+            populate_body_store,
         } = value;
         let mut dump: DumpArgs = dump.into();
         if let Some(from_env) = env_var_expect_unicode("PARALEGAL_DUMP")? {
@@ -66,12 +90,10 @@ impl TryFrom<ClapArgs> for Args {
         anactrl.analyze = anactrl
             .analyze
             .iter()
-            .flat_map(|s| s.split(',').map(ToOwned::to_owned))
+            .flat_map(|s| split_target_paths(s))
             .collect();
         if let Some(from_env) = env_var_expect_unicode("PARALEGAL_ANALYZE")? {
-            anactrl
-                .analyze
-                .extend(from_env.split(',').map(ToOwned::to_owned));
+            anactrl.analyze.extend(split_target_paths(&from_env));
         }
         let build_config_file = std::path::Path::new("Paralegal.toml");
         let build_config: (_, BuildConfig) = if let Ok(absolute) = build_config_file.canonicalize()
@@ -101,6 +123,7 @@ impl TryFrom<ClapArgs> for Args {
             verbosity,
             log_level_config,
             result_path,
+            stats_json,
             relaxed,
             target,
             abort_after_analysis,
@@ -110,6 +133,8 @@ impl TryFrom<ClapArgs> for Args {
             marker_control,
             cargo_args,
             attach_to_debugger,
+            body_store,
+            populate_body_store,
         })
     }
 }
@@ -127,6 +152,8 @@ pub struct Args {
     log_level_config: LogLevelConfig,
     /// Where to write the resulting forge code to (defaults to `analysis_result.frg`)
     result_path: std::path::PathBuf,
+    /// Where to write analysis statistics as JSON, if anywhere
+    stats_json: Option<std::path::PathBuf>,
     /// Emit warnings instead of aborting the analysis on sanity checks
     relaxed: bool,
     /// Target a specific package
@@ -145,6 +172,10 @@ pub struct Args {
     build_config: (Option<PathBuf>, BuildConfig),
     /// Additional options for cargo
     cargo_args: Vec<String>,
+    /// The shared store for the dumped bodies of dependencies
+    body_store: Option<PathBuf>,
+    /// Only dump bodies into the store, do not analyze
+    populate_body_store: bool,
 }
 
 impl Default for Args {
@@ -153,6 +184,7 @@ impl Default for Args {
             verbosity: log::LevelFilter::Info,
             log_level_config: LogLevelConfig::Disabled,
             result_path: PathBuf::from(paralegal_spdg::FLOW_GRAPH_OUT_NAME),
+            stats_json: None,
             relaxed: false,
             target: None,
             abort_after_analysis: false,
@@ -162,6 +194,8 @@ impl Default for Args {
             build_config: Default::default(),
             cargo_args: Vec::new(),
             attach_to_debugger: None,
+            body_store: None,
+            populate_body_store: false,
         }
     }
 }
@@ -190,6 +224,10 @@ pub struct ClapArgs {
     /// Where to write the resulting GraphLocation (defaults to `flow-graph.json`)
     #[clap(long, default_value = paralegal_spdg::FLOW_GRAPH_OUT_NAME)]
     result_path: std::path::PathBuf,
+    /// Write statistics about the analysis (timing, analyzed and approximated
+    /// functions per controller) as JSON to this file.
+    #[clap(long)]
+    stats_json: Option<std::path::PathBuf>,
     /// Emit warnings instead of aborting the analysis on sanity checks
     #[clap(long, env = "PARALEGAL_RELAXED")]
     relaxed: bool,
@@ -202,6 +240,18 @@ pub struct ClapArgs {
     /// Attach to a debugger before running the analyses
     #[clap(long)]
     attach_to_debugger: Option<Debugger>,
+    /// Directory of a shared store for the dumped MIR bodies and markers of
+    /// dependencies. Dependencies missing from the store are dumped into it,
+    /// those found in it are not dumped again. Dependencies in the store are
+    /// inlined as if they were passed to `--include`. Disabled if not set.
+    #[clap(long, env = "PARALEGAL_BODY_STORE")]
+    body_store: Option<PathBuf>,
+    /// Dump the bodies of all dependencies in the build into the body store
+    /// instead of analyzing. To also store the standard library pass
+    /// `-- -Zbuild-std --target <triple>` and use the same flags when
+    /// analyzing.
+    #[clap(long, requires = "body_store")]
+    populate_body_store: bool,
     /// Additional arguments that control the flow analysis specifically
     #[clap(flatten, next_help_heading = "Flow Analysis")]
     anactrl: ClapAnalysisCtrl,
@@ -355,6 +405,10 @@ impl Args {
     pub fn result_path(&self) -> &std::path::Path {
         self.result_path.as_path()
     }
+    /// The file to write analysis statistics to, if requested
+    pub fn stats_json(&self) -> Option<&std::path::Path> {
+        self.stats_json.as_deref()
+    }
     /// Should we output additional log messages (level `info`)
     pub fn verbosity(&self) -> log::LevelFilter {
         self.verbosity
@@ -380,6 +434,8 @@ impl Args {
         self.relaxed.hash(hasher);
         self.target.hash(hasher);
         self.result_path.hash(hasher);
+        self.body_store.hash(hasher);
+        self.populate_body_store.hash(hasher);
         config_hash_for_file(&self.marker_control.external_annotations, hasher);
     }
 
@@ -395,6 +451,16 @@ impl Args {
         self.attach_to_debugger
     }
 
+    /// The directory of the body store, if enabled
+    pub fn body_store(&self) -> Option<&std::path::Path> {
+        self.body_store.as_deref()
+    }
+
+    /// Only populate the body store, analyze nothing
+    pub fn populate_body_store(&self) -> bool {
+        self.populate_body_store
+    }
+
     pub fn setup_logging(&self) {
         let lvl = self.verbosity();
         // //let lvl = log::LevelFilter::Debug;
@@ -436,12 +502,23 @@ pub struct MarkerControl {
     /// `dump_serialized_flow_graph`.
     #[clap(long, env)]
     external_annotations: Option<std::path::PathBuf>,
+    /// Validate the external annotations file and print a report instead of
+    /// writing the analysis result. Every entry is resolved and argument
+    /// refinements are checked against the function signature. If all entries
+    /// are valid the analysis is run and markers that appear on no node or
+    /// type of its graph are also reported.
+    #[clap(long)]
+    check_annotations: bool,
 }
 
 impl MarkerControl {
     pub fn external_annotations(&self) -> Option<&std::path::Path> {
         self.external_annotations.as_deref()
     }
+
+    pub fn check_annotations(&self) -> bool {
+        self.check_annotations
+    }
 }
 
 /// Arguments that control the flow analysis
@@ -451,6 +528,12 @@ struct ClapAnalysisCtrl {
     /// `#[paralegal::analyze]`). Must be a full rust path and resolve to a
     /// function. May be specified multiple times and multiple, comma separated
     /// paths may be supplied at the same time.
+    ///
+    /// Type parameters can be instantiated with concrete types by ending the
+    /// path in generic arguments, e.g. `crate::handle::<crate::Postgres>`, or
+    /// `Name = Type` for parameters of a surrounding `impl`. A function
+    /// selected with several instantiations is analyzed for each of them and
+    /// their graphs together form its controller.
     #[clap(long)]
     analyze: Vec<String>,
     /// Disables all recursive analysis (both paralegal_flow's inlining as well as
@@ -458,18 +541,32 @@ struct ClapAnalysisCtrl {
     #[clap(long, env)]
     no_cross_function_analysis: bool,
     /// Generate PDGs that span all called functions which can attach markers
-    #[clap(long, conflicts_with_all = ["unconstrained_depth", "no_cross_function_analysis"])]
+    #[clap(long, conflicts_with_all = ["unconstrained_depth", "no_cross_function_analysis", "bounded_depth"])]
     adaptive_depth: bool,
     /// Generate PDGs that span to all functions for which we have source code.
     ///
     /// If no depth option is specified this is the default right now but that
     /// is not guaranteed to be the case in the future. If you want to guarantee
     /// this is used explicitly supply the argument.
-    #[clap(long, conflicts_with_all = ["adaptive_depth", "no_cross_function_analysis"])]
+    #[clap(long, conflicts_with_all = ["adaptive_depth", "no_cross_function_analysis", "bounded_depth"])]
     unconstrained_depth: bool,
+    /// Inline at most this many levels of calls below each controller and
+    /// approximate the calls beyond.
+    #[clap(long, conflicts_with_all = ["adaptive_depth", "unconstrained_depth", "no_cross_function_analysis"])]
+    bounded_depth: Option<usize>,
     /// Crates that should be recursed into.
     #[clap(long)]
     include: Vec<String>,
+    /// Resolve calls through `dyn Trait` objects to every implementation that
+    /// is coerced to such an object in the analyzed crate, instead of
+    /// approximating them. This assumes no other implementations exist.
+    #[clap(long, env)]
+    closed_world_dyn_dispatch: bool,
+    /// Assume that dereferences of raw pointers, transmutes and foreign calls
+    /// may access any place of the pointee type. Such operations are listed
+    /// in the `unsafe_approximations` of the output.
+    #[clap(long, env)]
+    conservative_unsafe: bool,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -478,11 +575,22 @@ pub struct AnalysisCtrl {
     /// `#[paralegal::analyze]`). Must be a full rust path and resolve to a
     /// function. May be specified multiple times and multiple, comma separated
     /// paths may be supplied at the same time.
+    ///
+    /// Type parameters can be instantiated with concrete types by ending the
+    /// path in generic arguments, e.g. `crate::handle::<crate::Postgres>`, or
+    /// `Name = Type` for parameters of a surrounding `impl`. A function
+    /// selected with several instantiations is analyzed for each of them and
+    /// their graphs together form its controller.
     analyze: Vec<String>,
     /// Disables all recursive analysis (both paralegal_flow's inlining as well as
     /// Flowistry's recursive analysis).
     inlining_depth: InliningDepth,
     include: Vec<String>,
+    /// Resolve calls through trait objects to all locally coerced
+    /// implementations.
+    closed_world_dyn_dispatch: bool,
+    /// Treat unsafe operations conservatively.
+    conservative_unsafe: bool,
 }
 
 impl Default for AnalysisCtrl {
@@ -491,6 +599,8 @@ impl Default for AnalysisCtrl {
             analyze: Vec::new(),
             inlining_depth: InliningDepth::Adaptive,
             include: Default::default(),
+            closed_world_dyn_dispatch: false,
+            conservative_unsafe: false,
         }
     }
 }
@@ -503,11 +613,16 @@ impl TryFrom<ClapAnalysisCtrl> for AnalysisCtrl {
             no_cross_function_analysis,
             adaptive_depth,
             unconstrained_depth: _,
+            bounded_depth,
             include,
+            closed_world_dyn_dispatch,
+            conservative_unsafe,
         } = value;
 
         let inlining_depth = if adaptive_depth {
             InliningDepth::Adaptive
+        } else if let Some(k) = bounded_depth {
+            InliningDepth::Bounded(k)
         } else if no_cross_function_analysis {
             InliningDepth::Shallow
         } else {
@@ -518,6 +633,8 @@ impl TryFrom<ClapAnalysisCtrl> for AnalysisCtrl {
             analyze,
             inlining_depth,
             include,
+            closed_world_dyn_dispatch,
+            conservative_unsafe,
         })
     }
 }
@@ -530,6 +647,19 @@ pub enum InliningDepth {
     Shallow,
     /// Inline so long as markers are reachable
     Adaptive,
+    /// Inline up to this many levels of calls below the controller
+    Bounded(usize),
+}
+
+impl InliningDepth {
+    /// The maximum number of call levels below a controller that are
+    /// inlined, `None` if there is no fixed limit.
+    pub fn call_string_limit(&self) -> Option<usize> {
+        match self {
+            InliningDepth::Bounded(k) => Some(*k),
+            _ => None,
+        }
+    }
 }
 
 impl AnalysisCtrl {
@@ -550,6 +680,17 @@ impl AnalysisCtrl {
     pub fn included(&self) -> &[String] {
         &self.include
     }
+
+    /// Are calls through trait objects resolved under the closed-world
+    /// assumption?
+    pub fn closed_world_dyn_dispatch(&self) -> bool {
+        self.closed_world_dyn_dispatch
+    }
+
+    /// Are raw pointers, transmutes and foreign calls treated conservatively?
+    pub fn conservative_unsafe(&self) -> bool {
+        self.conservative_unsafe
+    }
 }
 
 impl DumpArgs {
@@ -585,10 +726,52 @@ pub enum Stub {
     #[serde(rename_all = "kebab-case")]
     /// Replaces the result of a call to a higher-order function with a call to
     /// the input closure.
-    SubClosure { generic_name: String },
+    SubClosure {
+        generic_name: String,
+        /// What the closure is called with, one entry per parameter of the
+        /// closure. E.g. `[{ element-of = 0 }]` for `Option::map`, whose
+        /// closure receives the value in the option.
+        #[serde(default)]
+        arguments: Vec<ClosureArgumentSource>,
+    },
     #[serde(rename_all = "kebab-case")]
     /// Replaces the result of a higher-order future by an input future.
     SubFuture { generic_name: String },
+    #[serde(rename_all = "kebab-case")]
+    /// Replaces the effects of the call with the flows described here, instead
+    /// of inlining or approximating it.
+    Summary {
+        /// Arguments whose values flow into the return value.
+        #[serde(default)]
+        return_from: Vec<u8>,
+        /// Arguments the function writes through (e.g. `&mut` references) and
+        /// the arguments that flow into them.
+        #[serde(default)]
+        writes: Vec<ArgumentWrite>,
+        /// Markers to attach to the arguments and return value of the call.
+        #[serde(default)]
+        markers: Vec<crate::ann::MarkerAnnotation>,
+    },
+}
+
+/// The value a parameter of the closure of a [`Stub::SubClosure`] receives.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClosureArgumentSource {
+    /// The argument with this index of the replaced call
+    Argument(u8),
+    /// An element of the collection (or `Option`, `Result` etc.) passed as the
+    /// argument with this index of the replaced call
+    ElementOf(u8),
+}
+
+/// A write through an argument described by a [`Stub::Summary`].
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ArgumentWrite {
+    /// The argument that is written through
+    pub to: u8,
+    /// The arguments whose values are written
+    pub from: Vec<u8>,
 }
 
 /// Additional configuration for the build process/rustc
@@ -602,4 +785,122 @@ pub struct BuildConfig {
     pub include: Vec<String>,
     #[serde(default)]
     pub stubs: HashMap<String, Stub>,
+    /// Override the inlining decision for functions by path. A path is the
+    /// crate name followed by the enclosing modules and types, e.g.
+    /// `"myapp::db::Connection::query"`. A trailing `*` matches everything
+    /// below a module or type, e.g. `"serde_json::*"`. A `*` in any other
+    /// position matches exactly one segment, e.g. `"core::sync::atomic::*::store"`.
+    #[serde(default)]
+    pub inlining: HashMap<String, InliningOverride>,
+    /// Functions that mutate an interior-mutable value behind a shared
+    /// reference, by path (the same patterns as for [`Self::inlining`]).
+    /// These are in addition to the built-in models for `Cell`, `RefCell`,
+    /// `Mutex`, `RwLock` and the atomic types and take precedence over them.
+    /// Patterns that match the same function must agree on the model.
+    #[serde(default)]
+    pub interior_mutability: HashMap<String, InteriorMutabilityModel>,
+    /// Functions that send values into or receive values from a channel, by
+    /// path (the same patterns as for [`Self::inlining`]). Within a controller
+    /// the values sent flow to the results of every receive on a channel with
+    /// the same element type, which is the first generic type argument of the
+    /// function (e.g. `T` for the methods of `Sender<T>`). These are in
+    /// addition to the built-in models for the `std` and `tokio` channels.
+    #[serde(default)]
+    pub channels: HashMap<String, ChannelModel>,
+    /// Rules that select controllers automatically, in addition to
+    /// `#[paralegal::analyze]`, e.g. the route handlers of a web framework.
+    /// The key names the rule in the
+    /// [`discovered_controllers`](paralegal_spdg::ProgramDescription::discovered_controllers)
+    /// of the output.
+    #[serde(default)]
+    pub discovery: HashMap<String, DiscoveryRule>,
+}
+
+/// A rule from [`BuildConfig::discovery`]. Every non-generic local function
+/// that matches one of the attributes or registrations becomes a controller.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct DiscoveryRule {
+    /// Attribute macros that mark a function, such as `"rocket::get"`. These
+    /// are patterns like those of [`BuildConfig::inlining`], matched against
+    /// both the path the macro is defined at and the path it is exported
+    /// under.
+    #[serde(default)]
+    pub attributes: Vec<String>,
+    /// Functions that register a handler, such as `axum::routing::get`.
+    #[serde(default)]
+    pub registrations: Vec<Registration>,
+}
+
+/// A function that takes a handler as argument, see
+/// [`DiscoveryRule::registrations`]. A function item passed as argument
+/// becomes a controller.
+///
+/// The handler has to be passed to the function directly. In axum, for
+/// instance, handlers are wrapped in a method router before they are given
+/// to the router, as in `Router::new().route("/", get(handler))`, so the
+/// registrations are the method routers:
+///
+/// ```toml
+/// [discovery.axum]
+/// registrations = [
+///     { function = "axum::routing::get", argument = 0 },
+///     { function = "axum::routing::post", argument = 0 },
+/// ]
+/// ```
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Registration {
+    /// Pattern for the registering function, like those of
+    /// [`BuildConfig::inlining`]. Like [`DiscoveryRule::attributes`] it is
+    /// matched against the path the function is defined at and the path it
+    /// is exported under.
+    pub function: String,
+    /// The argument that receives the handler. The receiver of a method is
+    /// argument `0`.
+    pub argument: u8,
+}
+
+/// How a function from [`BuildConfig::channels`] uses the channel.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ChannelModel {
+    /// Sends argument `value` into the channel, like `Sender::send`. For an
+    /// `async fn` the value is taken from the awaited future.
+    Send { value: u8 },
+    /// Returns a value received from the channel, like `Receiver::recv`.
+    Receive,
+}
+
+/// How a function from [`BuildConfig::interior_mutability`] accesses the
+/// interior-mutable value.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum InteriorMutabilityModel {
+    /// Overwrites the value behind argument `target` with the other
+    /// arguments, like `Cell::set`.
+    Set {
+        #[serde(default)]
+        target: u8,
+    },
+    /// Combines the value behind argument `target` with the other arguments,
+    /// like `AtomicUsize::fetch_add`.
+    Update {
+        #[serde(default)]
+        target: u8,
+    },
+    /// Returns a guard through which the value behind the first argument is
+    /// mutated, like `Mutex::lock`.
+    Guard,
+}
+
+/// How to treat calls to the functions matching a pattern in
+/// [`BuildConfig::inlining`]. Takes precedence over [`InliningDepth`].
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InliningOverride {
+    /// Always inline the function, even if it is marked or its crate is not
+    /// `--include`d, as long as its MIR body is available.
+    Inline,
+    /// Never inline the function, approximate calls to it instead.
+    Abstract,
 }
@@ -6,26 +6,39 @@
 use std::rc::Rc;
 
 use crate::{
-    ana::{InlineJudge, SPDGGenerator},
+    ana::{item_path, InlineJudge, PathPattern, SPDGGenerator},
+    ann::parse::{analyze_ann_match, AnalyzeAnnotation},
     desc::*,
     stats::Stats,
     sym_vec,
     utils::*,
+    HashMap, HashSet,
 };
 
-use flowistry_pdg_construction::body_cache::BodyCache;
+use flowistry_pdg_construction::{body_cache::BodyCache, utils::Instantiation};
 
+use itertools::Itertools;
+use rustc_ast_pretty::pprust;
 use rustc_hir::{
-    def_id::LocalDefId,
+    self as hir,
+    def::{DefKind, Res},
+    def_id::{DefId, LocalDefId, CRATE_DEF_INDEX},
     intravisit::{self, FnKind},
-    BodyId,
+    BodyId, ExprKind,
+};
+use rustc_middle::{
+    hir::nested_filter::OnlyBodies,
+    ty::{GenericArgs, TyCtxt},
+};
+use rustc_span::{
+    hygiene::{ExpnData, ExpnId, ExpnKind, MacroKind},
+    symbol::Ident,
+    Span, Symbol,
 };
-use rustc_middle::{hir::nested_filter::OnlyBodies, ty::TyCtxt};
-use rustc_span::{symbol::Ident, Span, Symbol};
 
 use anyhow::Result;
 
-use self::resolve::expect_resolve_string_to_def_id;
+use self::resolve::resolve_string_to_instantiated_fn;
 
 /// Values of this type can be matched against Rust attributes
 pub type AttrMatchT = Vec<Symbol>;
@@ -42,7 +55,7 @@ pub struct CollectingVisitor<'tcx> {
     pub opts: &'static crate::Args,
     /// Functions that are annotated with `#[paralegal_flow::analyze]`. For these we will
     /// later perform the analysis
-    pub functions_to_analyze: Vec<FnToAnalyze>,
+    pub functions_to_analyze: Vec<FnToAnalyze<'tcx>>,
 
     stats: Stats,
 
@@ -52,16 +65,77 @@ pub struct CollectingVisitor<'tcx> {
     /// This will match the annotation `#[paralegal_flow::analyze]` when using
     /// [`MetaItemMatch::match_extract`](crate::utils::MetaItemMatch::match_extract)
     analyze_marker: AttrMatchT,
+    /// Parsed attributes of [`BuildConfig::discovery`](crate::BuildConfig::discovery)
+    discovery_attributes: Vec<DiscoveryPattern>,
+    /// Parsed registrations of [`BuildConfig::discovery`](crate::BuildConfig::discovery)
+    /// with the index of the handler argument
+    discovery_registrations: Vec<(DiscoveryPattern, usize)>,
+    /// Functions selected by the discovery rules
+    discovered: Vec<Discovery>,
+    /// Parsed `#[paralegal_flow::analyze(...)]` annotations
+    analyze_annotations: HashMap<LocalDefId, AnalyzeAnnotation<'tcx>>,
+}
+
+/// A pattern of a rule from [`BuildConfig::discovery`](crate::BuildConfig::discovery).
+struct DiscoveryPattern {
+    rule: Identifier,
+    via: Identifier,
+    pattern: PathPattern,
+}
+
+impl DiscoveryPattern {
+    fn record(&self, discovered: &mut Vec<Discovery>, function: LocalDefId) {
+        let discovery = Discovery {
+            function,
+            rule: self.rule,
+            via: self.via,
+        };
+        if !discovered.contains(&discovery) {
+            discovered.push(discovery);
+        }
+    }
+}
+
+/// A function selected as controller by a rule from
+/// [`BuildConfig::discovery`](crate::BuildConfig::discovery).
+#[derive(PartialEq, Eq)]
+pub struct Discovery {
+    pub function: LocalDefId,
+    /// Name of the rule
+    pub rule: Identifier,
+    /// The attribute or registering function that matched
+    pub via: Identifier,
 }
 
 /// A function we will be targeting to analyze with
 /// [`CollectingVisitor::handle_target`].
-pub struct FnToAnalyze {
+pub struct FnToAnalyze<'tcx> {
     pub name: Ident,
     pub def_id: LocalDefId,
+    /// Concrete types for (some of) the type parameters of this function,
+    /// selected with `#[paralegal_flow::analyze(T = Type)]` or
+    /// `--analyze 'path::<Type>'`.
+    pub instantiation: Instantiation<'tcx>,
+    /// See [`SPDG::tags`]
+    pub tags: Vec<Identifier>,
+    /// See [`SPDG::route`]
+    pub route: Option<Identifier>,
+    /// See [`SPDG::attributes`]
+    pub attributes: Vec<String>,
 }
 
-impl FnToAnalyze {
+impl<'tcx> FnToAnalyze<'tcx> {
+    fn new(name: Ident, def_id: LocalDefId, instantiation: Instantiation<'tcx>) -> Self {
+        Self {
+            name,
+            def_id,
+            instantiation,
+            tags: vec![],
+            route: None,
+            attributes: vec![],
+        }
+    }
+
     /// Give me a name that describes this function.
     pub fn name(&self) -> Symbol {
         self.name.name
@@ -75,17 +149,47 @@ impl<'tcx> CollectingVisitor<'tcx> {
             .selected_targets()
             .iter()
             .filter_map(|path| {
-                let def_id = expect_resolve_string_to_def_id(tcx, path, opts.relaxed())?;
+                let (def_id, instantiation) = resolve_string_to_instantiated_fn(tcx, path)
+                    .map_err(|err| {
+                        if opts.relaxed() {
+                            tcx.sess.warn(err);
+                        } else {
+                            tcx.sess.err(err);
+                        }
+                    })
+                    .ok()?;
                 if !def_id.is_local() {
                     tcx.sess.span_err(tcx.def_span(def_id), format!("found an external function {def_id:?} as analysis target. Analysis targets are required to be local."));
                     return None;
                 }
-                Some(FnToAnalyze {
-                    def_id: def_id.as_local()?,
-                    name: tcx.opt_item_ident(def_id).unwrap(),
-                })
+                Some(FnToAnalyze::new(
+                    tcx.opt_item_ident(def_id).unwrap(),
+                    def_id.as_local()?,
+                    instantiation,
+                ))
             })
             .collect();
+        let mut discovery_attributes = vec![];
+        let mut discovery_registrations = vec![];
+        let rules = opts
+            .build_config()
+            .discovery
+            .iter()
+            .sorted_by_key(|(name, _)| *name);
+        for (name, rule) in rules {
+            let rule_name = Identifier::new_intern(name);
+            let pattern = |path: &str| DiscoveryPattern {
+                rule: rule_name,
+                via: Identifier::new_intern(path),
+                pattern: PathPattern::parse(path),
+            };
+            discovery_attributes.extend(rule.attributes.iter().map(|path| pattern(path)));
+            discovery_registrations.extend(
+                rule.registrations
+                    .iter()
+                    .map(|r| (pattern(&r.function), r.argument as usize)),
+            );
+        }
         let body_cache = Rc::new(BodyCache::new(tcx));
         let inline_judge = InlineJudge::new(tcx, body_cache.clone(), opts);
         Self {
@@ -96,6 +200,10 @@ impl<'tcx> CollectingVisitor<'tcx> {
             stats,
             analyze_marker: sym_vec!["paralegal_flow", "analyze"],
             body_cache,
+            discovery_attributes,
+            discovery_registrations,
+            discovered: vec![],
+            analyze_annotations: Default::default(),
         }
     }
 
@@ -117,18 +225,238 @@ impl<'tcx> CollectingVisitor<'tcx> {
     pub fn run(mut self) -> Result<ProgramDescription> {
         let tcx = self.tcx;
         tcx.hir().visit_all_item_likes_in_crate(&mut self);
-        let targets = std::mem::take(&mut self.functions_to_analyze);
-        self.into_generator().analyze(targets)
+        self.select_discovered();
+        let targets = self.checked_targets();
+        let discovered = std::mem::take(&mut self.discovered);
+        self.into_generator().analyze(targets, discovered)
+    }
+
+    /// Add the functions found by the discovery rules to the targets, unless
+    /// they are already selected otherwise. Generic functions are skipped
+    /// with a warning, they need an explicit instantiation.
+    fn select_discovered(&mut self) {
+        let tcx = self.tcx;
+        for function in self.discovered.iter().map(|d| d.function).unique() {
+            if self
+                .functions_to_analyze
+                .iter()
+                .any(|target| target.def_id == function)
+            {
+                continue;
+            }
+            if tcx.generics_of(function).requires_monomorphization(tcx) {
+                tcx.sess.span_warn(
+                    tcx.def_span(function),
+                    format!(
+                        "{} was discovered as a controller but is generic and will not be analyzed. Select an instantiation with `#[paralegal::analyze(..)]`.",
+                        tcx.def_path_str(function)
+                    ),
+                );
+                continue;
+            }
+            self.functions_to_analyze.push(FnToAnalyze::new(
+                tcx.opt_item_ident(function.to_def_id()).unwrap(),
+                function,
+                Instantiation::default(),
+            ));
+        }
+    }
+
+    /// Run the attribute rules of the discovery on the attribute macros the
+    /// function `id` was written with.
+    fn discover_by_attribute(&mut self, id: LocalDefId) {
+        if self.discovery_attributes.is_empty() {
+            return;
+        }
+        let tcx = self.tcx;
+        for mac in attribute_macros(tcx, id).filter_map(|data| data.macro_def_id) {
+            let defined_at = item_path(tcx, mac);
+            let exported_as = visible_item_path(tcx, mac);
+            for pattern in &self.discovery_attributes {
+                if pattern.pattern.matches(&defined_at) || pattern.pattern.matches(&exported_as) {
+                    pattern.record(&mut self.discovered, id);
+                }
+            }
+        }
+    }
+
+    /// Fill in the metadata of `target` that ends up on its [`SPDG`]: the
+    /// tags and route of its analyze annotation, tags from its doc comment and
+    /// its attributes.
+    fn describe_target(&self, target: &mut FnToAnalyze<'tcx>) {
+        let tcx = self.tcx;
+        if let Some(annotation) = self.analyze_annotations.get(&target.def_id) {
+            target.tags.extend(annotation.tags.iter().copied());
+            target.route = annotation.route;
+        }
+        // Attribute macros are expanded outermost first, so they come before
+        // the attributes that remain. Our own attributes are left out, the
+        // `paralegal` macros expand to `paralegal_flow` tool attributes.
+        let source_map = tcx.sess.source_map();
+        let mut macros = attribute_macros(tcx, target.def_id)
+            .filter(|data| {
+                data.macro_def_id
+                    .map_or(true, |mac| !is_paralegal_name(tcx.crate_name(mac.krate)))
+            })
+            .filter_map(|data| source_map.span_to_snippet(data.call_site).ok())
+            .collect::<Vec<_>>();
+        macros.reverse();
+        target.attributes = macros;
+        for attr in tcx.hir().attrs(tcx.local_def_id_to_hir_id(target.def_id)) {
+            if let Some(doc) = attr.doc_str() {
+                target
+                    .tags
+                    .extend(doc_tags(doc.as_str()).map(Identifier::new_intern));
+            } else if !is_paralegal_attribute(attr) {
+                target.attributes.push(pprust::attribute_to_string(attr));
+            }
+        }
+        let mut seen = HashSet::new();
+        target.tags.retain(|tag| seen.insert(*tag));
+    }
+
+    /// Takes the discovered targets, dropping duplicates and those whose
+    /// instantiation does not satisfy the bounds of the function, and
+    /// describes them with [`Self::describe_target`]. A function may be
+    /// selected with several instantiations, each one is analyzed and their
+    /// graphs make up the one controller of the function.
+    fn checked_targets(&mut self) -> Vec<FnToAnalyze<'tcx>> {
+        let tcx = self.tcx;
+        let mut targets: Vec<FnToAnalyze<'tcx>> = vec![];
+        for mut target in std::mem::take(&mut self.functions_to_analyze) {
+            if targets
+                .iter()
+                .any(|t| t.def_id == target.def_id && t.instantiation == target.instantiation)
+            {
+                continue;
+            }
+            if !target.instantiation.is_empty() {
+                let args = GenericArgs::for_item(tcx, target.def_id.to_def_id(), |param, _| {
+                    target
+                        .instantiation
+                        .get(&param.def_id)
+                        .map_or_else(|| tcx.mk_param_from_def(param), |ty| (*ty).into())
+                });
+                if tcx.subst_and_check_impossible_predicates((target.def_id.to_def_id(), args)) {
+                    tcx.sess.span_err(
+                        tcx.def_span(target.def_id),
+                        format!(
+                            "The instantiation {args:?} of {} does not satisfy its bounds",
+                            tcx.def_path_str(target.def_id)
+                        ),
+                    );
+                    continue;
+                }
+            }
+            self.describe_target(&mut target);
+            targets.push(target);
+        }
+        targets
+    }
+
+    /// Run the registration rules of the discovery on `expr`, if it is a call.
+    fn discover_by_registration(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+        let tcx = self.tcx;
+        let typeck = tcx.typeck(tcx.hir().enclosing_body_owner(expr.hir_id));
+        let (callee, args) = match expr.kind {
+            ExprKind::Call(
+                func @ hir::Expr {
+                    kind: ExprKind::Path(qpath),
+                    ..
+                },
+                args,
+            ) => (
+                typeck.qpath_res(qpath, func.hir_id).opt_def_id(),
+                args.iter().collect::<Vec<_>>(),
+            ),
+            ExprKind::MethodCall(_, receiver, args, _) => (
+                typeck.type_dependent_def_id(expr.hir_id),
+                std::iter::once(receiver).chain(args).collect(),
+            ),
+            _ => return,
+        };
+        let Some(callee) = callee else {
+            return;
+        };
+        let defined_at = item_path(tcx, callee);
+        let exported_as = visible_item_path(tcx, callee);
+        for (pattern, argument) in &self.discovery_registrations {
+            if !pattern.pattern.matches(&defined_at) && !pattern.pattern.matches(&exported_as) {
+                continue;
+            }
+            if let Some(hir::Expr {
+                kind: ExprKind::Path(qpath),
+                hir_id,
+                ..
+            }) = args.get(*argument)
+                && let Res::Def(DefKind::Fn | DefKind::AssocFn, handler) =
+                    typeck.qpath_res(qpath, *hir_id)
+                && let Some(handler) = handler.as_local()
+            {
+                pattern.record(&mut self.discovered, handler);
+            }
+        }
     }
 
-    /// Does the function named by this id have the `paralegal_flow::analyze` annotation
-    fn should_analyze_function(&self, ident: LocalDefId) -> bool {
+    /// Does the function named by this id have the `paralegal_flow::analyze`
+    /// annotation or is it in an analyzed module? If so, returns the
+    /// instantiation selected by the annotation.
+    fn should_analyze_function(&mut self, ident: LocalDefId) -> Option<Instantiation<'tcx>> {
+        let tcx = self.tcx;
+        let from_attribute = tcx
+            .hir()
+            .attrs(tcx.local_def_id_to_hir_id(ident))
+            .iter()
+            .find_map(|a| {
+                a.match_extract(&self.analyze_marker, |args| {
+                    analyze_ann_match(args, tcx, ident)
+                })
+            });
+        match from_attribute {
+            Some(Ok(annotation)) => {
+                let instantiation = annotation.instantiation.clone();
+                self.analyze_annotations.insert(ident, annotation);
+                Some(instantiation)
+            }
+            Some(Err(err)) => {
+                tcx.sess.span_err(tcx.def_span(ident), err);
+                None
+            }
+            None => self.in_analyzed_module(ident).then(Instantiation::default),
+        }
+    }
+
+    fn has_analyze_marker(&self, ident: LocalDefId) -> bool {
         self.tcx
             .hir()
             .attrs(self.tcx.local_def_id_to_hir_id(ident))
             .iter()
             .any(|a| a.matches_path(&self.analyze_marker))
     }
+
+    /// Is this a public, non-generic free function or inherent method in a
+    /// module marked with `#[paralegal_flow::analyze]`? Trait method
+    /// implementations are not selected this way.
+    fn in_analyzed_module(&self, ident: LocalDefId) -> bool {
+        let tcx = self.tcx;
+        let is_candidate = tcx.visibility(ident).is_public()
+            && tcx
+                .impl_of_method(ident.to_def_id())
+                .map_or(true, |imp| tcx.trait_id_of_impl(imp).is_none())
+            && !tcx.generics_of(ident).requires_monomorphization(tcx);
+        if !is_candidate {
+            return false;
+        }
+        let mut parent = tcx.opt_local_parent(ident);
+        while let Some(container) = parent {
+            if matches!(tcx.def_kind(container), DefKind::Mod) && self.has_analyze_marker(container)
+            {
+                return true;
+            }
+            parent = tcx.opt_local_parent(container);
+        }
+        false
+    }
 }
 
 impl<'tcx> intravisit::Visitor<'tcx> for CollectingVisitor<'tcx> {
@@ -148,13 +476,12 @@ impl<'tcx> intravisit::Visitor<'tcx> for CollectingVisitor<'tcx> {
         id: LocalDefId,
     ) {
         match &kind {
-            FnKind::ItemFn(name, _, _) | FnKind::Method(name, _)
-                if self.should_analyze_function(id) =>
-            {
-                self.functions_to_analyze.push(FnToAnalyze {
-                    name: *name,
-                    def_id: id,
-                });
+            FnKind::ItemFn(name, _, _) | FnKind::Method(name, _) => {
+                self.discover_by_attribute(id);
+                if let Some(instantiation) = self.should_analyze_function(id) {
+                    self.functions_to_analyze
+                        .push(FnToAnalyze::new(*name, id, instantiation));
+                }
             }
             _ => (),
         }
@@ -164,4 +491,79 @@ impl<'tcx> intravisit::Visitor<'tcx> for CollectingVisitor<'tcx> {
         // it.
         intravisit::walk_fn(self, kind, declaration, body_id, id)
     }
+
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+        if !self.discovery_registrations.is_empty() {
+            self.discover_by_registration(expr);
+        }
+        intravisit::walk_expr(self, expr)
+    }
+}
+
+/// The expansions of the attribute macros that the function `id` was written
+/// with, innermost first.
+fn attribute_macros(tcx: TyCtxt, id: LocalDefId) -> impl Iterator<Item = ExpnData> {
+    let own_expansion = tcx.def_span(id).ctxt().outer_expn();
+    let mut expansion = tcx.expn_that_defined(id.to_def_id());
+    std::iter::from_fn(move || {
+        while expansion != ExpnId::root() {
+            // Functions the macro creates itself are not the ones annotated
+            if own_expansion.is_descendant_of(expansion) {
+                return None;
+            }
+            let data = expansion.expn_data();
+            expansion = data.parent;
+            if matches!(data.kind, ExpnKind::Macro(MacroKind::Attr, _)) {
+                return Some(data);
+            }
+        }
+        None
+    })
+}
+
+/// Is this the name of one of our crates or tools, `paralegal` or
+/// `paralegal_flow`.
+fn is_paralegal_name(name: Symbol) -> bool {
+    matches!(name.as_str(), "paralegal" | "paralegal_flow")
+}
+
+/// Is this a `#[paralegal::..]` or `#[paralegal_flow::..]` attribute.
+fn is_paralegal_attribute(attr: &rustc_ast::Attribute) -> bool {
+    let rustc_ast::AttrKind::Normal(normal) = &attr.kind else {
+        return false;
+    };
+    normal
+        .item
+        .path
+        .segments
+        .first()
+        .map_or(false, |segment| is_paralegal_name(segment.ident.name))
+}
+
+/// The `@tag` words in a line of documentation
+fn doc_tags(doc: &str) -> impl Iterator<Item = &str> {
+    doc.split_whitespace().filter_map(|word| {
+        let tag = word
+            .strip_prefix('@')?
+            .trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'));
+        (!tag.is_empty()).then_some(tag)
+    })
+}
+
+/// Like [`item_path`] but items of other crates are named by the path they are
+/// exported under, e.g. `tokio::main` instead of `tokio_macros::main`.
+fn visible_item_path(tcx: TyCtxt, def_id: DefId) -> Vec<Symbol> {
+    let visible_parents = tcx.visible_parent_map(());
+    let mut segments = vec![];
+    let mut current = def_id;
+    while current.index != CRATE_DEF_INDEX {
+        let Some(parent) = visible_parents.get(&current) else {
+            return item_path(tcx, def_id);
+        };
+        segments.push(tcx.item_name(current));
+        current = *parent;
+    }
+    segments.push(tcx.crate_name(current.krate));
+    segments.reverse();
+    segments
 }
@@ -26,6 +26,7 @@ extern crate num_traits;
 extern crate rustc_abi;
 extern crate rustc_arena;
 extern crate rustc_ast;
+extern crate rustc_ast_pretty;
 extern crate rustc_borrowck;
 extern crate rustc_data_structures;
 extern crate rustc_driver;
@@ -49,8 +50,9 @@ use ann::dump_markers;
 use args::{ClapArgs, Debugger, LogLevelConfig};
 use desc::{utils::write_sep, ProgramDescription};
 
-use flowistry_pdg_construction::body_cache::dump_mir_and_borrowck_facts;
+use flowistry_pdg_construction::body_cache::{dump_mir_and_borrowck_facts, BODY_STORE_VAR};
 use log::Level;
+use rustc_hir::def_id::LOCAL_CRATE;
 use rustc_middle::ty::TyCtxt;
 use rustc_plugin::CrateFilter;
 
@@ -74,6 +76,7 @@ mod stats;
 pub mod utils;
 #[cfg(feature = "test")]
 pub mod test_utils;
+mod workspace;
 
 pub use paralegal_spdg as desc;
 
@@ -151,7 +154,10 @@ impl rustc_driver::Callbacks for DumpOnlyCallbacks {
         queries: &'tcx rustc_interface::Queries<'tcx>,
     ) -> rustc_driver::Compilation {
         queries.global_ctxt().unwrap().enter(|tcx| {
-            dump_mir_and_borrowck_facts(tcx);
+            if let Err(e) = dump_mir_and_borrowck_facts(tcx) {
+                tcx.sess
+                    .fatal(format!("could not write the MIR bodies: {e}"));
+            }
             dump_markers(tcx);
         });
         rustc_driver::Compilation::Continue
@@ -165,7 +171,10 @@ impl rustc_driver::Callbacks for Callbacks {
         queries: &'tcx rustc_interface::Queries<'tcx>,
     ) -> rustc_driver::Compilation {
         queries.global_ctxt().unwrap().enter(|tcx| {
-            dump_mir_and_borrowck_facts(tcx);
+            if let Err(e) = dump_mir_and_borrowck_facts(tcx) {
+                tcx.sess
+                    .fatal(format!("could not write the MIR bodies: {e}"));
+            }
             dump_markers(tcx);
         });
         rustc_driver::Compilation::Continue
@@ -188,6 +197,21 @@ impl rustc_driver::Callbacks for Callbacks {
             .global_ctxt()
             .unwrap()
             .enter(|tcx| {
+                if self.opts.marker_control().check_annotations() {
+                    let mut report = ann::check::check_external_annotations(tcx, self.opts);
+                    if report.has_problems() {
+                        print!("{report}");
+                        tcx.sess.err("External annotations failed validation");
+                    } else {
+                        // Marker usage is only meaningful for a graph that was
+                        // built with exactly these annotations.
+                        let desc = self.run(tcx)?;
+                        tcx.sess.abort_if_errors();
+                        report.find_unused_markers(&desc);
+                        print!("{report}");
+                    }
+                    return anyhow::Ok(rustc_driver::Compilation::Stop);
+                }
                 let desc = self.run(tcx)?;
                 info!("All elems walked");
                 tcx.sess.abort_if_errors();
@@ -198,12 +222,21 @@ impl rustc_driver::Callbacks for Callbacks {
                 }
 
                 let ser = Instant::now();
-                desc.canonical_write(self.opts.result_path()).unwrap();
+                let crate_id = format!(
+                    "{}-{:x}",
+                    tcx.crate_name(LOCAL_CRATE),
+                    tcx.stable_crate_id(LOCAL_CRATE).as_u64()
+                );
+                workspace::write_result(&desc, self.opts.result_path(), &crate_id)?;
                 self.stats
                     .record_timed(TimedStat::Serialization, ser.elapsed());
 
                 println!("Analysis finished with timing: {}", self.stats);
 
+                if let Some(path) = self.opts.stats_json() {
+                    self.stats.write_json(&desc, path)?;
+                }
+
                 anyhow::Ok(if self.opts.abort_after_analysis() {
                     debug!("Aborting");
                     rustc_driver::Compilation::Stop
@@ -267,6 +300,7 @@ fn how_to_handle_this_crate(plugin_args: &Args, compiler_args: &mut Vec<String>)
 
     match &crate_name {
         Some(krate) if krate == "build_script_build" => CrateHandling::JustCompile,
+        _ if plugin_args.populate_body_store() => CrateHandling::CompileAndDump,
         Some(krate)
             if matches!(
                 plugin_args
@@ -277,7 +311,13 @@ fn how_to_handle_this_crate(plugin_args: &Args, compiler_args: &mut Vec<String>)
             CrateHandling::Analyze
         }
         _ if std::env::var("CARGO_PRIMARY_PACKAGE").is_ok() => CrateHandling::Analyze,
-        Some(krate) if plugin_args.anactrl().included().contains(krate) => {
+        // With a body store every dependency is dumped (or reused from the
+        // store) so that they are built the same way as when the store was
+        // populated and their crate hashes match.
+        Some(krate)
+            if plugin_args.anactrl().included().contains(krate)
+                || plugin_args.body_store().is_some() =>
+        {
             CrateHandling::CompileAndDump
         }
         _ => CrateHandling::JustCompile,
@@ -320,9 +360,17 @@ impl rustc_plugin::RustcPlugin for DfppPlugin {
         std::env::set_var("SYSROOT", env!("SYSROOT_PATH"));
 
         add_to_rustflags(["--cfg".into(), "paralegal".into()]).unwrap();
+        workspace::start_session();
+
+        let args: Args = args.args.try_into().unwrap();
+        // The compiler invocations find the store through the environment
+        match args.body_store() {
+            Some(store) => std::env::set_var(BODY_STORE_VAR, store),
+            None => std::env::remove_var(BODY_STORE_VAR),
+        }
 
         rustc_plugin::RustcPluginArgs {
-            args: args.args.try_into().unwrap(),
+            args,
             filter: CrateFilter::AllCrates,
         }
     }
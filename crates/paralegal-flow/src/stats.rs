@@ -1,10 +1,11 @@
 use std::{
     fmt::Display,
+    path::Path,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use paralegal_spdg::utils::TruncatedHumanTime;
+use paralegal_spdg::{utils::TruncatedHumanTime, ProgramDescription};
 use trait_enum::DerefMut;
 
 /// Statsistics that are counted as durations
@@ -48,6 +49,52 @@ impl Stats {
     pub fn get_timed(&self, stat: TimedStat) -> Duration {
         self.0.lock().unwrap().timed[stat].unwrap_or(Duration::ZERO)
     }
+
+    /// Write the timing information together with the program-wide and
+    /// per-controller statistics from `desc` as JSON. Durations are in
+    /// seconds.
+    pub fn write_json(&self, desc: &ProgramDescription, path: &Path) -> anyhow::Result<()> {
+        let timing = self
+            .0
+            .lock()
+            .unwrap()
+            .timed
+            .iter()
+            .filter_map(|(stat, dur)| Some((stat.as_ref().to_owned(), dur?.as_secs_f64())))
+            .collect::<serde_json::Map<_, _>>();
+        let controllers = desc
+            .controllers
+            .values()
+            .map(|ctrl| {
+                let stats = &ctrl.statistics;
+                let name = ctrl.path.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+                (
+                    name.join("::"),
+                    serde_json::json!({
+                        "unique_locs": stats.unique_locs,
+                        "unique_functions": stats.unique_functions,
+                        "analyzed_locs": stats.analyzed_locs,
+                        "analyzed_functions": stats.analyzed_functions,
+                        "inlinings_performed": stats.inlinings_performed,
+                        "approximations": stats.approximations,
+                        "construction_time": stats.construction_time.as_secs_f64(),
+                        "conversion_time": stats.conversion_time.as_secs_f64(),
+                    }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let json = serde_json::json!({
+            "timing": timing,
+            "marker_annotation_count": desc.marker_annotation_count,
+            "dedup_functions": desc.dedup_functions,
+            "dedup_locs": desc.dedup_locs,
+            "seen_functions": desc.seen_functions,
+            "seen_locs": desc.seen_locs,
+            "controllers": controllers,
+        });
+        serde_json::to_writer_pretty(std::fs::File::create(path)?, &json)?;
+        Ok(())
+    }
 }
 
 impl Default for Stats {
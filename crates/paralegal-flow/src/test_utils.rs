@@ -117,6 +117,30 @@ pub fn run_paralegal_flow_with_flow_graph_dump(dir: impl AsRef<Path>) -> bool {
 ///
 /// The result is suitable for reading with [`PreFrg::from_file_at`]
 pub fn run_paralegal_flow_with_flow_graph_dump_and<I, S>(dir: impl AsRef<Path>, extra: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    flow_graph_dump_command(dir, extra)
+        .status()
+        .unwrap()
+        .success()
+}
+
+/// Like [`run_paralegal_flow_with_flow_graph_dump`], but also returns the
+/// diagnostics the compiler emitted. They are echoed to stderr as well.
+pub fn run_paralegal_flow_with_flow_graph_dump_capturing(dir: impl AsRef<Path>) -> (bool, String) {
+    let output = flow_graph_dump_command::<_, &str>(dir, [])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    eprint!("{stderr}");
+    (output.status.success(), stderr)
+}
+
+/// Clean `dir` and prepare a `paralegal-flow` invocation that dumps the flow
+/// graph.
+fn flow_graph_dump_command<I, S>(dir: impl AsRef<Path>, extra: I) -> Command
 where
     I: IntoIterator<Item = S>,
     S: AsRef<std::ffi::OsStr>,
@@ -128,12 +152,9 @@ where
         .status()
         .unwrap()
         .success());
-    paralegal_flow_command(dir)
-        .args(["--abort-after-analysis"])
-        .args(extra)
-        .status()
-        .unwrap()
-        .success()
+    let mut cmd = paralegal_flow_command(dir);
+    cmd.args(["--abort-after-analysis"]).args(extra);
+    cmd
 }
 
 /// A "meta-macro" that should be used to implement a `define_test!` macro in a
@@ -215,6 +236,22 @@ impl InlineTestBuilder {
         self
     }
 
+    /// Add a `mark_source` function whose return value is marked `source` and
+    /// a `sink` function whose argument is marked `sink` to the input, for use
+    /// with [`source_flows_to_sink`].
+    pub fn with_source_and_sink(&mut self) -> &mut Self {
+        self.input.push_str(stringify!(
+            #[paralegal_flow::marker(source, return)]
+            fn mark_source<T>(t: T) -> T {
+                t
+            }
+
+            #[paralegal_flow::marker(sink, arguments = [0])]
+            fn sink<T>(t: T) {}
+        ));
+        self
+    }
+
     pub fn expect_fail_compile(&self) {
         let reached = Once::new();
         let res = self.run(|_| reached.call_once(|| ()));
@@ -253,7 +290,7 @@ impl InlineTestBuilder {
             .with_args(EXTRA_RUSTC_ARGS.iter().copied().map(ToOwned::to_owned))
             .with_query_override(None)
             .compile(move |result| {
-                dump_mir_and_borrowck_facts(result.tcx);
+                dump_mir_and_borrowck_facts(result.tcx).unwrap();
                 dump_markers(result.tcx);
                 let tcx = result.tcx;
                 let memo = crate::Callbacks::new(Box::leak(Box::new(args)));
@@ -264,6 +301,16 @@ impl InlineTestBuilder {
     }
 }
 
+/// Check that a node marked `source` flows to a node marked `sink`. Can be
+/// passed to [`InlineTestBuilder::check_ctrl`] directly.
+pub fn source_flows_to_sink(ctrl: CtrlRef) {
+    let sources = ctrl.marked(Identifier::new_intern("source"));
+    let sinks = ctrl.marked(Identifier::new_intern("sink"));
+    assert!(!sources.is_empty());
+    assert!(!sinks.is_empty());
+    assert!(sources.flows_to_any(&sinks));
+}
+
 pub trait HasGraph<'g>: Sized + Copy {
     fn graph(self) -> &'g PreFrg;
 
@@ -320,6 +367,8 @@ pub trait HasGraph<'g>: Sized + Copy {
 
     fn ctrl_hashed(self, name: &str) -> Endpoint {
         let name = name.strip_prefix("crate::").unwrap_or(name);
+        // Drop explicit generic arguments, e.g. `handle::<Db>`
+        let name = name.split("::<").next().unwrap();
         let candidates = self
             .graph()
             .desc
@@ -354,7 +403,7 @@ pub trait HasGraph<'g>: Sized + Copy {
                 .desc
                 .controllers
                 .values()
-                .any(|c| c.markers.values().any(|m| m.contains(&marker)))
+                .any(|c| c.markers.values().any(|m| m.contains_key(&marker)))
     }
 }
 
@@ -436,7 +485,7 @@ impl<'g> CtrlRef<'g> {
                 .ctrl
                 .markers
                 .iter()
-                .filter(|(_, markers)| markers.contains(&marker))
+                .filter(|(_, markers)| markers.contains_key(&marker))
                 .map(|(n, _)| *n)
                 .collect(),
             graph: self,
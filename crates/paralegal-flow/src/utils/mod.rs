@@ -212,6 +212,11 @@ pub trait InstanceExt<'tcx> {
     /// Emits warnings if a precise signature could not be obtained or there
     /// were type variables not instantiated.
     fn sig(self, tcx: TyCtxt<'tcx>) -> Result<ty::FnSig<'tcx>, ErrorGuaranteed>;
+
+    /// The item whose markers apply to a call of this instance. Same as
+    /// [`Instance::def_id`] except for closure shims, where the
+    /// `FnOnce::call_once` method is replaced by the closure itself.
+    fn marker_target(self) -> DefId;
 }
 
 impl<'tcx> InstanceExt<'tcx> for Instance<'tcx> {
@@ -243,6 +248,15 @@ impl<'tcx> InstanceExt<'tcx> for Instance<'tcx> {
                 late_bound_sig.skip_binder()
             }))
     }
+
+    fn marker_target(self) -> DefId {
+        match (self.def, self.args.types().next().map(|t| t.kind())) {
+            (ty::InstanceDef::ClosureOnceShim { .. }, Some(ty::TyKind::Closure(closure, _))) => {
+                *closure
+            }
+            _ => self.def_id(),
+        }
+    }
 }
 
 /// This exists to distinguish different types of functions, which is necessary
@@ -415,6 +429,29 @@ impl<'tcx, F: FnMut(&mir::Place<'tcx>)> mir::visit::Visitor<'tcx> for PlaceVisit
     }
 }
 
+/// Like [`PlaceVisitor`] but applies the `FnMut` to every [`Constant`]
+/// operand.
+pub struct ConstantVisitor<F>(pub F);
+
+impl<'tcx, F: FnMut(&Constant<'tcx>)> mir::visit::Visitor<'tcx> for ConstantVisitor<F> {
+    fn visit_constant(&mut self, constant: &Constant<'tcx>, _location: mir::Location) {
+        self.0(constant)
+    }
+}
+
+/// If this constant is a reference to a `static` or a use of a (not yet
+/// evaluated) `const` item, return the id of that item.
+pub fn referenced_static_or_const(tcx: TyCtxt, c: &Constant) -> Option<DefId> {
+    match c.literal {
+        mir::ConstantKind::Unevaluated(uv, _) => uv.promoted.is_none().then_some(uv.def),
+        mir::ConstantKind::Ty(cst) => match cst.kind() {
+            ty::ConstKind::Unevaluated(uv) => Some(uv.def),
+            _ => None,
+        },
+        mir::ConstantKind::Val(..) => c.check_static_ptr(tcx),
+    }
+}
+
 pub enum Overlap<'tcx> {
     Equal,
     Independent,
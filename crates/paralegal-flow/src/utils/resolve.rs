@@ -1,6 +1,7 @@
 use std::hash::Hash;
 
 use ast::Mutability;
+use flowistry_pdg_construction::utils::Instantiation;
 use hir::{
     def::{self, DefKind},
     def_id::CrateNum,
@@ -8,12 +9,16 @@ use hir::{
     def_id::LOCAL_CRATE,
     ImplItemRef, ItemKind, Node, PrimTy, TraitItemRef,
 };
-use rustc_ast::{self as ast, token::TokenKind, ExprKind, PathSegment, QSelf, Ty, TyKind};
+use rustc_ast::{
+    self as ast, ptr::P, token::TokenKind, AngleBracketedArg, ExprKind, PathSegment, QSelf, Ty,
+    TyKind,
+};
+use rustc_ast_pretty::pprust;
 use rustc_data_structures::stable_hasher::StableHasher;
 use rustc_hir::{self as hir, def_id::DefId};
 use rustc_middle::ty::{self, fast_reject::SimplifiedType, FloatTy, IntTy, TyCtxt, UintTy};
 use rustc_parse::new_parser_from_source_str;
-use rustc_span::Symbol;
+use rustc_span::{symbol::Ident, Symbol};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Res {
@@ -102,15 +107,298 @@ fn find_primitive_impls(tcx: TyCtxt<'_>, name: Symbol) -> impl Iterator<Item = D
 /// user and `None` is returned so the caller has the option of making progress
 /// before exiting.
 pub fn expect_resolve_string_to_def_id(tcx: TyCtxt, path: &str, relaxed: bool) -> Option<DefId> {
-    let report_err = if relaxed {
-        |tcx: TyCtxt<'_>, err: String| {
-            tcx.sess.warn(err);
+    resolve_string_to_def_id(tcx, path)
+        .map_err(|err| {
+            if relaxed {
+                tcx.sess.warn(err);
+            } else {
+                tcx.sess.err(err);
+            }
+        })
+        .ok()
+}
+
+/// Like [`expect_resolve_string_to_def_id`] but hands the error message to the
+/// caller instead of reporting it to the compiler session.
+pub fn resolve_string_to_def_id(tcx: TyCtxt, path: &str) -> std::result::Result<DefId, String> {
+    let (slf, segments) = parse_path(tcx, path)?;
+    let res = def_path_res(tcx, slf.as_deref(), &segments)
+        .map_err(|e| format!("Could not resolve {path}: {e:?}"))?;
+    match res {
+        Res::Def(_, did) => Ok(did),
+        other => Err(format!(
+            "expected {path} to resolve to an item, got {other:?}"
+        )),
+    }
+}
+
+/// Resolve a path to a function that may end in explicit generic arguments,
+/// e.g. `crate::handle::<crate::db::Postgres>`.
+///
+/// Positional arguments instantiate the type parameters of the function
+/// itself in order. Arguments of the form `Name = Type` can also instantiate
+/// parameters of a surrounding `impl` or trait, e.g.
+/// `crate::Api::handle::<D = crate::db::Postgres>`. Types are resolved with
+/// the same rules as paths, see [`def_path_res`].
+pub fn resolve_string_to_instantiated_fn<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    path: &str,
+) -> std::result::Result<(DefId, Instantiation<'tcx>), String> {
+    let (slf, mut segments) = parse_path(tcx, path)?;
+    let args = segments.last_mut().and_then(|segment| segment.args.take());
+    let res = def_path_res(tcx, slf.as_deref(), &segments)
+        .map_err(|e| format!("Could not resolve {path}: {e:?}"))?;
+    let Res::Def(_, function) = res else {
+        return Err(format!(
+            "expected {path} to resolve to an item, got {res:?}"
+        ));
+    };
+    let instantiation = match args {
+        Some(args) => resolve_instantiation(
+            tcx,
+            function,
+            &instantiation_args(tcx, function, &args)?,
+            None,
+        )?,
+        None => Instantiation::default(),
+    };
+    Ok((function, instantiation))
+}
+
+/// Resolve the instantiation given in `#[paralegal::analyze(T = Type, ..)]`
+/// on `function`, as pairs of an optional parameter name and a type. Unnamed
+/// types instantiate the type parameters of `function` in order, as in
+/// [`resolve_string_to_instantiated_fn`]. Types named by a single identifier
+/// are additionally looked up in the module of `function`.
+pub fn resolve_attribute_instantiation<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    function: LocalDefId,
+    args: &[(Option<Ident>, P<Ty>)],
+) -> std::result::Result<Instantiation<'tcx>, String> {
+    resolve_instantiation(
+        tcx,
+        function.to_def_id(),
+        &args
+            .iter()
+            .map(|(name, t)| (*name, &**t))
+            .collect::<Vec<_>>(),
+        Some(tcx.parent_module_from_def_id(function).to_local_def_id()),
+    )
+}
+
+/// The explicit generic arguments of a path as parameter names (if given)
+/// and types. Lifetimes are skipped.
+fn instantiation_args<'a>(
+    tcx: TyCtxt,
+    function: DefId,
+    args: &'a ast::GenericArgs,
+) -> std::result::Result<Vec<(Option<Ident>, &'a Ty)>, String> {
+    let ast::GenericArgs::AngleBracketed(args) = args else {
+        return Err(format!(
+            "Expected generic arguments in angle brackets for {}",
+            tcx.def_path_str(function)
+        ));
+    };
+    args.args
+        .iter()
+        .filter_map(|arg| match arg {
+            AngleBracketedArg::Arg(ast::GenericArg::Type(t)) => Some(Ok((None, &**t))),
+            AngleBracketedArg::Constraint(ast::AssocConstraint {
+                ident,
+                gen_args: None,
+                kind:
+                    ast::AssocConstraintKind::Equality {
+                        term: ast::Term::Ty(t),
+                    },
+                ..
+            }) => Some(Ok((Some(*ident), &**t))),
+            AngleBracketedArg::Arg(ast::GenericArg::Lifetime(_)) => None,
+            _ => Some(Err(format!(
+                "Unsupported generic argument for {}, expected a type or `Name = Type`",
+                tcx.def_path_str(function)
+            ))),
+        })
+        .collect()
+}
+
+fn resolve_instantiation<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    function: DefId,
+    args: &[(Option<Ident>, &Ty)],
+    scope: Option<LocalDefId>,
+) -> std::result::Result<Instantiation<'tcx>, String> {
+    let generics = tcx.generics_of(function);
+    let mut positional = generics
+        .params
+        .iter()
+        .filter(|param| matches!(param.kind, ty::GenericParamDefKind::Type { .. }));
+    let mut instantiation = Instantiation::default();
+    for (name, t) in args {
+        let param = match name {
+            None => positional.next().ok_or_else(|| {
+                format!(
+                    "Too many generic arguments for {}",
+                    tcx.def_path_str(function)
+                )
+            })?,
+            Some(ident) => type_param_named(tcx, generics, ident.name).ok_or_else(|| {
+                format!(
+                    "{} has no type parameter named {ident}",
+                    tcx.def_path_str(function)
+                )
+            })?,
+        };
+        let ty = resolve_concrete_ty(tcx, t, scope)?;
+        if instantiation.insert(param.def_id, ty).is_some() {
+            return Err(format!(
+                "Type parameter {} is instantiated more than once",
+                param.name
+            ));
         }
-    } else {
-        |tcx: TyCtxt<'_>, err| {
-            tcx.sess.err(err);
+    }
+    Ok(instantiation)
+}
+
+/// Find a type parameter in `generics` or the generics of its parents.
+fn type_param_named<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    mut generics: &'tcx ty::Generics,
+    name: Symbol,
+) -> Option<&'tcx ty::GenericParamDef> {
+    loop {
+        if let Some(param) = generics.params.iter().find(|param| {
+            param.name == name && matches!(param.kind, ty::GenericParamDefKind::Type { .. })
+        }) {
+            return Some(param);
         }
-    };
+        generics = tcx.generics_of(generics.parent?);
+    }
+}
+
+/// Resolve a fully concrete type. Unlike [`resolve_ty`] this supports generic
+/// arguments, primitive types, references and tuples. Lifetimes are erased.
+///
+/// If `scope` is given, types named by a single identifier that is not a
+/// primitive type are looked up in that module.
+fn resolve_concrete_ty<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    t: &Ty,
+    scope: Option<LocalDefId>,
+) -> std::result::Result<ty::Ty<'tcx>, String> {
+    let unsupported = || format!("Unsupported type {}", pprust::ty_to_string(t));
+    match &t.kind {
+        TyKind::Ref(_, ast::MutTy { ty, mutbl }) => Ok(ty::Ty::new_ref(
+            tcx,
+            tcx.lifetimes.re_erased,
+            ty::TypeAndMut {
+                ty: resolve_concrete_ty(tcx, ty, scope)?,
+                mutbl: *mutbl,
+            },
+        )),
+        TyKind::Tup(elems) => Ok(ty::Ty::new_tup(
+            tcx,
+            &elems
+                .iter()
+                .map(|elem| resolve_concrete_ty(tcx, elem, scope))
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+        )),
+        TyKind::Path(None, path) => {
+            let mut segments = path.segments.clone();
+            let args = match segments.last_mut().and_then(|segment| segment.args.take()) {
+                None => vec![],
+                Some(args) => {
+                    let ast::GenericArgs::AngleBracketed(args) = &*args else {
+                        return Err(unsupported());
+                    };
+                    args.args
+                        .iter()
+                        .filter_map(|arg| match arg {
+                            AngleBracketedArg::Arg(ast::GenericArg::Type(t)) => {
+                                Some(resolve_concrete_ty(tcx, t, scope))
+                            }
+                            AngleBracketedArg::Arg(ast::GenericArg::Lifetime(_)) => None,
+                            _ => Some(Err(unsupported())),
+                        })
+                        .collect::<std::result::Result<Vec<_>, _>>()?
+                }
+            };
+            let res =
+                def_path_res(tcx, None, &segments).or_else(|e| match (scope, &segments[..]) {
+                    (Some(module), [single]) => {
+                        local_item_children_by_name(tcx, module, single.ident.name)
+                            .unwrap_or(Err(e))
+                    }
+                    _ => Err(e),
+                });
+            let res = res.map_err(|e| {
+                format!("Could not resolve type {}: {e:?}", pprust::ty_to_string(t))
+            })?;
+            match res {
+                Res::PrimTy(prim) if args.is_empty() => Ok(primitive_ty(tcx, prim)),
+                Res::Def(DefKind::Struct | DefKind::Enum | DefKind::Union, adt) => {
+                    let generics = tcx.generics_of(adt);
+                    let mut args = args.into_iter();
+                    let mut instantiated: Vec<ty::GenericArg<'tcx>> = vec![];
+                    for idx in 0..generics.count() {
+                        let param = generics.param_at(idx, tcx);
+                        let arg = match param.kind {
+                            ty::GenericParamDefKind::Lifetime => tcx.lifetimes.re_erased.into(),
+                            ty::GenericParamDefKind::Type { .. } => match args.next() {
+                                Some(arg) => arg.into(),
+                                None => param
+                                    .default_value(tcx)
+                                    .ok_or_else(|| {
+                                        format!(
+                                            "Missing generic arguments in {}",
+                                            pprust::ty_to_string(t)
+                                        )
+                                    })?
+                                    .instantiate(tcx, &instantiated),
+                            },
+                            ty::GenericParamDefKind::Const { .. } => return Err(unsupported()),
+                        };
+                        instantiated.push(arg);
+                    }
+                    if args.next().is_some() {
+                        return Err(format!(
+                            "Too many generic arguments in {}",
+                            pprust::ty_to_string(t)
+                        ));
+                    }
+                    Ok(ty::Ty::new_adt(
+                        tcx,
+                        tcx.adt_def(adt),
+                        tcx.mk_args(&instantiated),
+                    ))
+                }
+                Res::Def(DefKind::TyAlias { .. }, alias)
+                    if args.is_empty() && tcx.generics_of(alias).count() == 0 =>
+                {
+                    Ok(tcx.type_of(alias).instantiate_identity())
+                }
+                _ => Err(format!("{} is not a type", pprust::ty_to_string(t))),
+            }
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+fn primitive_ty(tcx: TyCtxt, prim: PrimTy) -> ty::Ty {
+    match prim {
+        PrimTy::Int(i) => ty::Ty::new_int(tcx, ty::int_ty(i)),
+        PrimTy::Uint(u) => ty::Ty::new_uint(tcx, ty::uint_ty(u)),
+        PrimTy::Float(f) => ty::Ty::new_float(tcx, ty::float_ty(f)),
+        PrimTy::Str => tcx.types.str_,
+        PrimTy::Bool => tcx.types.bool,
+        PrimTy::Char => tcx.types.char,
+    }
+}
+
+/// Parse `path` as a (possibly qualified) path expression.
+fn parse_path(
+    tcx: TyCtxt,
+    path: &str,
+) -> std::result::Result<(Option<P<QSelf>>, Vec<PathSegment>), String> {
     let mut hasher = StableHasher::new();
     path.hash(&mut hasher);
     let mut parser = new_parser_from_source_str(
@@ -118,28 +406,27 @@ pub fn expect_resolve_string_to_def_id(tcx: TyCtxt, path: &str, relaxed: bool) -
         rustc_span::FileName::Anon(hasher.finish()),
         path.to_string(),
     );
-    let qpath = parser.parse_expr().map_err(|mut e| e.emit()).ok()?;
+    let qpath = parser.parse_expr().map_err(|e| {
+        let message = e
+            .styled_message()
+            .iter()
+            .map(|(message, _)| {
+                tcx.sess
+                    .diagnostic()
+                    .eagerly_translate_to_string(message.clone(), e.args())
+            })
+            .collect::<String>();
+        e.cancel();
+        format!("Could not parse path {path}: {message}")
+    })?;
     if parser.token.kind != TokenKind::Eof {
-        report_err(tcx, format!("Tokens left over after parsing path {path}"));
-        return None;
+        return Err(format!("Tokens left over after parsing path {path}"));
     }
 
-    let ExprKind::Path(slf, rest) = &qpath.kind else {
-        report_err(tcx, format!("Expected path expression, got {path}"));
-        return None;
+    let ExprKind::Path(slf, rest) = qpath.into_inner().kind else {
+        return Err(format!("Expected path expression, got {path}"));
     };
-
-    let res = def_path_res(tcx, slf.as_deref(), &rest.segments)
-        .map_err(|e| report_err(tcx, format!("Could not resolve {path}: {e:?}")))
-        .ok()?;
-    match res {
-        Res::Def(_, did) => Some(did),
-        other => {
-            let msg = format!("expected {path} to resolve to an item, got {other:?}");
-            report_err(tcx, msg);
-            None
-        }
-    }
+    Ok((slf, rest.segments.into_iter().collect()))
 }
 
 fn item_child_by_name(tcx: TyCtxt<'_>, def_id: DefId, name: Symbol) -> Option<Result<Res>> {
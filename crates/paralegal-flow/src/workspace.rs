@@ -0,0 +1,122 @@
+//! Combining the graphs of all analyzed crates in a workspace.
+//!
+//! Every crate that contains controllers is analyzed by a separate compiler
+//! invocation. Each of them writes its [`ProgramDescription`] as a partial
+//! graph into a directory next to the result path and then merges all partial
+//! graphs of the current `cargo paralegal-flow` run (see
+//! [`ProgramDescription::merge`]) into the result path. A lock file makes sure
+//! concurrently running invocations do not overwrite each others results.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+use crate::desc::ProgramDescription;
+
+/// Environment variable that identifies one `cargo paralegal-flow` run. Partial
+/// graphs of other runs are stale and are ignored.
+const SESSION_VAR: &str = "PARALEGAL_ANALYSIS_SESSION";
+
+/// A lock file older than this was left behind by a crashed invocation.
+const STALE_LOCK: Duration = Duration::from_secs(120);
+
+/// Start a new run. Must be called in the `cargo paralegal-flow` process
+/// before cargo is invoked so that the compiler invocations inherit it.
+pub fn start_session() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    std::env::set_var(SESSION_VAR, format!("{now}-{}", std::process::id()));
+}
+
+/// Write the graph of the current crate (identified by `crate_id`) as a
+/// partial graph and update `result_path` with the merge of all partial
+/// graphs of this run.
+///
+/// If the compiler was not started by `cargo paralegal-flow` this writes
+/// `desc` to `result_path` directly.
+pub fn write_result(desc: &ProgramDescription, result_path: &Path, crate_id: &str) -> Result<()> {
+    let Ok(session) = std::env::var(SESSION_VAR) else {
+        return desc.canonical_write(result_path);
+    };
+    let dir = partial_graphs_dir(result_path);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Creating partial graph directory {}", dir.display()))?;
+    let prefix = format!("{session}.");
+    let tmp = dir.join(format!("{prefix}{crate_id}.tmp"));
+    desc.canonical_write(&tmp)?;
+    fs::rename(&tmp, dir.join(format!("{prefix}{crate_id}")))?;
+
+    let _lock = Lock::acquire(dir.join("lock"))?;
+    let mut partials = vec![];
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !name.starts_with(&prefix) {
+            if name != "lock" {
+                debug!("Removing stale partial graph {}", path.display());
+                fs::remove_file(&path)?;
+            }
+        } else if !name.ends_with(".tmp") {
+            partials.push(path);
+        }
+    }
+    partials.sort();
+    let merged = ProgramDescription::merge(
+        partials
+            .iter()
+            .map(ProgramDescription::canonical_read)
+            .collect::<Result<Vec<_>>>()?,
+    )?;
+    merged.canonical_write(result_path)
+}
+
+/// Where the partial graphs for `result_path` are stored.
+fn partial_graphs_dir(result_path: &Path) -> PathBuf {
+    let mut name = result_path.file_name().unwrap_or_default().to_owned();
+    name.push(".partial");
+    result_path.with_file_name(name)
+}
+
+/// An exclusive lock, held as long as this value lives.
+struct Lock(PathBuf);
+
+impl Lock {
+    fn acquire(path: PathBuf) -> Result<Self> {
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Lock(path)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let is_stale = fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .map_or(false, |age| age > STALE_LOCK);
+                    if is_stale {
+                        warn!("Removing stale lock {}", path.display());
+                        let _ = fs::remove_file(&path);
+                    } else {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Acquiring lock {}", path.display()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,5 @@
+#[paralegal::analyze]
+fn main() {
+    let v = std::env::args().collect::<Vec<_>>();
+    println!("{}", v.len());
+}
@@ -0,0 +1,45 @@
+//! Tests for `--check-annotations`, which validates an external annotations
+//! file instead of writing the analysis result.
+
+use paralegal_flow::test_utils::paralegal_flow_command;
+
+const TEST_CRATE_NAME: &str = "tests/annotation-check";
+
+fn check_output(annotations: &str) -> (bool, String) {
+    let output = paralegal_flow_command(TEST_CRATE_NAME)
+        .args(["--check-annotations", "--external-annotations", annotations])
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn reports_problems() {
+    let (success, report) = check_output("external-annotations.toml");
+    println!("{report}");
+    assert!(!success, "check should fail on a malformed annotation file");
+    assert!(report.contains("Checked 5 external annotation entries, 4 with problems"));
+    assert!(report.contains("[ok] std::env::args"));
+    assert!(report.contains("[error] std::does::not::exist (line 6)"));
+    assert!(report.contains("marker 'sink' targets argument 3 but the function has 1 argument(s)"));
+    assert!(report.contains("marker 'sensitive' is refined to arguments or return"));
+    assert!(report.contains("Could not parse path std::vec::Vec::+: expected identifier"));
+    assert!(report.contains("Marker usage not checked"));
+}
+
+#[test]
+fn reports_unused_markers() {
+    let (success, report) = check_output("valid-annotations.toml");
+    println!("{report}");
+    assert!(success);
+    assert!(report.contains("Checked 2 external annotation entries, 0 with problems"));
+    let unused = report
+        .split_once("Markers that appear on no node or type in the analyzed graph:")
+        .expect("unused markers are reported")
+        .1;
+    assert!(unused.contains("unused"));
+    assert!(!unused.contains("source"));
+}
@@ -1,3 +1,7 @@
+#![feature(async_fn_in_trait)]
+
+use std::{future::Future, pin::Pin};
+
 // extern crate async_std;
 // use async_std::prelude::*;
 
@@ -220,4 +224,79 @@ async fn async_return_from_async() -> usize {
     id_fun(some_input()).await
 }
 
+trait Process {
+    async fn process(&self, user_data: UserData) -> UserData;
+}
+
+struct NativeIdentity;
+
+impl Process for NativeIdentity {
+    async fn process(&self, user_data: UserData) -> UserData {
+        user_data
+    }
+}
+
+#[paralegal::analyze]
+async fn native_async_fn_in_trait() {
+    let user_data = NativeIdentity.process(get_user_data()).await;
+    send_user_data(&user_data);
+}
+
+// The shape of the code `#[async_trait]` generates.
+trait BoxedProcess {
+    fn process<'a>(
+        &'a self,
+        user_data: UserData,
+    ) -> Pin<Box<dyn Future<Output = UserData> + Send + 'a>>;
+}
+
+struct BoxedIdentity;
+
+impl BoxedProcess for BoxedIdentity {
+    fn process<'a>(
+        &'a self,
+        user_data: UserData,
+    ) -> Pin<Box<dyn Future<Output = UserData> + Send + 'a>> {
+        Box::pin(async move {
+            let _self = self;
+            user_data
+        })
+    }
+}
+
+#[paralegal::analyze]
+async fn async_trait_method() {
+    let user_data = BoxedIdentity.process(get_user_data()).await;
+    send_user_data(&user_data);
+}
+
+fn wrapper(mut user_data: UserData) -> impl Future<Output = UserData> {
+    user_data.data.push(4);
+    async move { user_data }
+}
+
+#[paralegal::analyze]
+async fn impl_future_wrapper() {
+    let user_data = wrapper(get_user_data()).await;
+    send_user_data(&user_data);
+}
+
+#[paralegal::analyze]
+fn boxed_entrypoint() -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    Box::pin(async move { send_user_data(&get_user_data()) })
+}
+
+#[paralegal::analyze]
+fn wrapper_entrypoint() -> impl Future<Output = ()> {
+    async move { send_user_data(&get_user_data()) }
+}
+
+#[paralegal::analyze]
+fn wrapper_entrypoint_with_prelude(user_data: UserData) -> impl Future<Output = ()> {
+    let copy = UserData {
+        data: user_data.data.clone(),
+    };
+    async move { send_user_data(&copy) }
+}
+
 fn main() {}
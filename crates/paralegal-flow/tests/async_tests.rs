@@ -260,6 +260,48 @@ define_test!(markers: graph -> {
     assert!(input.flows_to_data(&output));
 });
 
+fn approximation_reasons(graph: &CtrlRef<'_>) -> Vec<Identifier> {
+    graph
+        .graph()
+        .desc
+        .approximations
+        .iter()
+        .filter(|a| a.controller == graph.id())
+        .map(|a| a.reason)
+        .collect()
+}
+
+define_test!(native_async_fn_in_trait: graph -> {
+    source_flows_to_sink(graph);
+});
+
+define_test!(async_trait_method: graph -> {
+    source_flows_to_sink(graph);
+});
+
+define_test!(impl_future_wrapper: graph -> {
+    source_flows_to_sink(graph);
+});
+
+define_test!(boxed_entrypoint: graph -> {
+    let prelude = Identifier::new_intern("async wrapper prelude");
+    assert!(!approximation_reasons(&graph).contains(&prelude));
+    source_flows_to_sink(graph);
+});
+
+define_test!(wrapper_entrypoint: graph -> {
+    let prelude = Identifier::new_intern("async wrapper prelude");
+    assert!(!approximation_reasons(&graph).contains(&prelude));
+    source_flows_to_sink(graph);
+});
+
+// The copy is made before the async block is created, only the block is
+// analyzed.
+define_test!(wrapper_entrypoint_with_prelude: graph -> {
+    let prelude = Identifier::new_intern("async wrapper prelude");
+    assert!(approximation_reasons(&graph).contains(&prelude));
+});
+
 #[test]
 fn await_on_generic() {
     InlineTestBuilder::new(stringify!(
@@ -0,0 +1,14 @@
+#[paralegal::marker(sink, arguments = [0])]
+fn sink(_: usize) {}
+
+#[paralegal::analyze]
+fn through_stored_dependency() {
+    sink(stored::forward(stored::source()))
+}
+
+#[paralegal::analyze]
+fn marker_inside_stored_dependency() {
+    sink(stored::sourced())
+}
+
+fn main() {}
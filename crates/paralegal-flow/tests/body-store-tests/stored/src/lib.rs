@@ -0,0 +1,12 @@
+#[paralegal::marker(source, return)]
+pub fn source() -> usize {
+    0
+}
+
+pub fn forward(x: usize) -> usize {
+    x
+}
+
+pub fn sourced() -> usize {
+    forward(source())
+}
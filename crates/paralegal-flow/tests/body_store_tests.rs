@@ -0,0 +1,46 @@
+//! Tests populating the body store in one run and consuming it in the next.
+
+#![feature(rustc_private)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use paralegal_flow::{define_flow_test_template, test_utils::*};
+use paralegal_spdg::Identifier;
+
+const TEST_CRATE_NAME: &str = "tests/body-store-tests";
+
+lazy_static! {
+    static ref TEST_CRATE_ANALYZED: bool = {
+        let store = std::env::temp_dir().join("paralegal-body-store-tests");
+        let _ = std::fs::remove_dir_all(&store);
+        let store = store.to_str().unwrap();
+        // Neither run passes `--include stored`, its markers and bodies are
+        // only known through the store.
+        run_paralegal_flow_with_flow_graph_dump_and(
+            TEST_CRATE_NAME,
+            ["--body-store", store, "--populate-body-store"],
+        ) && run_paralegal_flow_with_flow_graph_dump_and(TEST_CRATE_NAME, ["--body-store", store])
+    };
+}
+
+macro_rules! define_test {
+    ($($t:tt)*) => {
+        define_flow_test_template!(TEST_CRATE_ANALYZED, TEST_CRATE_NAME, $($t)*);
+    };
+}
+
+define_test!(through_stored_dependency: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    assert!(!src.is_empty());
+    assert!(!sink.is_empty());
+    assert!(src.flows_to_data(&sink));
+});
+
+define_test!(marker_inside_stored_dependency: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    assert!(!src.is_empty());
+    assert!(src.flows_to_data(&sink));
+});
@@ -0,0 +1,14 @@
+#[paralegal::marker(source, return)]
+fn source() -> usize {
+    0
+}
+
+#[paralegal::marker(sink, arguments = [0])]
+fn sink(_: usize) {}
+
+#[paralegal::analyze]
+fn through_std() {
+    sink(Some(source()).map(|x| x + 1).unwrap())
+}
+
+fn main() {}
@@ -0,0 +1,70 @@
+//! Tests storing the bodies of the standard library, built with
+//! `-Zbuild-std`, and inlining them when analyzing.
+
+#![feature(rustc_private)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use paralegal_flow::{define_flow_test_template, test_utils::*};
+use paralegal_spdg::Identifier;
+
+const TEST_CRATE_NAME: &str = "tests/build-std-tests";
+
+/// The target triple of the host, `-Zbuild-std` requires an explicit target.
+fn host_target() -> String {
+    let output = std::process::Command::new("rustc")
+        .arg("-vV")
+        .current_dir(TEST_CRATE_NAME)
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .unwrap()
+        .to_owned()
+}
+
+lazy_static! {
+    static ref TEST_CRATE_ANALYZED: bool = {
+        let store = std::env::temp_dir().join("paralegal-build-std-tests");
+        let _ = std::fs::remove_dir_all(&store);
+        let store = store.to_str().unwrap();
+        let target = host_target();
+        let build_std = ["--", "-Zbuild-std", "--target", target.as_str()];
+        run_paralegal_flow_with_flow_graph_dump_and(
+            TEST_CRATE_NAME,
+            ["--body-store", store, "--populate-body-store"]
+                .into_iter()
+                .chain(build_std),
+        ) && run_paralegal_flow_with_flow_graph_dump_and(
+            TEST_CRATE_NAME,
+            ["--body-store", store].into_iter().chain(build_std),
+        )
+    };
+}
+
+macro_rules! define_test {
+    ($($t:tt)*) => {
+        define_flow_test_template!(TEST_CRATE_ANALYZED, TEST_CRATE_NAME, $($t)*);
+    };
+}
+
+define_test!(through_std: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    assert!(src.flows_to_data(&sink));
+
+    // `Option::map` and `Option::unwrap` are inlined from the store
+    let approximations = graph
+        .graph()
+        .desc
+        .approximations
+        .iter()
+        .filter(|a| a.controller == graph.id())
+        .map(|a| a.reason.as_str())
+        .collect::<Vec<_>>();
+    assert!(!approximations.contains(&"missing MIR"));
+    assert!(!approximations.contains(&"inlining for crate disabled"));
+});
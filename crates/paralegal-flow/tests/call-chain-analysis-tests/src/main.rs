@@ -155,3 +155,38 @@ fn acceptor(t: (usize, i32)) {
     target(t.0);
     another_target(t.1);
 }
+
+trait Handler {
+    fn handle(&self, x: i32) -> i32;
+}
+
+struct Logged;
+
+impl Handler for Logged {
+    #[paralegal::marker(logged, arguments = [1])]
+    fn handle(&self, x: i32) -> i32 {
+        x
+    }
+}
+
+struct Plain;
+
+impl Handler for Plain {
+    fn handle(&self, x: i32) -> i32 {
+        x + 1
+    }
+}
+
+fn process<H: Handler>(handler: H, x: i32) -> i32 {
+    handler.handle(x)
+}
+
+#[paralegal::analyze]
+fn process_logged() {
+    receiver(process(Logged, source()));
+}
+
+#[paralegal::analyze]
+fn process_plain() {
+    receiver(process(Plain, source()));
+}
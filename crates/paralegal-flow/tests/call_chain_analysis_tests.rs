@@ -4,6 +4,7 @@
 extern crate lazy_static;
 
 use paralegal_flow::{define_flow_test_template, test_utils::*};
+use paralegal_spdg::Identifier;
 
 const TEST_CRATE_NAME: &str = "tests/call-chain-analysis-tests";
 
@@ -28,6 +29,16 @@ define_test!(without_return: ctrl -> {
     assert!(src.output().flows_to_data(&dest));
 });
 
+define_test!(statistics_are_collected: ctrl, without_return -> {
+    let stats = &ctrl.spdg().statistics;
+    assert!(stats.inlinings_performed > 0);
+    assert!(stats.unique_functions >= 2);
+    assert!(stats.analyzed_functions <= stats.unique_functions);
+    // Only the marked `source` and `receiver` are not inlined
+    assert_eq!(stats.approximations.len(), 1);
+    assert_eq!(stats.approximations[&Identifier::new_intern("marked")], 2);
+});
+
 #[test]
 fn with_return() {
     InlineTestBuilder::new(stringify!(
@@ -221,3 +232,27 @@ define_test!(no_overtaint_over_nested_fn_call
     assert!(!another_input.output().flows_to_data(&target.input()));
 
 });
+
+/// Both controllers call the same generic `process`, but only the
+/// instantiation with `Logged` approximates the (marked) `handle` call.
+fn nested_approximations(ctrl: &CtrlRef<'_>) -> Vec<Identifier> {
+    ctrl.graph()
+        .desc
+        .approximations
+        .iter()
+        .filter(|a| a.controller == ctrl.id() && !a.call_string.is_at_root())
+        .map(|a| a.reason)
+        .collect()
+}
+
+define_test!(approximations_of_logged_instantiation: ctrl, process_logged -> {
+    assert_eq!(nested_approximations(&ctrl), [Identifier::new_intern("marked")]);
+    let stats = &ctrl.spdg().statistics;
+    assert_eq!(stats.approximations[&Identifier::new_intern("marked")], 3);
+});
+
+define_test!(approximations_of_plain_instantiation: ctrl, process_plain -> {
+    assert!(nested_approximations(&ctrl).is_empty());
+    let stats = &ctrl.spdg().statistics;
+    assert_eq!(stats.approximations[&Identifier::new_intern("marked")], 2);
+});
@@ -40,7 +40,7 @@ define_test!(basic : graph -> {
 define_test!(basic_marker: graph -> {
 
     let marker = Identifier::new_intern("mark");
-    assert!(dbg!(&graph.spdg().markers).iter().any(|(_, markers)| markers.contains(&marker)))
+    assert!(dbg!(&graph.spdg().markers).iter().any(|(_, markers)| markers.contains_key(&marker)))
 });
 
 define_test!(assigns_marker: graph -> {
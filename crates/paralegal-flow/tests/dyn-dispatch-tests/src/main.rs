@@ -0,0 +1,103 @@
+#[paralegal::marker(source, return)]
+fn source() -> u32 {
+    0
+}
+
+#[paralegal::marker(sink, arguments = [0])]
+fn sink(_: u32) {}
+
+#[paralegal::marker(archive, arguments = [0])]
+fn archive(_: u32) {}
+
+trait Storage {
+    fn store(&self, value: u32);
+}
+
+struct Database;
+
+impl Storage for Database {
+    fn store(&self, value: u32) {
+        sink(value)
+    }
+}
+
+struct Archive;
+
+impl Storage for Archive {
+    fn store(&self, value: u32) {
+        archive(value)
+    }
+}
+
+/// Never coerced to `dyn Storage`, so never a candidate.
+#[allow(dead_code)]
+struct Unused;
+
+impl Storage for Unused {
+    fn store(&self, value: u32) {
+        sink(value + 1)
+    }
+}
+
+fn pick_storage(use_archive: bool) -> Box<dyn Storage> {
+    if use_archive {
+        Box::new(Archive)
+    } else {
+        Box::new(Database)
+    }
+}
+
+#[paralegal::analyze]
+fn store_through_box(use_archive: bool) {
+    let storage = pick_storage(use_archive);
+    storage.store(source());
+}
+
+#[paralegal::analyze]
+fn store_through_ref() {
+    let storage: &dyn Storage = &Database;
+    storage.store(source());
+}
+
+#[paralegal::marker(logged, arguments = [0])]
+fn logged(_: usize) {}
+
+trait Notifier {
+    fn notify(&self, value: u32, log: &mut Vec<u32>);
+}
+
+struct Email;
+
+impl Notifier for Email {
+    fn notify(&self, value: u32, _log: &mut Vec<u32>) {
+        sink(value)
+    }
+}
+
+struct AuditLog;
+
+impl Notifier for AuditLog {
+    /// Marked, so this candidate is approximated rather than inlined.
+    #[paralegal::marker(audit, arguments = [1])]
+    fn notify(&self, value: u32, log: &mut Vec<u32>) {
+        log.push(value)
+    }
+}
+
+fn pick_notifier(audit: bool) -> Box<dyn Notifier> {
+    if audit {
+        Box::new(AuditLog)
+    } else {
+        Box::new(Email)
+    }
+}
+
+#[paralegal::analyze]
+fn partially_inlined_dispatch(audit: bool) {
+    let notifier = pick_notifier(audit);
+    let mut log = vec![];
+    notifier.notify(source(), &mut log);
+    logged(log.len());
+}
+
+fn main() {}
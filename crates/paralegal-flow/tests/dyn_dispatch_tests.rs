@@ -0,0 +1,82 @@
+//! Tests for resolving calls through trait objects in closed-world mode.
+
+#![feature(rustc_private)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use paralegal_flow::{define_flow_test_template, test_utils::*};
+use paralegal_spdg::Identifier;
+
+const TEST_CRATE_NAME: &str = "tests/dyn-dispatch-tests";
+
+lazy_static! {
+    static ref TEST_CRATE_ANALYZED: bool = run_paralegal_flow_with_flow_graph_dump_and(
+        TEST_CRATE_NAME,
+        ["--closed-world-dyn-dispatch"]
+    );
+}
+
+macro_rules! define_test {
+    ($($t:tt)*) => {
+        define_flow_test_template!(TEST_CRATE_ANALYZED, TEST_CRATE_NAME, $($t)*);
+    };
+}
+
+define_test!(store_through_box: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    let archive = graph.marked(Identifier::new_intern("archive"));
+
+    assert!(!src.is_empty());
+    assert!(!sink.is_empty());
+    assert!(!archive.is_empty());
+
+    assert!(src.flows_to_data(&sink));
+    assert!(src.flows_to_data(&archive));
+});
+
+define_test!(store_through_ref: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+
+    assert!(!src.is_empty());
+    assert!(!sink.is_empty());
+    assert!(src.flows_to_data(&sink));
+});
+
+/// The marked candidate is approximated, which writes the source to the log,
+/// without losing the flows of the inlined one.
+define_test!(partially_inlined_dispatch: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    let logged = graph.marked(Identifier::new_intern("logged"));
+
+    assert!(!src.is_empty());
+    assert!(!sink.is_empty());
+    assert!(!logged.is_empty());
+
+    assert!(src.flows_to_data(&sink));
+    assert!(src.flows_to_data(&logged));
+});
+
+/// Only `Database` and `Archive` are ever coerced to `dyn Storage` and only
+/// `Email` and `AuditLog` to `dyn Notifier`, so every dispatched call has
+/// exactly two candidates and `Unused` is ignored.
+#[test]
+fn candidates_are_recorded() {
+    assert!(*TEST_CRATE_ANALYZED);
+    use_rustc(|| {
+        let graph = PreFrg::from_file_at(TEST_CRATE_NAME);
+        let dispatched = graph
+            .desc
+            .instruction_info
+            .values()
+            .filter(|info| !info.call_candidates.is_empty())
+            .collect::<Vec<_>>();
+        assert!(!dispatched.is_empty());
+        for info in dispatched {
+            assert_eq!(info.call_candidates.len(), 2, "{:?}", info.description);
+        }
+    })
+}
@@ -1,4 +1,5 @@
-use paralegal_flow::test_utils::InlineTestBuilder;
+use paralegal_flow::test_utils::*;
+use paralegal_spdg::Identifier;
 
 #[test]
 fn basic_external_entrypoint_test() {
@@ -65,3 +66,119 @@ fn reject_generic_arguments_test() {
     .with_entrypoint("<crate::MyStruct<usize> as std::clone::Clone>::clone")
     .expect_fail_compile()
 }
+
+const DB_IMPLS: &str = stringify!(
+    trait Db {
+        fn get(&self) -> u32;
+    }
+
+    struct Postgres;
+
+    impl Db for Postgres {
+        fn get(&self) -> u32 {
+            mark_source(1)
+        }
+    }
+
+    struct Sqlite;
+
+    impl Db for Sqlite {
+        fn get(&self) -> u32 {
+            1
+        }
+    }
+);
+
+fn with_db_impls(handler: &str) -> String {
+    format!("{DB_IMPLS}\n{handler}")
+}
+
+#[test]
+fn instantiated_entrypoint_test() {
+    InlineTestBuilder::new(with_db_impls(stringify!(
+        fn handle<T: Db>(db: &T) {
+            sink(db.get())
+        }
+    )))
+    .with_source_and_sink()
+    .with_entrypoint("crate::handle::<crate::Postgres>")
+    .check_ctrl(source_flows_to_sink)
+}
+
+#[test]
+fn instantiated_entrypoint_by_name_test() {
+    InlineTestBuilder::new(with_db_impls(stringify!(
+        fn handle<T: Db>(db: &T) {
+            sink(db.get())
+        }
+    )))
+    .with_source_and_sink()
+    .with_entrypoint("crate::handle::<T = crate::Sqlite>")
+    .check_ctrl(|ctrl| {
+        assert!(ctrl.marked(Identifier::new_intern("source")).is_empty());
+        assert!(!ctrl.marked(Identifier::new_intern("sink")).is_empty());
+    })
+}
+
+/// The command line selection agrees with the annotation, so the function is
+/// analyzed once.
+#[test]
+fn instantiated_entrypoint_attribute_test() {
+    InlineTestBuilder::new(with_db_impls(stringify!(
+        #[paralegal_flow::analyze(T = Postgres)]
+        fn handle<T: Db>(db: &T) {
+            sink(db.get())
+        }
+    )))
+    .with_source_and_sink()
+    .with_entrypoint("crate::handle::<crate::Postgres>")
+    .check_ctrl(source_flows_to_sink)
+}
+
+/// Both instantiations are analyzed and make up the controller. Only the one
+/// with `Postgres` reaches the sink from the source.
+#[test]
+fn multiple_instantiations_test() {
+    InlineTestBuilder::new(with_db_impls(stringify!(
+        #[paralegal_flow::analyze(T = Postgres)]
+        fn handle<T: Db>(db: &T) {
+            sink(db.get())
+        }
+    )))
+    .with_source_and_sink()
+    .with_entrypoint("crate::handle::<crate::Sqlite>")
+    .check_ctrl(|ctrl| {
+        let sources = ctrl.marked(Identifier::new_intern("source"));
+        let sinks = ctrl.marked(Identifier::new_intern("sink"));
+        assert!(sources.flows_to_any(&sinks));
+        assert!(sinks.as_singles().any(|sink| !sources.flows_to_any(&sink)));
+    })
+}
+
+#[test]
+fn instantiated_impl_parameter_test() {
+    InlineTestBuilder::new(with_db_impls(stringify!(
+        struct Api<D>(D);
+
+        impl<D: Db> Api<D> {
+            fn handle(&self) {
+                sink(self.0.get())
+            }
+        }
+    )))
+    .with_source_and_sink()
+    .with_entrypoint("crate::Api::handle::<D = crate::Postgres>")
+    .check_ctrl(source_flows_to_sink)
+}
+
+#[test]
+fn reject_unsatisfied_instantiation_test() {
+    InlineTestBuilder::new(with_db_impls(stringify!(
+        fn handle<T: Db>(db: &T) {
+            sink(db.get())
+        }
+    )))
+    .with_source_and_sink()
+    .with_entrypoint("crate::handle::<u32>")
+    .expect_fail_compile()
+}
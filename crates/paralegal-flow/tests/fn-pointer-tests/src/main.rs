@@ -0,0 +1,49 @@
+#[paralegal::marker(source, return)]
+fn source() -> u32 {
+    0
+}
+
+#[paralegal::marker(sink, arguments = [0])]
+fn sink(_: u32) {}
+
+#[paralegal::marker(archive, arguments = [0])]
+fn archive(_: u32) {}
+
+fn store_db(value: u32) {
+    sink(value)
+}
+
+fn store_archive(value: u32) {
+    archive(value)
+}
+
+struct Route {
+    handler: fn(u32),
+}
+
+#[paralegal::analyze]
+fn route_table(select: usize) {
+    let routes = [
+        Route { handler: store_db },
+        Route {
+            handler: store_archive,
+        },
+    ];
+    (routes[select].handler)(source());
+}
+
+#[paralegal::analyze]
+fn handler_registry() {
+    let mut handlers: Vec<fn(u32)> = Vec::new();
+    handlers.push(store_db);
+    for handler in &handlers {
+        handler(source());
+    }
+}
+
+#[paralegal::analyze]
+fn unknown_handler(handler: fn(u32)) {
+    handler(source());
+}
+
+fn main() {}
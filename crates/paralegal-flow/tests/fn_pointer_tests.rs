@@ -0,0 +1,61 @@
+//! Tests for resolving calls through function pointers.
+
+#![feature(rustc_private)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use paralegal_flow::{define_flow_test_template, test_utils::*};
+use paralegal_spdg::Identifier;
+
+const TEST_CRATE_NAME: &str = "tests/fn-pointer-tests";
+
+lazy_static! {
+    static ref TEST_CRATE_ANALYZED: bool = run_paralegal_flow_with_flow_graph_dump(TEST_CRATE_NAME);
+}
+
+macro_rules! define_test {
+    ($($t:tt)*) => {
+        define_flow_test_template!(TEST_CRATE_ANALYZED, TEST_CRATE_NAME, $($t)*);
+    };
+}
+
+define_test!(route_table: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    let archive = graph.marked(Identifier::new_intern("archive"));
+
+    assert!(!src.is_empty());
+    assert!(!sink.is_empty());
+    assert!(!archive.is_empty());
+
+    assert!(src.flows_to_data(&sink));
+    assert!(src.flows_to_data(&archive));
+});
+
+define_test!(handler_registry: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    let archive = graph.marked(Identifier::new_intern("archive"));
+
+    assert!(!src.is_empty());
+    assert!(!sink.is_empty());
+    assert!(archive.is_empty());
+
+    assert!(src.flows_to_data(&sink));
+});
+
+define_test!(unknown_handler: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    assert!(!src.is_empty());
+
+    let approximations = graph
+        .graph()
+        .desc
+        .approximations
+        .iter()
+        .filter(|a| a.controller == graph.id() && a.callee.is_none())
+        .collect::<Vec<_>>();
+    assert_eq!(approximations.len(), 1);
+    assert_eq!(approximations[0].reason.as_str(), "unknown function pointer");
+});
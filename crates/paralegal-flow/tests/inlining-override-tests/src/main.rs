@@ -0,0 +1,53 @@
+#[paralegal::marker(source, return)]
+fn source() -> u32 {
+    0
+}
+
+#[paralegal::marker(sink, arguments = [0])]
+fn sink(_: u32) {}
+
+mod db {
+    pub struct Connection;
+
+    impl Connection {
+        pub fn store(&self, value: u32) -> u32 {
+            encode(value)
+        }
+    }
+
+    fn encode(value: u32) -> u32 {
+        value + 1
+    }
+}
+
+mod audit {
+    pub fn log(value: u32) {
+        super::sink(value)
+    }
+}
+
+mod checked {
+    #[paralegal::marker(validated, return)]
+    pub fn validate(value: u32) -> u32 {
+        super::sink(value);
+        value
+    }
+}
+
+#[paralegal::analyze]
+fn forced_inline() {
+    let stored = db::Connection.store(source());
+    sink(stored)
+}
+
+#[paralegal::analyze]
+fn forced_abstract() {
+    audit::log(source())
+}
+
+#[paralegal::analyze]
+fn forced_inline_marked() {
+    checked::validate(source());
+}
+
+fn main() {}
@@ -0,0 +1,50 @@
+//! Tests for the per-function inlining overrides in `Paralegal.toml`.
+//!
+//! The crate is analyzed with adaptive inlining, which would approximate
+//! `db::Connection::store` (no markers reachable) and inline `audit::log`
+//! (calls `sink`). The build configuration reverses both decisions. It also
+//! forces inlining of the marked `checked::validate`, which would otherwise
+//! be approximated.
+
+#![feature(rustc_private)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use paralegal_flow::{define_flow_test_template, test_utils::*};
+use paralegal_spdg::Identifier;
+
+const TEST_CRATE_NAME: &str = "tests/inlining-override-tests";
+
+lazy_static! {
+    static ref TEST_CRATE_ANALYZED: bool =
+        run_paralegal_flow_with_flow_graph_dump_and(TEST_CRATE_NAME, ["--adaptive-depth"]);
+}
+
+macro_rules! define_test {
+    ($($t:tt)*) => {
+        define_flow_test_template!(TEST_CRATE_ANALYZED, TEST_CRATE_NAME, $($t)*);
+    };
+}
+
+define_test!(forced_inline: graph -> {
+    let encode = graph.function("encode");
+    assert!(!graph.call_sites(&encode).is_empty());
+
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    assert!(src.flows_to_data(&sink));
+});
+
+define_test!(forced_abstract: graph -> {
+    let log = graph.function("log");
+    assert!(!graph.call_sites(&log).is_empty());
+    assert!(graph.marked(Identifier::new_intern("sink")).is_empty());
+});
+
+define_test!(forced_inline_marked: graph -> {
+    // `sink` is only called in the body of `validate`
+    let src = graph.marked(Identifier::new_intern("source"));
+    let sink = graph.marked(Identifier::new_intern("sink"));
+    assert!(src.flows_to_data(&sink));
+});
@@ -1,3 +1,5 @@
+#![feature(stmt_expr_attributes, proc_macro_hygiene)]
+
 #[derive(Clone)]
 //#[repr(transparent)]
 #[paralegal::marker(wrapper)]
@@ -54,4 +56,136 @@ fn typed_input_zst(w: MarkedZST) {
     consume_any(w)
 }
 
+#[paralegal::marker(secret)]
+static API_KEY: &str = "not-really-a-key";
+
+#[paralegal::analyze]
+fn marked_static_read() {
+    consume_any(API_KEY)
+}
+
+#[paralegal::marker(salt)]
+const SALT: u32 = 7;
+
+#[paralegal::analyze]
+fn marked_const_read() {
+    consume_any(SALT)
+}
+
+#[paralegal::marker(source, return)]
+fn closure_input() -> u32 {
+    0
+}
+
+#[paralegal::analyze]
+fn marked_closure() {
+    let send = #[paralegal::marker(sink, arguments = [0])]
+    |x: u32| consume_any(x);
+    send(closure_input())
+}
+
+#[paralegal::marker(owned_secret, propagate = "owned")]
+struct OwnedSecret;
+
+#[paralegal::analyze]
+fn owned_propagation_box(b: Box<OwnedSecret>) {
+    consume_any(b)
+}
+
+#[paralegal::analyze]
+fn owned_propagation_ref(r: &OwnedSecret) {
+    consume_any(r)
+}
+
+#[paralegal::analyze]
+fn owned_propagation_vec(v: Vec<OwnedSecret>) {
+    consume_any(v)
+}
+
+#[paralegal::analyze]
+fn owned_propagation_arc_mutex(m: std::sync::Arc<std::sync::Mutex<OwnedSecret>>) {
+    consume_any(m)
+}
+
+/// Holds a raw pointer to the secret, but the `PhantomData` marks it as
+/// borrowed.
+#[paralegal::analyze]
+fn owned_propagation_slice_iter(i: std::slice::Iter<OwnedSecret>) {
+    consume_any(i)
+}
+
+#[paralegal::marker(phantom_secret, propagate = "no-phantom")]
+struct PhantomSecret;
+
+struct Handle(std::marker::PhantomData<PhantomSecret>);
+
+#[paralegal::analyze]
+fn phantom_no_propagation(h: Handle) {
+    consume_any(h)
+}
+
+struct MarkedHandle(std::marker::PhantomData<Marked>);
+
+#[paralegal::analyze]
+fn phantom_default_propagation(h: MarkedHandle) {
+    consume_any(h)
+}
+
+#[paralegal::marker(propagate_first, propagate = "owned", return)]
+fn propagate_first_source() -> u32 {
+    0
+}
+
+#[paralegal::analyze]
+fn propagate_before_refinement() {
+    consume_any(propagate_first_source())
+}
+
+#[paralegal::marker(stored, arguments = [0])]
+mod storage {
+    pub fn store_value(x: u32) {}
+
+    #[paralegal::marker(stored, return)]
+    pub fn load_value() -> u32 {
+        0
+    }
+}
+
+struct Logger;
+
+#[paralegal::marker(logged, arguments = [1])]
+impl Logger {
+    fn log_value(&self, x: u32) {}
+}
+
+#[paralegal::analyze]
+fn inherited_markers(input: u32) {
+    storage::store_value(input);
+    Logger.log_value(storage::load_value())
+}
+
+#[paralegal::marker(audited, arguments = [0])]
+mod audit {
+    pub fn make_auditor() -> impl Fn(u32) {
+        |x| super::consume_any(x)
+    }
+}
+
+#[paralegal::analyze]
+fn inherited_closure_markers() {
+    let audit = audit::make_auditor();
+    audit(closure_input())
+}
+
+#[paralegal::analyze]
+mod endpoints {
+    pub fn public_endpoint(x: u32) {
+        super::consume_any(x)
+    }
+
+    fn private_helper(x: u32) {
+        super::consume_any(x)
+    }
+}
+
 fn main() {}
@@ -43,7 +43,7 @@ define_test!(trait_method_marker: ctrl -> {
                 let weight = spdg.graph.node_weight(*node).unwrap();
                 !matches!(ctrl.graph().desc.instruction_info[&weight.at.leaf()].kind,
                     InstructionKind::FunctionCall(fun) if fun.id == method.ident)
-                || markers.contains(&marker)
+                || markers.contains_key(&marker)
             }));
     }
 });
@@ -77,3 +77,134 @@ define_test!(typed_input_zst: ctrl -> {
         })
     }))
 });
+
+define_test!(marked_static_read: ctrl -> {
+    let secret = ctrl.marked(Identifier::new_intern("secret"));
+    assert!(!secret.is_empty());
+    let consume = ctrl.function("consume_any");
+    let cs = ctrl.call_site(&consume);
+    assert!(secret.flows_to_data(&cs.input()));
+});
+
+define_test!(marked_const_read: ctrl -> {
+    let salt = ctrl.marked(Identifier::new_intern("salt"));
+    assert!(!salt.is_empty());
+    let consume = ctrl.function("consume_any");
+    let cs = ctrl.call_site(&consume);
+    assert!(salt.flows_to_data(&cs.input()));
+});
+
+define_test!(marked_closure: ctrl -> {
+    let source = ctrl.marked(Identifier::new_intern("source"));
+    let sink = ctrl.marked(Identifier::new_intern("sink"));
+    assert!(!source.is_empty());
+    assert!(!sink.is_empty());
+    assert!(source.flows_to_data(&sink));
+});
+
+#[test]
+fn marked_async_block() {
+    InlineTestBuilder::new(stringify!(
+        #![feature(stmt_expr_attributes)]
+
+        fn read() -> u32 {
+            0
+        }
+
+        #[paralegal_flow::marker(sink, arguments = [0])]
+        fn sink<T>(t: T) {}
+
+        async fn main() {
+            let value = #[paralegal_flow::marker(source, return)]
+            async {
+                read()
+            }
+            .await;
+            sink(value)
+        }
+    ))
+    .check_ctrl(|ctrl| {
+        let sources = ctrl.marked(Identifier::new_intern("source"));
+        let sinks = ctrl.marked(Identifier::new_intern("sink"));
+        assert!(!sources.is_empty());
+        assert!(sources.flows_to_data(&sinks));
+    })
+}
+
+fn argument_has_type_marker(ctrl: &CtrlRef, marker: &str) -> bool {
+    let marker = Identifier::new_intern(marker);
+    ctrl.spdg().arguments.iter().any(|node| {
+        ctrl.spdg()
+            .node_types(*node)
+            .iter()
+            .any(|t| ctrl.graph().desc.type_info[t].markers.contains(&marker))
+    })
+}
+
+define_test!(owned_propagation_box: ctrl -> {
+    assert!(argument_has_type_marker(&ctrl, "owned_secret"));
+});
+
+define_test!(owned_propagation_ref: ctrl -> {
+    assert!(!argument_has_type_marker(&ctrl, "owned_secret"));
+});
+
+define_test!(owned_propagation_vec: ctrl -> {
+    assert!(argument_has_type_marker(&ctrl, "owned_secret"));
+});
+
+define_test!(owned_propagation_arc_mutex: ctrl -> {
+    assert!(argument_has_type_marker(&ctrl, "owned_secret"));
+});
+
+define_test!(owned_propagation_slice_iter: ctrl -> {
+    assert!(!argument_has_type_marker(&ctrl, "owned_secret"));
+});
+
+define_test!(phantom_no_propagation: ctrl -> {
+    assert!(!argument_has_type_marker(&ctrl, "phantom_secret"));
+});
+
+define_test!(phantom_default_propagation: ctrl -> {
+    assert!(argument_has_type_marker(&ctrl, "marked"));
+});
+
+define_test!(propagate_before_refinement: ctrl -> {
+    let marked = ctrl.marked(Identifier::new_intern("propagate_first"));
+    let consume = ctrl.function("consume_any");
+    assert!(marked.flows_to_data(&ctrl.call_site(&consume).input()));
+});
+
+define_test!(inherited_markers: ctrl -> {
+    let stored = ctrl.marked(Identifier::new_intern("stored"));
+    let write = ctrl.function("store_value");
+    let read = ctrl.function("load_value");
+    let log = ctrl.function("log_value");
+    assert!(stored.overlaps(&ctrl.call_site(&write).input()));
+    // The marker on `load_value` overrides the one on the module
+    assert!(stored.overlaps(&ctrl.call_site(&read).output()));
+    let logged = ctrl.marked(Identifier::new_intern("logged"));
+    assert!(logged.overlaps(&ctrl.call_site(&log).input()));
+});
+
+define_test!(inherited_closure_markers: ctrl -> {
+    let source = ctrl.marked(Identifier::new_intern("source"));
+    let audited = ctrl.marked(Identifier::new_intern("audited"));
+    assert!(!audited.is_empty());
+    assert!(source.flows_to_data(&audited));
+});
+
+#[test]
+fn module_analyze_selects_public_functions() {
+    assert!(*TEST_CRATE_ANALYZED);
+    let graph = PreFrg::from_file_at(TEST_CRATE_NAME);
+    let has_ctrl = |name: &str| {
+        graph
+            .desc
+            .controllers
+            .values()
+            .any(|ctrl| ctrl.name.as_str() == name)
+    };
+    assert!(has_ctrl("public_endpoint"));
+    assert!(!has_ctrl("private_helper"));
+}
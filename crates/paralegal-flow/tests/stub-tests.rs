@@ -1,7 +1,8 @@
 //! These tests check that replacement models work.
 //!
-//! At the moment it only checks that replacing `std::thread::spawn(f)` being
-//! replaced by `f()` and analogous for `tokio::spawn` works.
+//! It checks that `std::thread::spawn(f)` is replaced by `f()` and analogous
+//! for `tokio::spawn`, that closures receive the arguments the stub maps onto
+//! their parameters, and that summary stubs create only the declared flows.
 
 #![feature(rustc_private)]
 
@@ -14,7 +15,9 @@ use paralegal_spdg::Identifier;
 const TEST_CRATE_NAME: &str = "tests/stub-tests";
 
 lazy_static! {
-    static ref TEST_CRATE_ANALYZED: bool = run_paralegal_flow_with_flow_graph_dump(TEST_CRATE_NAME);
+    static ref ANALYSIS: (bool, String) =
+        run_paralegal_flow_with_flow_graph_dump_capturing(TEST_CRATE_NAME);
+    static ref TEST_CRATE_ANALYZED: bool = ANALYSIS.0;
 }
 
 macro_rules! define_test {
@@ -89,3 +92,67 @@ define_test!(no_taint_without_connection: graph -> {
 
     assert!(!src.flows_to_data(&target));
 });
+
+define_test!(closure_on_element: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let pass = graph.marked(Identifier::new_intern("pass"));
+    let target = graph.marked(Identifier::new_intern("target"));
+
+    assert!(!src.is_empty());
+    assert!(!pass.is_empty());
+    assert!(!target.is_empty());
+
+    assert!(src.flows_to_data(&pass));
+    assert!(pass.flows_to_data(&target));
+});
+
+define_test!(closure_with_argument: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let pass = graph.marked(Identifier::new_intern("pass"));
+    let target = graph.marked(Identifier::new_intern("target"));
+
+    assert!(!src.is_empty());
+    assert!(!pass.is_empty());
+    assert!(!target.is_empty());
+
+    assert!(src.flows_to_data(&pass));
+    assert!(pass.flows_to_data(&target));
+});
+
+define_test!(stub_not_applicable: graph -> {
+    assert!(ANALYSIS.1.contains("configured stub for `call_with` is not used"));
+
+    let src = graph.marked(Identifier::new_intern("source"));
+    let target = graph.marked(Identifier::new_intern("target"));
+
+    assert!(!src.is_empty());
+    assert!(!target.is_empty());
+    assert!(!src.flows_to_data(&target));
+});
+
+define_test!(summary_replace: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let other = graph.marked(Identifier::new_intern("other_source"));
+    let pass = graph.marked(Identifier::new_intern("pass"));
+    let target = graph.marked(Identifier::new_intern("target"));
+    let replaced = graph.marked(Identifier::new_intern("replaced"));
+
+    assert!(!src.is_empty());
+    assert!(!other.is_empty());
+    assert!(!replaced.is_empty());
+
+    // The old value is returned, the new one written to the argument
+    assert!(src.flows_to_data(&target));
+    assert!(other.flows_to_data(&pass));
+    assert!(!other.flows_to_data(&target));
+});
+
+define_test!(summary_write_through_reference: graph -> {
+    let src = graph.marked(Identifier::new_intern("source"));
+    let target = graph.marked(Identifier::new_intern("target"));
+
+    assert!(!src.is_empty());
+    assert!(!target.is_empty());
+    // The value behind the reference is written, not just the reference
+    assert!(src.flows_to_data(&target));
+});
@@ -3,6 +3,11 @@ fn source() -> usize {
     0
 }
 
+#[paralegal::marker(other_source, return)]
+fn other_source() -> usize {
+    1
+}
+
 #[paralegal::marker(pass, arguments = [0])]
 fn pass<T>(t: T) -> T {
     t
@@ -19,6 +24,27 @@ fn thread_spawn() {
     target(next);
 }
 
+#[allow(dead_code)]
+#[paralegal::analyze]
+fn summary_replace() {
+    let mut value = source();
+    let old = std::mem::replace(&mut value, other_source());
+    target(old);
+    pass(value);
+}
+
+/// Stubbed to write `*src` into `*dst`.
+fn copy_into(_dst: &mut usize, _src: &usize) {}
+
+#[allow(dead_code)]
+#[paralegal::analyze]
+fn summary_write_through_reference() {
+    let src = source();
+    let mut dst = 0;
+    copy_into(&mut dst, &src);
+    target(dst);
+}
+
 fn main() {}
 
 #[allow(dead_code)]
@@ -101,3 +127,35 @@ async fn no_taint_without_connection(
         .await??,
     ))
 }
+
+#[allow(dead_code)]
+#[paralegal::analyze]
+fn closure_on_element() {
+    let next = Some(source()).map(|src| pass(src)).unwrap();
+    target(next);
+}
+
+/// Stubbed to call `f` with `value`.
+fn call_with<F: FnOnce(usize) -> usize>(f: F, _value: usize) -> usize {
+    f(0)
+}
+
+#[allow(dead_code)]
+#[paralegal::analyze]
+fn closure_with_argument() {
+    let next = call_with(|src| pass(src), source());
+    target(next);
+}
+
+fn pass_on(value: usize) -> usize {
+    value
+}
+
+/// The stub of `call_with` does not apply to a function pointer, so the call
+/// is analyzed as written, which drops the source.
+#[allow(dead_code)]
+#[paralegal::analyze]
+fn stub_not_applicable() {
+    let f: fn(usize) -> usize = pass_on;
+    target(call_with(f, source()));
+}
@@ -5,13 +5,13 @@ use std::time::{Duration, Instant};
 use std::vec;
 use std::{io::Write, process::exit, sync::Arc};
 
-use paralegal_spdg::rustc_portable::defid_as_local;
 pub use paralegal_spdg::rustc_portable::{DefId, LocalDefId};
 use paralegal_spdg::traverse::{generic_flows_to, EdgeSelection};
 use paralegal_spdg::{
-    CallString, DefKind, DisplayNode, Endpoint, GlobalNode, HashMap, HashSet, Identifier,
-    InstructionInfo, IntoIterGlobalNodes, Node as SPDGNode, NodeCluster, NodeInfo,
-    ProgramDescription, SPDGImpl, Span, TypeId, SPDG,
+    AnnotationSource, Approximation, CallString, DefKind, DiscoveredController, DisplayNode,
+    Endpoint, GlobalNode, HashMap, HashSet, Identifier, InstructionInfo, IntoIterGlobalNodes,
+    MarkerProvenance, Node as SPDGNode, NodeCluster, NodeInfo, ProgramDescription, SPDGImpl, Span,
+    TypeId, UnsafeApproximation, SPDG,
 };
 
 use anyhow::{anyhow, bail, Result};
@@ -297,7 +297,7 @@ impl Context {
             .values()
             .flat_map(|spdg| {
                 spdg.markers.iter().flat_map(move |(&inner, anns)| {
-                    anns.iter().map(move |marker| {
+                    anns.keys().map(move |marker| {
                         (
                             *marker,
                             Either::Left(GlobalNode::from_local_node(spdg.id, inner)),
@@ -358,6 +358,93 @@ impl Context {
             .chain(self.nodes_marked_via_type(marker))
     }
 
+    /// Why `node` carries `marker`, be that directly or via its type. Empty if
+    /// the node does not carry the marker.
+    pub fn marker_provenance(&self, node: GlobalNode, marker: Marker) -> Vec<MarkerProvenance> {
+        let direct = self.desc.controllers[&node.controller_id()]
+            .markers
+            .get(&node.local_node())
+            .and_then(|markers| markers.get(&marker))
+            .into_iter()
+            .flat_map(|provenance| provenance.iter().cloned());
+        let via_type = node
+            .types(self)
+            .iter()
+            .filter(|t| {
+                self.desc
+                    .type_info
+                    .get(t)
+                    .map_or(false, |info| info.markers.contains(&marker))
+            })
+            .map(|&item| MarkerProvenance {
+                item,
+                source: AnnotationSource::Type,
+                via_trait: false,
+            });
+        direct.chain(via_type).collect()
+    }
+
+    /// Explain a [`MarkerProvenance`] as the end of the sentence "this node
+    /// has the marker because ...".
+    pub fn describe_marker_provenance(&self, provenance: &MarkerProvenance) -> String {
+        let item = self.describe_def(provenance.item);
+        let on = if provenance.via_trait {
+            format!("{item}, which the called function implements")
+        } else {
+            item.to_string()
+        };
+        match &provenance.source {
+            AnnotationSource::Attribute => format!("of a marker attribute on {on}"),
+            AnnotationSource::External { file, line } => {
+                format!("of the entry for {on} in {file}:{line}")
+            }
+            AnnotationSource::Type => format!("it contains a value of the marked {item}"),
+            AnnotationSource::Stub => format!("of the summary stub configured for {on}"),
+        }
+    }
+
+    /// The calls in the PDG of `ctrl_id` that were approximated rather than
+    /// inlined.
+    pub fn approximations(&self, ctrl_id: Endpoint) -> impl Iterator<Item = &Approximation> + '_ {
+        self.desc
+            .approximations
+            .iter()
+            .filter(move |approximation| approximation.controller == ctrl_id)
+    }
+
+    /// The operations in the PDG of `ctrl_id` that were approximated
+    /// conservatively because of unsafe code. Flows through these operations
+    /// may be spurious.
+    pub fn unsafe_approximations(
+        &self,
+        ctrl_id: Endpoint,
+    ) -> impl Iterator<Item = &UnsafeApproximation> + '_ {
+        self.desc
+            .unsafe_approximations
+            .iter()
+            .filter(move |approximation| approximation.controller == ctrl_id)
+    }
+
+    /// The functions the controller discovery rules of the build configuration
+    /// selected. A policy can check that all of them were
+    /// [analyzed](DiscoveredController::analyzed) to make sure no route
+    /// handler is missing.
+    pub fn discovered_controllers(&self) -> impl Iterator<Item = &DiscoveredController> + '_ {
+        self.desc.discovered_controllers.iter()
+    }
+
+    /// The approximated calls that any of `nodes` are an argument to or the
+    /// return value of.
+    pub fn approximations_affecting(&self, nodes: impl IntoIterGlobalNodes) -> Vec<&Approximation> {
+        let call_strings = nodes
+            .iter_global_nodes()
+            .map(|node| self.node_info(node).at)
+            .collect::<HashSet<_>>();
+        self.approximations(nodes.controller_id())
+            .filter(|approximation| call_strings.contains(&approximation.call_string))
+            .collect()
+    }
+
     /// Find the node that represents the `index`th argument of the controller
     /// `ctrl_id`.
     ///
@@ -529,6 +616,15 @@ impl Context {
         self.desc().controllers.iter().map(|(k, v)| (*k, v))
     }
 
+    /// Iterate over the controllers that have `tag`, see [`SPDG::tags`].
+    pub fn controllers_with_tag(
+        &self,
+        tag: Identifier,
+    ) -> impl Iterator<Item = (Endpoint, &SPDG)> + '_ {
+        self.all_controllers()
+            .filter(move |(_, ctrl)| ctrl.tags.contains(&tag))
+    }
+
     /// Returns a DisplayDef for the given def_id
     pub fn describe_def(&self, def_id: DefId) -> DisplayDef {
         DisplayDef { ctx: self, def_id }
@@ -628,12 +724,16 @@ impl Context {
                         self.desc
                             .def_info
                             .iter()
-                            .filter(|(did, _)| {
-                                !matches!(defid_as_local(**did), Some(local)
-                                    if self.desc.analyzed_spans.contains_key(&local)
+                            .filter(|(did, _)| !self.desc.analyzed_spans.contains_key(did))
+                            .map(|(_, i)| {
+                                (
+                                    &i.src_info,
+                                    matches!(
+                                        i.kind,
+                                        DefKind::Type | DefKind::Static | DefKind::Const
+                                    ),
                                 )
                             })
-                            .map(|(_, i)| (&i.src_info, matches!(i.kind, DefKind::Type)))
                     })
                     .into_iter()
                     .flatten(),
@@ -95,7 +95,9 @@ use indexmap::IndexMap;
 use std::rc::Rc;
 use std::{io::Write, sync::Arc};
 
-use paralegal_spdg::{Endpoint, GlobalNode, Identifier, Span, SpanCoord, SPDG};
+use paralegal_spdg::{
+    AnnotationSource, Endpoint, GlobalNode, Identifier, IntoIterGlobalNodes, Span, SpanCoord, SPDG,
+};
 
 use crate::{Context, NodeExt};
 
@@ -428,6 +430,31 @@ impl<'a, A: HasDiagnosticsBase + ?Sized> DiagnosticBuilder<'a, A> {
         self.with_node(Severity::Note, node, message.into())
     }
 
+    /// Append notes explaining why `node` carries `marker`, one for each
+    /// annotation it got the marker from.
+    pub fn with_marker_provenance(&mut self, node: GlobalNode, marker: Identifier) -> &mut Self {
+        let base = self.base;
+        let ctx = base.as_ctx();
+        for provenance in ctx.marker_provenance(node, marker) {
+            let message = format!(
+                "{} is '{marker}' because {}",
+                node.describe(ctx),
+                ctx.describe_marker_provenance(&provenance)
+            );
+            let src_info = match provenance.source {
+                AnnotationSource::External { .. } | AnnotationSource::Stub => None,
+                // The item may be missing from the description, e.g. if it
+                // was merged from a crate that was not analyzed.
+                _ => ctx.desc().def_info.get(&provenance.item),
+            };
+            match src_info {
+                Some(info) => self.with_span_note(info.src_info.clone(), message),
+                None => self.with_note(message),
+            };
+        }
+        self
+    }
+
     fn with_node(&mut self, severity: Severity, node: GlobalNode, message: String) -> &mut Self {
         self.with_child(
             message,
@@ -681,6 +708,43 @@ pub trait Diagnostics: HasDiagnosticsBase {
     fn node_help(&self, node: GlobalNode, msg: impl Into<String>) {
         self.struct_node_note(node, msg).emit()
     }
+
+    /// Emit a warning for each approximated call that any of `nodes` are an
+    /// argument to or the return value of (see
+    /// [`Context::approximations_affecting`]). Flows through these calls
+    /// were not analyzed precisely.
+    ///
+    /// Returns whether any warning was emitted.
+    fn warn_on_approximations(&self, nodes: impl IntoIterGlobalNodes) -> bool {
+        let ctx = self.as_ctx();
+        let approximations = ctx.approximations_affecting(nodes);
+        for approximation in &approximations {
+            let callee = approximation.callee.map_or_else(
+                || "a function pointer with unknown targets".to_string(),
+                |callee| ctx.describe_def(callee).to_string(),
+            );
+            let mut warning = self.struct_span_warning(
+                approximation.span.clone(),
+                format!(
+                    "call to {callee} was approximated ({})",
+                    approximation.reason
+                ),
+            );
+            if approximation.markers_reachable {
+                warning.with_warning(
+                    "the called function reaches marked functions, markers may be missing",
+                );
+            }
+            for missing in approximation.missing_bodies.iter() {
+                warning.with_warning(format!(
+                    "the body of {} is missing, markers reachable through it are unknown",
+                    ctx.describe_def(*missing)
+                ));
+            }
+            warning.emit();
+        }
+        !approximations.is_empty()
+    }
 }
 
 fn highlighted_node_span(ctx: &Context, node: GlobalNode) -> HighlightedSpan {
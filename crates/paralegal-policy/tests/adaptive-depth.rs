@@ -1,6 +1,6 @@
 use anyhow::Result;
 use helpers::Test;
-use paralegal_policy::{assert_error, EdgeSelection};
+use paralegal_policy::{assert_error, Diagnostics, EdgeSelection};
 use paralegal_spdg::Identifier;
 
 mod helpers;
@@ -62,3 +62,109 @@ fn higher_order_futures() -> Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn approximations_are_reported() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        fn transform(x: usize) -> usize {
+            x + 1
+        }
+
+        #[paralegal::marker(sink, arguments = [0])]
+        fn sink<T>(t: T) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            sink(transform(source()))
+        }
+    ))?;
+
+    test.with_paralegal_args(["--adaptive-depth"]);
+
+    test.run(|ctx| {
+        let m_source = Identifier::new_intern("source");
+        let sources = ctx.marked_nodes(m_source).collect::<Box<_>>();
+        assert_error!(ctx, !sources.is_empty());
+        let region = sources
+            .iter()
+            .flat_map(|&source| ctx.influencees(source, EdgeSelection::Data))
+            .collect::<Box<_>>();
+        let approximations = region
+            .iter()
+            .flat_map(|&node| ctx.approximations_affecting(node))
+            .collect::<Vec<_>>();
+        assert_error!(
+            ctx,
+            approximations.iter().any(|a| {
+                a.callee.map_or(false, |callee| {
+                    ctx.desc().def_info[&callee].name.as_str() == "transform"
+                }) && a.reason.as_str() == "adaptive inlining"
+                    && !a.markers_reachable
+            }),
+            "Approximation of `transform` not reported"
+        );
+        assert_error!(
+            ctx,
+            region.iter().any(|&node| ctx.warn_on_approximations(node))
+        );
+        Ok(())
+    })
+}
+
+/// The standard library is not compiled by paralegal-flow, so neither its
+/// bodies nor its marker metadata are available even though it is included.
+#[test]
+fn missing_dependency_bodies_are_reported() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(sink, arguments = [0])]
+        fn sink<T>(t: T) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            sink(core::convert::identity(source()))
+        }
+    ))?;
+
+    test.with_paralegal_args(["--adaptive-depth", "--include", "core"]);
+
+    test.run(|ctx| {
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<_>>();
+        let sinks = ctx
+            .marked_nodes(Identifier::new_intern("sink"))
+            .collect::<Box<_>>();
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &sinks, EdgeSelection::Data)
+                .is_some()
+        );
+        let approximations = ctx
+            .desc()
+            .controllers
+            .keys()
+            .flat_map(|&ctrl| ctx.approximations(ctrl))
+            .collect::<Vec<_>>();
+        assert_error!(
+            ctx,
+            approximations.iter().any(|a| {
+                a.callee.map_or(false, |callee| {
+                    ctx.desc().def_info[&callee].name.as_str() == "identity"
+                        && a.missing_bodies.contains(&callee)
+                }) && a.reason.as_str() == "missing MIR"
+            }),
+            "Approximation of `identity` not reported"
+        );
+        Ok(())
+    })
+}
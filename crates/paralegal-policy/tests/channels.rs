@@ -0,0 +1,98 @@
+mod helpers;
+
+use anyhow::Result;
+use helpers::{check_flow, Test};
+
+#[test]
+fn flows_through_std_channel() -> Result<()> {
+    let test = Test::new(stringify!(
+        use std::sync::mpsc::channel;
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let (tx, rx) = channel();
+            tx.send(source()).unwrap();
+            target(rx.recv().unwrap())
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_into_spawned_thread() -> Result<()> {
+    let test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let value = source();
+            std::thread::spawn(move || target(value)).join().unwrap()
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_into_spawned_task() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        async fn main() {
+            let value = source();
+            tokio::spawn(async move { target(value) }).await.unwrap()
+        }
+    ))?;
+
+    test.with_dep(["tokio", "--features", "full"]);
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_through_tokio_channel() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        use tokio::sync::mpsc::channel;
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        async fn main() {
+            let (tx, mut rx) = channel(1);
+            tokio::spawn(async move { tx.send(source()).await.unwrap() });
+            target(rx.recv().await.unwrap())
+        }
+    ))?;
+
+    test.with_dep(["tokio", "--features", "full"]);
+
+    test.run(check_flow)
+}
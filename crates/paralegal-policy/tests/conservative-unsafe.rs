@@ -0,0 +1,195 @@
+mod helpers;
+
+use anyhow::Result;
+use helpers::Test;
+use paralegal_policy::{assert_error, EdgeSelection};
+use paralegal_spdg::Identifier;
+
+#[test]
+fn raw_pointer_writes_are_conservative() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let mut a = 0;
+            let addr = &mut a as *mut usize as usize;
+            let p = addr as *mut usize;
+            unsafe {
+                *p = source();
+            }
+            target(a)
+        }
+    ))?;
+
+    test.with_paralegal_args(["--conservative-unsafe"]);
+
+    test.run(|ctx| {
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<[_]>>();
+        let targets = ctx
+            .marked_nodes(Identifier::new_intern("target"))
+            .collect::<Box<[_]>>();
+        assert_error!(ctx, !sources.is_empty());
+        assert_error!(ctx, !targets.is_empty());
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &targets, EdgeSelection::Data)
+                .is_some()
+        );
+        let ctrl = ctx.controller_by_name(Identifier::new_intern("main"))?;
+        assert_error!(
+            ctx,
+            ctx.unsafe_approximations(ctrl)
+                .any(|a| a.operation == Identifier::new_intern("raw pointer dereference"))
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn transmuted_pointers_are_conservative() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let mut a = 0;
+            let addr = &mut a as *mut usize as usize;
+            let p: &mut usize = unsafe { std::mem::transmute(addr) };
+            *p = source();
+            target(a)
+        }
+    ))?;
+
+    test.with_paralegal_args(["--conservative-unsafe"]);
+
+    test.run(|ctx| {
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<[_]>>();
+        let targets = ctx
+            .marked_nodes(Identifier::new_intern("target"))
+            .collect::<Box<[_]>>();
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &targets, EdgeSelection::Data)
+                .is_some()
+        );
+        let ctrl = ctx.controller_by_name(Identifier::new_intern("main"))?;
+        assert_error!(
+            ctx,
+            ctx.unsafe_approximations(ctrl)
+                .any(|a| a.operation == Identifier::new_intern("transmute"))
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn foreign_calls_are_conservative() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> i64 {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: i64) {}
+
+        extern "C" {
+            fn labs(x: i64) -> i64;
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            let v = unsafe { labs(source()) };
+            target(v)
+        }
+    ))?;
+
+    test.with_paralegal_args(["--conservative-unsafe"]);
+
+    test.run(|ctx| {
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<[_]>>();
+        let targets = ctx
+            .marked_nodes(Identifier::new_intern("target"))
+            .collect::<Box<[_]>>();
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &targets, EdgeSelection::Data)
+                .is_some()
+        );
+        let ctrl = ctx.controller_by_name(Identifier::new_intern("main"))?;
+        assert_error!(
+            ctx,
+            ctx.unsafe_approximations(ctrl)
+                .any(|a| a.operation == Identifier::new_intern("foreign call"))
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn stubs_replace_conservative_foreign_calls() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> i64 {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: i64) {}
+
+        extern "C" {
+            fn labs(x: i64) -> i64;
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            let v = unsafe { labs(source()) };
+            target(v)
+        }
+    ))?;
+
+    test.with_paralegal_args(["--conservative-unsafe"]);
+    // The return value does not depend on the argument
+    test.with_build_config(
+        r#"
+        [stubs."crate::labs"]
+        mode = "summary"
+        "#,
+    );
+
+    test.run(|ctx| {
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<[_]>>();
+        let targets = ctx
+            .marked_nodes(Identifier::new_intern("target"))
+            .collect::<Box<[_]>>();
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &targets, EdgeSelection::Data)
+                .is_none()
+        );
+        let ctrl = ctx.controller_by_name(Identifier::new_intern("main"))?;
+        assert_error!(ctx, ctx.unsafe_approximations(ctrl).next().is_none());
+        Ok(())
+    })
+}
@@ -0,0 +1,211 @@
+mod helpers;
+
+use anyhow::Result;
+use helpers::Test;
+use paralegal_policy::{assert_error, Context, EdgeSelection};
+use paralegal_spdg::Identifier;
+use std::sync::Arc;
+
+fn discovered<'a>(ctx: &'a Context, name: &str) -> Vec<&'a paralegal_spdg::DiscoveredController> {
+    ctx.discovered_controllers()
+        .filter(|d| ctx.desc().def_info[&d.function].name.as_str() == name)
+        .collect()
+}
+
+#[test]
+fn discover_registered_handlers() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        struct Router;
+
+        impl Router {
+            fn new() -> Self {
+                Router
+            }
+
+            fn route(self, path: &str, handler: fn(u32)) -> Self {
+                self
+            }
+        }
+
+        #[paralegal::marker(source, return)]
+        fn source() -> u32 {
+            0
+        }
+
+        #[paralegal::marker(sink, arguments = [0])]
+        fn sink(x: u32) {}
+
+        fn index(x: u32) {
+            sink(source())
+        }
+
+        fn users(x: u32) {}
+
+        fn generic<T>(x: u32) {}
+
+        fn app() -> Router {
+            Router::new()
+                .route("/", index)
+                .route("/users", users)
+                .route("/generic", generic::<u8>)
+        }
+    ))?;
+    test.with_build_config(
+        r#"
+        [discovery.router]
+        registrations = [{ function = "*::Router::route", argument = 2 }]
+        "#,
+    );
+    test.run(|ctx| {
+        let controller_names = ctx
+            .desc()
+            .controllers
+            .values()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_error!(ctx, controller_names.contains(&"index"));
+        assert_error!(ctx, controller_names.contains(&"users"));
+        assert_error!(ctx, !controller_names.contains(&"app"));
+        assert_error!(ctx, ctx.discovered_controllers().count() == 3);
+        for d in ctx.discovered_controllers() {
+            assert_error!(ctx, d.rule == Identifier::new_intern("router"));
+        }
+        let [generic] = discovered(&ctx, "generic")[..] else {
+            panic!("generic was not discovered")
+        };
+        assert_error!(ctx, !generic.analyzed);
+
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<[_]>>();
+        let sinks = ctx
+            .marked_nodes(Identifier::new_intern("sink"))
+            .collect::<Box<[_]>>();
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &sinks, EdgeSelection::Data)
+                .is_some()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn discover_attribute_macros() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[tokio::main]
+        async fn serve() {}
+
+        async fn not_a_controller() {}
+    ))?;
+    test.with_dep(["tokio", "--features", "full"]);
+    test.with_build_config(
+        r#"
+        [discovery.tokio]
+        attributes = ["tokio::main"]
+        "#,
+    );
+    test.run(|ctx: Arc<Context>| {
+        let [serve] = discovered(&ctx, "serve")[..] else {
+            panic!("serve was not discovered")
+        };
+        assert_error!(ctx, serve.analyzed);
+        assert_error!(ctx, serve.via == Identifier::new_intern("tokio::main"));
+        assert_error!(ctx, ctx.discovered_controllers().count() == 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn discover_axum_handlers() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        use axum::{routing::get, Router};
+
+        #[paralegal::marker(source, return)]
+        fn source() -> u32 {
+            0
+        }
+
+        #[paralegal::marker(sink, arguments = [0])]
+        fn sink(x: u32) {}
+
+        async fn index() {
+            sink(source())
+        }
+
+        async fn users() {}
+
+        pub fn app() -> Router {
+            Router::new()
+                .route("/", get(index))
+                .route("/users", get(users))
+        }
+    ))?;
+    test.with_dep(["axum@0.7"]);
+    test.with_build_config(
+        r#"
+        [discovery.axum]
+        registrations = [{ function = "axum::routing::get", argument = 0 }]
+        "#,
+    );
+    test.run(|ctx| {
+        let [index] = discovered(&ctx, "index")[..] else {
+            panic!("index was not discovered")
+        };
+        assert_error!(ctx, index.analyzed);
+        assert_error!(ctx, discovered(&ctx, "users").len() == 1);
+        assert_error!(ctx, ctx.discovered_controllers().count() == 2);
+
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<[_]>>();
+        let sinks = ctx
+            .marked_nodes(Identifier::new_intern("sink"))
+            .collect::<Box<[_]>>();
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &sinks, EdgeSelection::Data)
+                .is_some()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn discover_rocket_routes() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[rocket::get("/")]
+        fn index() -> &'static str {
+            "index"
+        }
+
+        #[rocket::post("/users")]
+        fn create_user() {}
+
+        fn not_a_route() {}
+    ))?;
+    test.with_dep(["rocket@0.5"]);
+    test.with_build_config(
+        r#"
+        [discovery.rocket]
+        attributes = ["rocket::get", "rocket::post"]
+        "#,
+    );
+    test.run(|ctx| {
+        let [index] = discovered(&ctx, "index")[..] else {
+            panic!("index was not discovered")
+        };
+        assert_error!(ctx, index.analyzed);
+        assert_error!(ctx, index.via == Identifier::new_intern("rocket::get"));
+        let [create_user] = discovered(&ctx, "create_user")[..] else {
+            panic!("create_user was not discovered")
+        };
+        assert_error!(
+            ctx,
+            create_user.via == Identifier::new_intern("rocket::post")
+        );
+        // The functions the route attributes generate are not controllers
+        assert_error!(ctx, ctx.discovered_controllers().count() == 2);
+        Ok(())
+    })
+}
@@ -0,0 +1,74 @@
+mod helpers;
+
+use anyhow::Result;
+use helpers::Test;
+use paralegal_policy::assert_error;
+use paralegal_spdg::Identifier;
+
+#[test]
+fn tags_and_routes() -> Result<()> {
+    let test = Test::new(stringify!(
+        /// Deletes a user. @admin
+        #[paralegal::analyze(tag = "public", route = "/users/:id")]
+        #[inline(never)]
+        fn delete_user(id: u32) {}
+
+        #[paralegal::analyze]
+        fn index() {}
+    ))?;
+    test.run(|ctx| {
+        let public = ctx
+            .controllers_with_tag(Identifier::new_intern("public"))
+            .map(|(_, ctrl)| ctrl)
+            .collect::<Vec<_>>();
+        assert_error!(ctx, public.len() == 1);
+        let delete_user = public[0];
+        assert_error!(ctx, delete_user.name.as_str() == "delete_user");
+        assert_error!(
+            ctx,
+            delete_user.route == Some(Identifier::new_intern("/users/:id"))
+        );
+        assert_error!(
+            ctx,
+            delete_user.tags.contains(&Identifier::new_intern("admin"))
+        );
+        // The paralegal annotation is left out
+        assert_error!(ctx, &*delete_user.attributes == ["#[inline(never)]"]);
+        assert_error!(
+            ctx,
+            ctx.controllers_with_tag(Identifier::new_intern("admin"))
+                .count()
+                == 1
+        );
+
+        let index = ctx.controller_by_name(Identifier::new_intern("index"))?;
+        let index = &ctx.desc().controllers[&index];
+        assert_error!(ctx, index.tags.is_empty());
+        assert_error!(ctx, index.route.is_none());
+        assert_error!(ctx, index.attributes.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn tags_with_instantiation() -> Result<()> {
+    let test = Test::new(stringify!(
+        trait Db {}
+
+        struct Postgres;
+
+        impl Db for Postgres {}
+
+        #[paralegal::analyze(T = Postgres, tag = "db")]
+        fn handle<T: Db>(db: &T) {}
+    ))?;
+    test.run(|ctx| {
+        assert_error!(
+            ctx,
+            ctx.controllers_with_tag(Identifier::new_intern("db"))
+                .count()
+                == 1
+        );
+        Ok(())
+    })
+}
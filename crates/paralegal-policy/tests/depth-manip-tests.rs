@@ -86,3 +86,68 @@ fn adaptive_inlines_if_reachable_async() -> Result<()> {
         Ok(())
     })
 }
+
+/// `main` calls `outer`, which calls `inner`, which calls the source. With a
+/// bound of 2 the source is reached, with a bound of 1 the call to `inner` is
+/// cut off and reported as an approximation.
+fn bounded_depth_test(depth: usize, expect_flow: bool) -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments=[0])]
+        fn target(u: usize) {}
+
+        fn inner() -> usize {
+            source()
+        }
+
+        fn outer() -> usize {
+            inner()
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            target(outer())
+        }
+    ))?;
+
+    test.with_paralegal_args(["--bounded-depth".to_string(), depth.to_string()]);
+
+    test.run(|ctx| {
+        let sources = ctx
+            .marked_nodes(Identifier::new_intern("source"))
+            .collect::<Box<[_]>>();
+        let targets = ctx
+            .marked_nodes(Identifier::new_intern("target"))
+            .collect::<Box<[_]>>();
+        assert_error!(ctx, !targets.is_empty());
+        assert_error!(
+            ctx,
+            ctx.any_flows(&sources, &targets, EdgeSelection::Data)
+                .is_some()
+                == expect_flow
+        );
+        let cut = ctx.desc().approximations.iter().any(|a| {
+            a.reason.as_str() == "call string limit"
+                && a.callee.map_or(false, |callee| {
+                    ctx.desc().def_info[&callee].name.as_str() == "inner"
+                })
+                && a.markers_reachable
+        });
+        assert_error!(ctx, cut != expect_flow);
+        Ok(())
+    })
+}
+
+#[test]
+fn bounded_depth_inlines_within_bound() -> Result<()> {
+    bounded_depth_test(2, true)
+}
+
+#[test]
+fn bounded_depth_reports_cut() -> Result<()> {
+    bounded_depth_test(1, false)
+}
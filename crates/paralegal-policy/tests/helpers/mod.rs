@@ -11,7 +11,8 @@ use std::{
 use anyhow::anyhow;
 pub use anyhow::{ensure, Result};
 
-use paralegal_policy::{Context, GraphLocation};
+use paralegal_policy::{assert_error, Context, EdgeSelection, GraphLocation};
+use paralegal_spdg::Identifier;
 
 lazy_static::lazy_static! {
     static ref TOOL_BUILT: PathBuf = {
@@ -56,7 +57,9 @@ pub struct Test {
     paralegal_args: Vec<String>,
     context_config: paralegal_policy::Config,
     external_annotations: Option<String>,
+    build_config: Option<String>,
     deps: Vec<Vec<OsString>>,
+    members: Vec<(String, String)>,
     tool_path: &'static Path,
     external_ann_file_name: PathBuf,
     cleanup: bool,
@@ -81,8 +84,10 @@ impl Test {
             paralegal_args: vec![],
             context_config: Default::default(),
             external_annotations: None,
+            build_config: None,
             tool_path: &*TOOL_BUILT,
             deps: Default::default(),
+            members: Default::default(),
             cleanup: true,
             expect_fail: false,
         })
@@ -116,6 +121,16 @@ impl Test {
         self
     }
 
+    /// Contents of the `Paralegal.toml` build configuration of the test crate.
+    #[allow(dead_code)]
+    pub fn with_build_config(&mut self, config: impl Into<String>) -> &mut Self {
+        let res = self.build_config.replace(config.into());
+        if let Some(config) = res {
+            panic!("Duplicate setting of build config. Found prior:\n{config}");
+        }
+        self
+    }
+
     /// Add additional dependencies. The argument to this function are command
     /// line arguments as would be given to `cargo add`. You may call this
     /// function multiple times fo add more dependencies.
@@ -125,6 +140,18 @@ impl Test {
         self
     }
 
+    /// Turn the test crate into a workspace with an additional member crate
+    /// called `name` whose `lib.rs` contains `code`.
+    #[allow(dead_code)]
+    pub fn with_workspace_member(
+        &mut self,
+        name: impl Into<String>,
+        code: impl Into<String>,
+    ) -> &mut Self {
+        self.members.push((name.into(), code.into()));
+        self
+    }
+
     #[allow(dead_code)]
     pub fn context_config(&mut self) -> &mut paralegal_policy::Config {
         &mut self.context_config
@@ -171,11 +198,49 @@ impl Test {
             let mut f = File::create(&self.external_ann_file_name)?;
             writeln!(f, "{external_anns}")?;
         }
+        if let Some(build_config) = self.build_config.as_ref() {
+            let mut f = File::create(self.tempdir.join("Paralegal.toml"))?;
+            writeln!(f, "{build_config}")?;
+        }
 
         let main_file_path = self.tempdir.join("src").join("lib.rs");
         let mut main_file = File::create(main_file_path)?;
         writeln!(main_file, "#![allow(dead_code)]")?;
         writeln!(main_file, "{}", self.code)?;
+        self.populate_workspace_members(&paralegal_lib_path)
+    }
+
+    fn populate_workspace_members(&self, paralegal_lib_path: &Path) -> Result<()> {
+        use std::io::Write;
+        if self.members.is_empty() {
+            return Ok(());
+        }
+        for (name, code) in &self.members {
+            let mut cmd = self.cargo_cmd();
+            cmd.args(["new", "--lib", name]);
+            ensure_run_success(&mut cmd)?;
+            let mut cmd = self.cargo_cmd();
+            cmd.current_dir(self.tempdir.join(name));
+            cmd.args([OsStr::new("add"), OsStr::new("--path")])
+                .arg(paralegal_lib_path);
+            ensure_run_success(&mut cmd)?;
+            let mut lib_file = File::create(self.tempdir.join(name).join("src").join("lib.rs"))?;
+            writeln!(lib_file, "#![allow(dead_code)]")?;
+            writeln!(lib_file, "{code}")?;
+        }
+        let mut manifest = fs::OpenOptions::new()
+            .append(true)
+            .open(self.tempdir.join("Cargo.toml"))?;
+        writeln!(manifest, "\n[workspace]")?;
+        writeln!(
+            manifest,
+            "members = [{}]",
+            self.members
+                .iter()
+                .map(|(name, _)| format!("{name:?}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
         Ok(())
     }
 
@@ -212,3 +277,33 @@ impl Test {
         ensure_run_success(&mut paralegal_cmd)
     }
 }
+
+/// Check that data flows from a node marked `source` to a node marked
+/// `target`.
+pub fn check_flow(ctx: Arc<Context>) -> Result<()> {
+    check_source_target_flow(ctx, true)
+}
+
+/// Check that there are nodes marked `source` and `target`, but no data flows
+/// between them.
+pub fn check_no_flow(ctx: Arc<Context>) -> Result<()> {
+    check_source_target_flow(ctx, false)
+}
+
+fn check_source_target_flow(ctx: Arc<Context>, expect_flow: bool) -> Result<()> {
+    let sources = ctx
+        .marked_nodes(Identifier::new_intern("source"))
+        .collect::<Box<[_]>>();
+    let targets = ctx
+        .marked_nodes(Identifier::new_intern("target"))
+        .collect::<Box<[_]>>();
+    assert_error!(ctx, !sources.is_empty());
+    assert_error!(ctx, !targets.is_empty());
+    assert_error!(
+        ctx,
+        ctx.any_flows(&sources, &targets, EdgeSelection::Data)
+            .is_some()
+            == expect_flow
+    );
+    Ok(())
+}
@@ -0,0 +1,176 @@
+mod helpers;
+
+use anyhow::Result;
+use helpers::{check_flow, Test};
+
+#[test]
+fn flows_through_ref_cell() -> Result<()> {
+    let test = Test::new(stringify!(
+        use std::cell::RefCell;
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let c = RefCell::new(0);
+            {
+                let mut g = c.borrow_mut();
+                *g = source();
+            }
+            target(*c.borrow())
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_through_atomic() -> Result<()> {
+    let test = Test::new(stringify!(
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let a = AtomicUsize::new(0);
+            a.store(source(), Ordering::SeqCst);
+            target(a.load(Ordering::SeqCst))
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_through_mutex() -> Result<()> {
+    let test = Test::new(stringify!(
+        use std::sync::Mutex;
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let m = Mutex::new(0);
+            *m.lock().unwrap() = source();
+            target(*m.lock().unwrap())
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_through_cell() -> Result<()> {
+    let test = Test::new(stringify!(
+        use std::cell::Cell;
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let c = Cell::new(0);
+            c.set(source());
+            target(c.get())
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_through_rw_lock() -> Result<()> {
+    let test = Test::new(stringify!(
+        use std::sync::RwLock;
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            let l = RwLock::new(0);
+            *l.write().unwrap() = source();
+            target(*l.read().unwrap())
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_through_configured_model() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        use std::cell::UnsafeCell;
+
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        struct Slot {
+            value: UnsafeCell<usize>,
+        }
+
+        impl Slot {
+            fn put(&self, value: usize) {
+                unsafe { *self.value.get() = value }
+            }
+
+            fn take(&self) -> usize {
+                unsafe { *self.value.get() }
+            }
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            let slot = Slot {
+                value: UnsafeCell::new(0),
+            };
+            slot.put(source());
+            target(slot.take())
+        }
+    ))?;
+    test.with_build_config(
+        r#"
+        [interior-mutability]
+        "*::Slot::put" = { kind = "set", target = 0 }
+
+        [inlining]
+        "*::Slot::take" = "abstract"
+        "#,
+    );
+
+    test.run(check_flow)
+}
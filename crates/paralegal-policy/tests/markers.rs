@@ -1,7 +1,7 @@
 use anyhow::Result;
 use helpers::Test;
 use paralegal_policy::{assert_error, Context, Diagnostics, EdgeSelection, NodeExt};
-use paralegal_spdg::{GlobalNode, Identifier};
+use paralegal_spdg::{AnnotationSource, GlobalNode, Identifier};
 use std::sync::Arc;
 
 mod helpers;
@@ -650,3 +650,75 @@ fn boxes() -> Result<()> {
     ))?;
     test.run(policy)
 }
+
+#[test]
+fn marker_provenance() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(dangerous)]
+        struct Child {
+            field: usize,
+        }
+
+        #[paralegal::marker(sink, arguments = [0])]
+        fn sink<T>(_: T) {}
+
+        #[paralegal::analyze]
+        fn main() {
+            sink(Child { field: 0 });
+            sink(std::path::PathBuf::new())
+        }
+    ))?;
+    test.with_external_annotations(
+        "
+[[\"std::path::PathBuf::new\"]]
+marker = \"source\"
+on_return = true
+    ",
+    );
+    test.run(|ctx| {
+        let m_sink = Identifier::new_intern("sink");
+        let m_source = Identifier::new_intern("source");
+        let m_dangerous = Identifier::new_intern("dangerous");
+        let sinks = ctx.marked_nodes(m_sink).collect::<Vec<_>>();
+        let sources = ctx.marked_nodes(m_source).collect::<Vec<_>>();
+        let dangerous = ctx.nodes_marked_via_type(m_dangerous).collect::<Vec<_>>();
+        assert_error!(ctx, !sinks.is_empty());
+        assert_error!(ctx, !sources.is_empty());
+        assert_error!(ctx, !dangerous.is_empty());
+        for &sink in &sinks {
+            let provenance = ctx.marker_provenance(sink, m_sink);
+            assert_error!(
+                ctx,
+                provenance
+                    .iter()
+                    .any(|p| p.source == AnnotationSource::Attribute
+                        && ctx.desc().def_info[&p.item].name.as_str() == "sink"),
+                "Sink marker not attributed to the annotation on `sink`"
+            );
+        }
+        for &source in &sources {
+            let provenance = ctx.marker_provenance(source, m_source);
+            assert_error!(
+                ctx,
+                provenance
+                    .iter()
+                    .any(|p| matches!(p.source, AnnotationSource::External { line: 2, .. })),
+                "Source marker not attributed to the external annotation"
+            );
+        }
+        for &node in &dangerous {
+            let provenance = ctx.marker_provenance(node, m_dangerous);
+            assert_error!(
+                ctx,
+                provenance
+                    .iter()
+                    .any(|p| p.source == AnnotationSource::Type),
+                "Type marker not attributed to the type"
+            );
+            let mut msg = ctx.struct_node_note(node, "This node is dangerous");
+            msg.with_marker_provenance(node, m_dangerous);
+            msg.emit();
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,136 @@
+mod helpers;
+
+use anyhow::Result;
+use helpers::{check_flow, check_no_flow, Test};
+
+#[test]
+fn flows_through_direct_recursion() -> Result<()> {
+    let test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        fn walk(n: usize, x: usize) -> usize {
+            if n == 0 {
+                x
+            } else {
+                walk(n - 1, x)
+            }
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            target(walk(3, source()))
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn flows_through_mutual_recursion() -> Result<()> {
+    let test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        fn even(n: usize, from: usize, to: &mut usize) {
+            if n == 0 {
+                *to = from;
+            } else {
+                odd(n - 1, from, to)
+            }
+        }
+
+        fn odd(n: usize, from: usize, to: &mut usize) {
+            if n != 0 {
+                even(n - 1, from, to)
+            }
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            let mut result = 0;
+            odd(5, source(), &mut result);
+            target(result)
+        }
+    ))?;
+
+    test.run(check_flow)
+}
+
+#[test]
+fn recursion_summary_is_precise() -> Result<()> {
+    let test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        fn count(n: usize, ignored: usize) -> usize {
+            if n == 0 {
+                0
+            } else {
+                count(n - 1, ignored) + 1
+            }
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            target(count(3, source()))
+        }
+    ))?;
+
+    test.run(check_no_flow)
+}
+
+#[test]
+fn flows_through_rotating_cycle() -> Result<()> {
+    let test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        fn first(n: usize, p: usize, q: usize, out: &mut usize) {
+            second(n, p, q, out)
+        }
+
+        fn second(n: usize, p: usize, q: usize, out: &mut usize) {
+            third(n, p, q, out)
+        }
+
+        fn third(n: usize, p: usize, q: usize, out: &mut usize) {
+            if n == 0 {
+                *out = q;
+            } else {
+                first(n - 1, q, p, out)
+            }
+        }
+
+        #[paralegal::analyze]
+        fn main() {
+            let mut result = 0;
+            first(4, source(), 0, &mut result);
+            target(result);
+            let mut other = 0;
+            third(4, source(), 0, &mut other);
+        }
+    ))?;
+
+    test.run(check_flow)
+}
@@ -0,0 +1,64 @@
+mod helpers;
+
+use anyhow::Result;
+use helpers::Test;
+use paralegal_policy::{assert_error, EdgeSelection};
+use paralegal_spdg::Identifier;
+
+#[test]
+fn controllers_of_all_members_are_merged() -> Result<()> {
+    let mut test = Test::new(stringify!(
+        #[paralegal::marker(source, return)]
+        fn source() -> usize {
+            0
+        }
+
+        #[paralegal::marker(target, arguments = [0])]
+        fn target(u: usize) {}
+
+        #[paralegal::analyze]
+        fn first() {
+            target(source())
+        }
+    ))?;
+
+    test.with_workspace_member(
+        "second_member",
+        stringify!(
+            #[paralegal::marker(source, return)]
+            fn source() -> usize {
+                0
+            }
+
+            #[paralegal::marker(target, arguments = [0])]
+            fn target(u: usize) {}
+
+            #[paralegal::analyze]
+            fn second() {
+                target(source())
+            }
+        ),
+    );
+
+    test.run(|ctx| {
+        for name in ["first", "second"] {
+            let ctrl = ctx.controller_by_name(Identifier::new_intern(name))?;
+            let sources = ctx
+                .marked_nodes(Identifier::new_intern("source"))
+                .filter(|n| n.controller_id() == ctrl)
+                .collect::<Box<[_]>>();
+            let targets = ctx
+                .marked_nodes(Identifier::new_intern("target"))
+                .filter(|n| n.controller_id() == ctrl)
+                .collect::<Box<[_]>>();
+            assert_error!(ctx, !sources.is_empty());
+            assert_error!(ctx, !targets.is_empty());
+            assert_error!(
+                ctx,
+                ctx.any_flows(&sources, &targets, EdgeSelection::Data)
+                    .is_some()
+            );
+        }
+        Ok(())
+    })
+}
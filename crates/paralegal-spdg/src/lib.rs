@@ -29,6 +29,7 @@ extern crate strum;
 pub use flowistry_pdg::*;
 
 pub mod dot;
+mod merge;
 pub mod ser;
 mod tiny_bitset;
 pub mod traverse;
@@ -46,7 +47,6 @@ use utils::serde_map_via_vec;
 
 pub use crate::tiny_bitset::pretty as tiny_bitset_pretty;
 pub use crate::tiny_bitset::TinyBitSet;
-use flowistry_pdg::rustc_portable::LocalDefId;
 use petgraph::graph::{EdgeIndex, EdgeReference, NodeIndex};
 use petgraph::prelude::EdgeRef;
 use petgraph::visit::IntoNodeIdentifiers;
@@ -64,35 +64,6 @@ pub type Function = Identifier;
 /// [`ProgramDescription`].
 pub const FLOW_GRAPH_OUT_NAME: &str = "flow-graph.o";
 
-#[allow(dead_code)]
-mod ser_localdefid_map {
-    use serde::{Deserialize, Serialize};
-
-    use flowistry_pdg::rustc_proxies;
-
-    #[derive(Serialize, Deserialize)]
-    struct Helper(#[serde(with = "rustc_proxies::LocalDefId")] super::LocalDefId);
-
-    pub fn serialize<S: serde::Serializer, V: serde::Serialize>(
-        map: &super::HashMap<super::LocalDefId, V>,
-        serializer: S,
-    ) -> Result<S::Ok, S::Error> {
-        map.iter()
-            .map(|(k, v)| (Helper(*k), v))
-            .collect::<Vec<_>>()
-            .serialize(serializer)
-    }
-
-    pub fn deserialize<'de, D: serde::Deserializer<'de>, V: serde::Deserialize<'de>>(
-        deserializer: D,
-    ) -> Result<super::HashMap<super::LocalDefId, V>, D::Error> {
-        Ok(Vec::deserialize(deserializer)?
-            .into_iter()
-            .map(|(Helper(k), v)| (k, v))
-            .collect())
-    }
-}
-
 /// A marker annotation and its refinements.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
 pub struct MarkerAnnotation {
@@ -122,6 +93,77 @@ impl MarkerAnnotation {
     }
 }
 
+/// How a marker placed on a type carries over to types that contain it.
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    strum::AsRefStr,
+    strum::EnumString,
+)]
+#[cfg_attr(
+    feature = "rustc",
+    derive(rustc_macros::Encodable, rustc_macros::Decodable)
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum MarkerPropagation {
+    /// Every type that contains the marked type is marked, including
+    /// references, pointers and `PhantomData`.
+    #[default]
+    All,
+    /// Like [`Self::All`] but `PhantomData` does not propagate the marker.
+    NoPhantom,
+    /// Only types that own a value of the marked type are marked. This
+    /// includes fields, tuples, arrays and owning pointers such as `Box`, `Vec`
+    /// or `Arc`, but not references, raw pointers or `PhantomData`.
+    Owned,
+    /// Only the marked type itself carries the marker.
+    None,
+}
+
+/// How an annotation was attached to an item.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum AnnotationSource {
+    /// A `#[paralegal::marker]` attribute in the source code. This includes
+    /// markers inherited from an enclosing `impl` block or module.
+    Attribute,
+    /// An entry in the external annotation file.
+    External {
+        /// Path of the annotation file
+        file: String,
+        /// Line of the entry (1-based, 0 if unknown)
+        line: usize,
+    },
+    /// The item is a type that carries the marker and the node is of (or
+    /// contains) that type.
+    Type,
+    /// A summary stub for the item in the build configuration.
+    Stub,
+}
+
+/// Why a node carries a particular marker.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct MarkerProvenance {
+    /// The annotated item (function, static or type).
+    #[cfg_attr(feature = "rustc", serde(with = "rustc_proxies::DefId"))]
+    pub item: DefId,
+    /// How the annotation was attached to [`Self::item`].
+    pub source: AnnotationSource,
+    /// [`Self::item`] is a trait method and the marker applies to the node
+    /// because it belongs to a call to (or the body of) one of its
+    /// implementations.
+    pub via_trait: bool,
+}
+
 #[cfg(feature = "rustc")]
 mod ser_defid_map {
     use serde::{Deserialize, Serialize};
@@ -202,6 +244,10 @@ pub enum DefKind {
     Closure,
     /// A type
     Type,
+    /// A `static` item
+    Static,
+    /// A `const` item, including associated constants
+    Const,
 }
 
 /// An interned [`SourceFileInfo`]
@@ -313,6 +359,75 @@ pub struct InstructionInfo {
     pub span: Span,
     /// Textual rendering of the MIR
     pub description: Identifier,
+    /// The functions a call through a trait object (under the closed-world
+    /// assumption) or a function pointer was resolved to. Empty for all other
+    /// instructions.
+    #[cfg_attr(feature = "rustc", serde(with = "ser_defid_seq"))]
+    #[serde(default)]
+    pub call_candidates: Box<[DefId]>,
+}
+
+/// A call that was approximated during PDG construction instead of being
+/// inlined.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Approximation {
+    /// The controller in whose PDG the call occurs
+    #[cfg_attr(feature = "rustc", serde(with = "rustc_proxies::DefId"))]
+    pub controller: Endpoint,
+    /// The function that was called, `None` for a call through a function
+    /// pointer whose possible targets are unknown
+    #[cfg_attr(feature = "rustc", serde(with = "ser_opt_defid"))]
+    pub callee: Option<DefId>,
+    /// Why the call was not inlined, e.g. "marked" or "adaptive inlining"
+    pub reason: Identifier,
+    /// The location of the call in the PDG of [`Self::controller`]
+    pub call_string: CallString,
+    /// The source code span of the call
+    pub span: Span,
+    /// Whether the callee calls marked functions. If so the PDG may be
+    /// missing markers.
+    pub markers_reachable: bool,
+    /// Functions reachable from the callee, including the callee itself,
+    /// whose bodies were not available, e.g. because their crate was not
+    /// compiled by paralegal-flow. Markers reachable through them are unknown.
+    #[cfg_attr(feature = "rustc", serde(with = "ser_defid_seq"))]
+    #[serde(default)]
+    pub missing_bodies: Box<[DefId]>,
+}
+
+/// An operation whose effects were approximated conservatively because the
+/// analysis cannot see through it, e.g. the dereference of a raw pointer or a
+/// transmute. Flows through such operations may be spurious.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnsafeApproximation {
+    /// The controller in whose PDG the operation occurs
+    #[cfg_attr(feature = "rustc", serde(with = "rustc_proxies::DefId"))]
+    pub controller: Endpoint,
+    /// The kind of operation, e.g. "raw pointer dereference" or "transmute"
+    pub operation: Identifier,
+    /// The location of the operation in the PDG of [`Self::controller`]
+    pub call_string: CallString,
+    /// The source code span of the operation
+    pub span: Span,
+}
+
+/// A function that a controller discovery rule from the build configuration
+/// selected as a controller.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiscoveredController {
+    /// The selected function
+    #[cfg_attr(feature = "rustc", serde(with = "rustc_proxies::DefId"))]
+    pub function: DefId,
+    /// The name of the rule that selected the function
+    pub rule: Identifier,
+    /// The pattern of the rule that matched, i.e. an attribute macro or a
+    /// function the controller was registered with
+    pub via: Identifier,
+    /// The source code span of the function
+    pub span: Span,
+    /// Whether a PDG was created for the function, i.e. whether it is in
+    /// [`ProgramDescription::controllers`]. Generic functions are not analyzed.
+    pub analyzed: bool,
 }
 
 /// information about each encountered type.
@@ -359,9 +474,40 @@ pub struct ProgramDescription {
     /// [`Self::seen_functions`]. This is the sum of all
     /// `analyzed_locs` of the controllers but deduplicated.
     pub seen_locs: u32,
+    /// Every call that was approximated rather than inlined and left a trace
+    /// in the PDG of a controller.
+    #[serde(default)]
+    pub approximations: Vec<Approximation>,
+    /// Every operation that was approximated conservatively and left a trace
+    /// in the PDG of a controller. Only recorded with `--conservative-unsafe`.
+    #[serde(default)]
+    pub unsafe_approximations: Vec<UnsafeApproximation>,
+    /// The crates the [`DefId`]s in this description refer to.
+    #[serde(default)]
+    pub crates: Vec<CrateInfo>,
+    /// Every function selected by a controller discovery rule, including
+    /// those that could not be analyzed.
+    #[serde(default)]
+    pub discovered_controllers: Vec<DiscoveredController>,
     #[doc(hidden)]
-    #[serde(with = "ser_localdefid_map")]
-    pub analyzed_spans: HashMap<LocalDefId, Span>,
+    #[cfg_attr(not(feature = "rustc"), serde(with = "serde_map_via_vec"))]
+    #[cfg_attr(feature = "rustc", serde(with = "ser_defid_map"))]
+    pub analyzed_spans: HashMap<DefId, Span>,
+}
+
+/// A crate that [`DefId`]s in a [`ProgramDescription`] refer to.
+///
+/// Crate numbers are assigned by the compiler session that created the
+/// description, the stable id identifies the same crate across sessions. See
+/// [`ProgramDescription::merge`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrateInfo {
+    /// The crate number used in the [`DefId`]s of this description
+    pub number: u32,
+    /// The name of the crate
+    pub name: Identifier,
+    /// The `StableCrateId` rustc assigned to this crate
+    pub stable_id: u64,
 }
 
 /// Metadata about a type
@@ -374,6 +520,30 @@ pub struct TypeDescription {
     pub otypes: Box<[TypeId]>,
     /// Attached markers. Guaranteed not to be empty.
     pub markers: Vec<Identifier>,
+    /// The propagation rule for each marker in `markers`.
+    pub propagation: HashMap<Identifier, MarkerPropagation>,
+}
+
+#[cfg(feature = "rustc")]
+mod ser_opt_defid {
+    use flowistry_pdg::rustc_proxies;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct DefIdWrap(#[serde(with = "rustc_proxies::DefId")] crate::DefId);
+
+    pub fn serialize<S: Serializer>(
+        v: &Option<crate::DefId>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        v.map(DefIdWrap).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<crate::DefId>, D::Error> {
+        Ok(Option::<DefIdWrap>::deserialize(deserializer)?.map(|DefIdWrap(id)| id))
+    }
 }
 
 #[cfg(feature = "rustc")]
@@ -833,8 +1003,9 @@ pub struct SPDG {
     pub id: Endpoint,
     /// The PDG
     pub graph: SPDGImpl,
-    /// Nodes to which markers are assigned.
-    pub markers: HashMap<Node, Box<[Identifier]>>,
+    /// Nodes to which markers are assigned, together with where each marker
+    /// comes from.
+    pub markers: HashMap<Node, HashMap<Identifier, Box<[MarkerProvenance]>>>,
     /// The nodes that represent arguments to the entrypoint
     pub arguments: Box<[Node]>,
     /// If the return is `()` or `!` then this is `None`
@@ -845,6 +1016,20 @@ pub struct SPDG {
     pub type_assigns: HashMap<Node, Types>,
     /// Statistics
     pub statistics: SPDGStats,
+    /// Tags of this controller, from `#[paralegal::analyze(tag = "...")]` and
+    /// `@tag` words in its doc comment
+    #[serde(default)]
+    pub tags: Box<[Identifier]>,
+    /// The route given with `#[paralegal::analyze(route = "...")]`. Only
+    /// set by that annotation, also for discovered controllers; the route
+    /// attributes of web frameworks are kept in [`Self::attributes`].
+    #[serde(default)]
+    pub route: Option<Identifier>,
+    /// The attributes of the controller function as written in the source,
+    /// except for doc comments and paralegal's own attributes. Includes
+    /// attribute macros, e.g. the route attributes of web frameworks.
+    #[serde(default)]
+    pub attributes: Box<[String]>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
@@ -862,14 +1047,18 @@ pub struct SPDGStats {
     pub analyzed_locs: u32,
     /// Number of functions that correspond to [`Self::analyzed_locs]`
     pub analyzed_functions: u32,
-    /// How many times we inlined functions. This will be higher than
-    /// [`Self::analyzed_functions`] because sometimes the callee PDG is served
-    /// from the cache.
+    /// How many call sites in this PDG had their callee inlined, including
+    /// those inside callee PDGs served from the cache. This will be higher
+    /// than [`Self::analyzed_functions`] for that reason.
     pub inlinings_performed: u32,
     /// How long it took to create this PDG
     pub construction_time: Duration,
     /// How long it took to calculate markers and otherwise set up the pdg
     pub conversion_time: Duration,
+    /// How many calls in this PDG were approximated instead of inlined, by
+    /// the reason for the approximation.
+    #[serde(default)]
+    pub approximations: HashMap<Identifier, u32>,
 }
 
 /// Holds [`TypeId`]s that were assigned to a node.
@@ -0,0 +1,330 @@
+//! Combining the [`ProgramDescription`]s of several crates into one.
+//!
+//! When a workspace has controllers in multiple crates each crate is analyzed
+//! in its own compiler session and produces its own description. The crate
+//! numbers in the [`DefId`]s of these descriptions are assigned per session,
+//! so before the descriptions can be combined every [`DefId`] is renumbered
+//! using the stable crate ids recorded in [`ProgramDescription::crates`].
+
+use std::{cell::Cell, collections::hash_map::Entry};
+
+use anyhow::{bail, Result};
+use flowistry_pdg::{
+    rustc_portable::{crate_num_of, with_crate_num, DefId},
+    CallString, GlobalLocation,
+};
+
+use crate::{CrateInfo, HashMap, InstructionKind, ProgramDescription, Types, SPDG};
+
+impl ProgramDescription {
+    /// Combine the descriptions of multiple crates into one.
+    ///
+    /// Information about items that several crates know of (such as
+    /// [`Self::def_info`] and [`Self::type_info`]) is deduplicated, the
+    /// statistics are summed. The crate numbers of the result are those of the
+    /// first description, extended by the crates only later descriptions know.
+    ///
+    /// Fails if a later description refers to a crate that is not listed in
+    /// its [`Self::crates`].
+    pub fn merge(descriptions: impl IntoIterator<Item = ProgramDescription>) -> Result<Self> {
+        let mut descriptions = descriptions.into_iter();
+        let Some(mut merged) = descriptions.next() else {
+            return Ok(Self::empty());
+        };
+        let mut by_stable_id = merged
+            .crates
+            .iter()
+            .map(|info| (info.stable_id, info.number))
+            .collect::<HashMap<_, _>>();
+        for desc in descriptions {
+            let mut renumbering = HashMap::new();
+            for info in &desc.crates {
+                let next = merged
+                    .crates
+                    .iter()
+                    .map(|c| c.number + 1)
+                    .max()
+                    .unwrap_or(0);
+                let number = match by_stable_id.entry(info.stable_id) {
+                    Entry::Occupied(entry) => *entry.get(),
+                    Entry::Vacant(entry) => {
+                        merged.crates.push(CrateInfo {
+                            number: next,
+                            ..info.clone()
+                        });
+                        *entry.insert(next)
+                    }
+                };
+                renumbering.insert(info.number, number);
+            }
+            let renumber = Renumber {
+                numbers: &renumbering,
+                unmapped: Cell::new(None),
+            };
+            let desc = renumber.description(desc);
+            if let Some(krate) = renumber.unmapped.get() {
+                bail!("crate number {krate} is used in a description but not listed in its crates");
+            }
+            merged.absorb(desc);
+        }
+        Ok(merged)
+    }
+
+    fn empty() -> Self {
+        ProgramDescription {
+            controllers: Default::default(),
+            type_info: Default::default(),
+            instruction_info: Default::default(),
+            def_info: Default::default(),
+            marker_annotation_count: 0,
+            rustc_time: Default::default(),
+            dedup_functions: 0,
+            dedup_locs: 0,
+            seen_functions: 0,
+            seen_locs: 0,
+            approximations: vec![],
+            unsafe_approximations: vec![],
+            crates: vec![],
+            discovered_controllers: vec![],
+            analyzed_spans: Default::default(),
+        }
+    }
+
+    /// Add the contents of `other`, which must already use the crate numbers
+    /// of `self`.
+    fn absorb(&mut self, other: ProgramDescription) {
+        self.controllers.extend(other.controllers);
+        for (id, info) in other.type_info {
+            self.type_info.entry(id).or_insert(info);
+        }
+        self.instruction_info.extend(other.instruction_info);
+        for (id, info) in other.def_info {
+            self.def_info.entry(id).or_insert(info);
+        }
+        self.marker_annotation_count += other.marker_annotation_count;
+        self.rustc_time += other.rustc_time;
+        self.dedup_functions += other.dedup_functions;
+        self.dedup_locs += other.dedup_locs;
+        self.seen_functions += other.seen_functions;
+        self.seen_locs += other.seen_locs;
+        self.approximations.extend(other.approximations);
+        self.unsafe_approximations
+            .extend(other.unsafe_approximations);
+        self.discovered_controllers
+            .extend(other.discovered_controllers);
+        self.analyzed_spans.extend(other.analyzed_spans);
+    }
+}
+
+/// Rewrites the crate numbers of [`DefId`]s.
+struct Renumber<'a> {
+    numbers: &'a HashMap<u32, u32>,
+    /// A crate number that is missing from [`Self::numbers`], if any was
+    /// encountered.
+    unmapped: Cell<Option<u32>>,
+}
+
+impl Renumber<'_> {
+    fn def_id(&self, id: DefId) -> DefId {
+        let krate = crate_num_of(id);
+        match self.numbers.get(&krate) {
+            Some(&krate) => with_crate_num(id, krate),
+            None => {
+                self.unmapped.set(Some(krate));
+                id
+            }
+        }
+    }
+
+    fn location(&self, location: GlobalLocation) -> GlobalLocation {
+        GlobalLocation {
+            function: self.def_id(location.function),
+            ..location
+        }
+    }
+
+    fn call_string(&self, call_string: CallString) -> CallString {
+        let mut locations = call_string.iter().map(|l| self.location(l));
+        let root = CallString::single(locations.next().unwrap());
+        locations.fold(root, CallString::push)
+    }
+
+    fn spdg(&self, mut spdg: SPDG) -> SPDG {
+        spdg.id = self.def_id(spdg.id);
+        for node in spdg.graph.node_weights_mut() {
+            node.at = self.call_string(node.at);
+        }
+        for edge in spdg.graph.edge_weights_mut() {
+            edge.at = self.call_string(edge.at);
+        }
+        for provenance in spdg.markers.values_mut().flat_map(|m| m.values_mut()) {
+            for p in provenance.iter_mut() {
+                p.item = self.def_id(p.item);
+            }
+        }
+        for types in spdg.type_assigns.values_mut() {
+            *types = Types(types.0.iter().map(|t| self.def_id(*t)).collect());
+        }
+        spdg
+    }
+
+    fn description(&self, desc: ProgramDescription) -> ProgramDescription {
+        ProgramDescription {
+            controllers: desc
+                .controllers
+                .into_values()
+                .map(|spdg| {
+                    let spdg = self.spdg(spdg);
+                    (spdg.id, spdg)
+                })
+                .collect(),
+            type_info: desc
+                .type_info
+                .into_iter()
+                .map(|(id, mut info)| {
+                    info.otypes = info.otypes.iter().map(|t| self.def_id(*t)).collect();
+                    (self.def_id(id), info)
+                })
+                .collect(),
+            instruction_info: desc
+                .instruction_info
+                .into_iter()
+                .map(|(location, mut info)| {
+                    if let InstructionKind::FunctionCall(call) = &mut info.kind {
+                        call.id = self.def_id(call.id);
+                    }
+                    info.call_candidates = info
+                        .call_candidates
+                        .iter()
+                        .map(|c| self.def_id(*c))
+                        .collect();
+                    (self.location(location), info)
+                })
+                .collect(),
+            def_info: desc
+                .def_info
+                .into_iter()
+                .map(|(id, info)| (self.def_id(id), info))
+                .collect(),
+            approximations: desc
+                .approximations
+                .into_iter()
+                .map(|mut approximation| {
+                    approximation.controller = self.def_id(approximation.controller);
+                    approximation.callee = approximation.callee.map(|callee| self.def_id(callee));
+                    approximation.call_string = self.call_string(approximation.call_string);
+                    approximation
+                })
+                .collect(),
+            unsafe_approximations: desc
+                .unsafe_approximations
+                .into_iter()
+                .map(|mut approximation| {
+                    approximation.controller = self.def_id(approximation.controller);
+                    approximation.call_string = self.call_string(approximation.call_string);
+                    approximation
+                })
+                .collect(),
+            discovered_controllers: desc
+                .discovered_controllers
+                .into_iter()
+                .map(|mut discovered| {
+                    discovered.function = self.def_id(discovered.function);
+                    discovered
+                })
+                .collect(),
+            analyzed_spans: desc
+                .analyzed_spans
+                .into_iter()
+                .map(|(id, span)| (self.def_id(id), span))
+                .collect(),
+            ..desc
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flowistry_pdg::rustc_portable::def_id_from_parts;
+
+    use crate::{
+        CrateInfo, DefInfo, DefKind, Identifier, ProgramDescription, SourceFileInfo, Span,
+        SpanCoord,
+    };
+
+    fn crate_info(number: u32, name: &str, stable_id: u64) -> CrateInfo {
+        CrateInfo {
+            number,
+            name: Identifier::new_intern(name),
+            stable_id,
+        }
+    }
+
+    fn def_info(name: &str) -> DefInfo {
+        let coord = SpanCoord { line: 1, col: 1 };
+        DefInfo {
+            name: Identifier::new_intern(name),
+            path: Box::new([]),
+            kind: DefKind::Fn,
+            src_info: Span {
+                source_file: SourceFileInfo {
+                    file_path: "src/lib.rs".into(),
+                    abs_file_path: "/src/lib.rs".into(),
+                }
+                .intern(),
+                start: coord,
+                end: coord,
+            },
+            markers: Box::new([]),
+        }
+    }
+
+    fn description(crates: Vec<CrateInfo>, items: &[(u32, u32, &str)]) -> ProgramDescription {
+        ProgramDescription {
+            crates,
+            def_info: items
+                .iter()
+                .map(|&(krate, index, name)| (def_id_from_parts(krate, index), def_info(name)))
+                .collect(),
+            ..ProgramDescription::empty()
+        }
+    }
+
+    #[test]
+    fn renumbers_crates_by_stable_id() {
+        let app = description(
+            vec![crate_info(0, "app", 1), crate_info(1, "lib", 2)],
+            &[(1, 5, "helper")],
+        );
+        let lib = description(
+            vec![
+                crate_info(0, "lib", 2),
+                crate_info(1, "app", 1),
+                crate_info(2, "other", 3),
+            ],
+            &[(0, 5, "helper"), (1, 7, "handler"), (2, 3, "extra")],
+        );
+        let merged = ProgramDescription::merge([app, lib]).unwrap();
+
+        assert_eq!(
+            merged.crates,
+            [
+                crate_info(0, "app", 1),
+                crate_info(1, "lib", 2),
+                crate_info(2, "other", 3)
+            ]
+        );
+        assert_eq!(merged.def_info.len(), 3);
+        let name_of = |krate, index| merged.def_info[&def_id_from_parts(krate, index)].name;
+        assert_eq!(name_of(1, 5).as_str(), "helper");
+        assert_eq!(name_of(0, 7).as_str(), "handler");
+        assert_eq!(name_of(2, 3).as_str(), "extra");
+    }
+
+    #[test]
+    fn rejects_unlisted_crates() {
+        let app = description(vec![crate_info(0, "app", 1)], &[]);
+        let lib = description(vec![crate_info(0, "lib", 2)], &[(4, 1, "unknown")]);
+        assert!(ProgramDescription::merge([app, lib]).is_err());
+    }
+}
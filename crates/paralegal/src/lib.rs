@@ -49,6 +49,46 @@ export!(
     /// #[paralegal::marker(leaking, arguments = [1])]
     /// fn send(recipients: &[String], content: &str) {  }
     /// ```
+    ///
+    /// Markers on types also apply to types containing the marked type. The
+    /// `propagate` option restricts this: `"no-phantom"` does not mark
+    /// `PhantomData<User>` (and types containing it), `"owned"` only marks
+    /// types that own a value of the marked type (e.g. `Vec<User>` but not
+    /// `&User`), `"none"` only marks the type itself. The default is `"all"`.
+    ///
+    /// ```
+    /// #[paralegal::marker(sensitive, propagate = "owned")]
+    /// struct Password {}
+    /// ```
+    ///
+    /// `static` and `const` items can be marked too, reading them then
+    /// produces a marked value.
+    ///
+    /// ```
+    /// #[paralegal::marker(secret)]
+    /// static API_KEY: &str = "...";
+    /// ```
+    ///
+    /// Closures and `async` blocks can be marked like functions. This requires
+    /// the `stmt_expr_attributes` and `proc_macro_hygiene` features.
+    ///
+    /// ```ignore
+    /// let send = #[paralegal::marker(leaking, arguments = [0])]
+    /// |content: &str| { /* ... */ };
+    /// ```
+    ///
+    /// A marker on an `impl` block or a module applies to every function
+    /// inside it. A marker of the same name on an inner container or on the
+    /// function itself takes precedence.
+    ///
+    /// ```
+    /// #[paralegal::marker(leaking, arguments = [0])]
+    /// mod network {
+    ///     pub fn send(content: &str) {}
+    ///     #[paralegal::marker(leaking, arguments = [1])]
+    ///     pub fn send_to(recipient: &str, content: &str) {}
+    /// }
+    /// ```
     marker
 );
 export!(
@@ -70,6 +110,39 @@ export!(
     ///
     /// See also
     /// <https://justus-adam.notion.site/Markers-and-Annotations-b3b078ea2f7f41739de1efe7f9d33484?pvs=4#9a99ae10f3e047d4a3bc97364c3d253e>
+    ///
+    /// When placed on a module every public, non-generic function and inherent
+    /// method in it (and its submodules) becomes an entry point.
+    ///
+    /// ```
+    /// #[paralegal::analyze]
+    /// mod handlers {
+    ///     pub fn login(user: &str) {}
+    /// }
+    /// ```
+    ///
+    /// A generic function is analyzed with its type parameters instantiated as
+    /// `dyn` of their bounds. To analyze it for a concrete type instead, name
+    /// the type for the parameter. Parameters of a surrounding `impl` can be
+    /// instantiated the same way.
+    ///
+    /// ```
+    /// # trait Db {}
+    /// struct MyDb;
+    /// impl Db for MyDb {}
+    ///
+    /// #[paralegal::analyze(T = MyDb)]
+    /// fn handle<T: Db>(db: &T) {}
+    /// ```
+    ///
+    /// Policies can select controllers by tags and routes given here, or by
+    /// `@tag` words in the doc comment of the function.
+    ///
+    /// ```
+    /// /// Deletes a user. @admin
+    /// #[paralegal::analyze(tag = "public", route = "/users/:id")]
+    /// fn delete_user(id: u32) {}
+    /// ```
     analyze
 );
 
@@ -130,6 +203,6 @@ mod impl_ {
     }
 
     tool_attr!(marker);
-    tool_attr!(analyze, false);
+    tool_attr!(analyze);
     tool_attr!(output_types);
 }